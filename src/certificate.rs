@@ -0,0 +1,161 @@
+/* A certificate that an extraction is valid, which can be checked against the egraph
+without trusting (or running) any extractor:
+
+    {
+      "roots": ["r"],
+      "order": [
+        {"class": "x", "node": "x1", "children": []},
+        {"class": "r", "node": "f2", "children": ["x"]}
+      ],
+      "dag_cost": 3.0
+    }
+
+`order` has each class the roots reach with its chosen node and the classes of that node's
+children, and lists every class after its children, which witnesses that there is no cycle.
+*/
+
+use crate::*;
+use rustc_hash::FxHashMap;
+use serde_json::{json, Value};
+
+pub fn to_certificate(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<Value> {
+    let mut order = Order {
+        egraph,
+        result,
+        entries: vec![],
+        state: FxHashMap::default(),
+    };
+    for root in roots {
+        order.visit(root)?;
+    }
+    let dag_cost: f64 = order
+        .entries
+        .iter()
+        .map(|entry| egraph[&result.choices[entry.0]].cost.into_inner())
+        .sum();
+    let entries: Vec<Value> = order
+        .entries
+        .iter()
+        .map(|(class_id, children)| {
+            json!({
+                "class": class_id.to_string(),
+                "node": result.choices[*class_id].to_string(),
+                "children": children.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "roots": roots.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+        "order": entries,
+        "dag_cost": dag_cost,
+    }))
+}
+
+struct Order<'a> {
+    egraph: &'a EGraph,
+    result: &'a ExtractionResult,
+    entries: Vec<(&'a ClassId, Vec<&'a ClassId>)>,
+    /// Whether each class is done, or still being visited.
+    state: FxHashMap<&'a ClassId, bool>,
+}
+
+impl<'a> Order<'a> {
+    fn visit(&mut self, class_id: &'a ClassId) -> anyhow::Result<()> {
+        match self.state.get(class_id) {
+            Some(true) => return Ok(()),
+            Some(false) => anyhow::bail!("The extraction has a cycle through class {class_id}"),
+            None => {}
+        }
+        self.state.insert(class_id, false);
+        let Some(node_id) = self.result.choices.get(class_id) else {
+            anyhow::bail!("The extraction doesn't choose a node for class {class_id}");
+        };
+        let children: Vec<&ClassId> = self.egraph[node_id]
+            .children
+            .iter()
+            .map(|c| self.egraph.nid_to_cid(c))
+            .collect();
+        for child in &children {
+            self.visit(child)?;
+        }
+        self.state.insert(class_id, true);
+        self.entries.push((class_id, children));
+        Ok(())
+    }
+}
+
+/// Checks a certificate against the egraph: every root is covered, each class comes once,
+/// with a node of that class whose children are the listed classes, all listed earlier,
+/// and the dag cost is the sum of the chosen nodes' costs.
+pub fn verify_certificate(egraph: &EGraph, certificate: &Value) -> anyhow::Result<()> {
+    let strings = |value: &Value, what: &str| -> anyhow::Result<Vec<String>> {
+        let Some(array) = value.as_array() else {
+            anyhow::bail!("The certificate's {what} should be a list");
+        };
+        array
+            .iter()
+            .map(|v| match v.as_str() {
+                Some(s) => Ok(s.to_string()),
+                None => anyhow::bail!("The certificate's {what} should be strings, not {v}"),
+            })
+            .collect()
+    };
+
+    let Some(order) = certificate["order"].as_array() else {
+        anyhow::bail!("The certificate has no order");
+    };
+    let mut position: FxHashMap<String, usize> = FxHashMap::default();
+    let mut dag_cost = 0.0;
+    for (i, entry) in order.iter().enumerate() {
+        let (Some(class), Some(node)) = (entry["class"].as_str(), entry["node"].as_str()) else {
+            anyhow::bail!("Entry {i} of the order needs a class and a node");
+        };
+        let Some(n) = egraph.nodes.get(&NodeId::from(node)) else {
+            anyhow::bail!("Node {node} isn't in the egraph");
+        };
+        if n.eclass.to_string() != class {
+            anyhow::bail!("Node {node} is in class {}, not {class}", n.eclass);
+        }
+        let children = strings(&entry["children"], "children")?;
+        let actual: Vec<String> = n
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c).to_string())
+            .collect();
+        if children != actual {
+            anyhow::bail!("Node {node} has children in {actual:?}, not {children:?}");
+        }
+        for child in &children {
+            if !position.contains_key(child) {
+                anyhow::bail!("Class {class} comes before its child {child}");
+            }
+        }
+        if position.insert(class.to_string(), i).is_some() {
+            anyhow::bail!("Class {class} is in the order more than once");
+        }
+        dag_cost += n.cost.into_inner();
+    }
+
+    let roots = strings(&certificate["roots"], "roots")?;
+    let expected: Vec<String> = egraph.root_eclasses.iter().map(|r| r.to_string()).collect();
+    if roots != expected {
+        anyhow::bail!("The certificate is for roots {roots:?}, not {expected:?}");
+    }
+    for root in &roots {
+        if !position.contains_key(root) {
+            anyhow::bail!("Root {root} isn't covered");
+        }
+    }
+
+    let Some(claimed) = certificate["dag_cost"].as_f64() else {
+        anyhow::bail!("The certificate has no dag cost");
+    };
+    if (claimed - dag_cost).abs() > EPSILON_ALLOWANCE {
+        anyhow::bail!("The dag cost is {dag_cost}, not {claimed}");
+    }
+    Ok(())
+}
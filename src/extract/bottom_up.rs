@@ -3,32 +3,134 @@ use super::*;
 pub struct BottomUpExtractor;
 impl Extractor for BottomUpExtractor {
     fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
+        self.extract_with(egraph, &NodeCost)
+    }
+}
+
+impl BottomUpExtractor {
+    /// Extracts using the costs given by `model`, in any cost domain.
+    pub fn extract_with<C: CostDomain>(
+        &self,
+        egraph: &EGraph,
+        model: &impl CostModel<C>,
+    ) -> ExtractionResult {
+        self.extract_traced_with(egraph, model, |_, _, _, _| {})
+    }
+
+    /// Like `extract_with`, and calls `on_choice` with the pass over the classes (from 1),
+    /// the class, the node and its cost each time a class's choice improves.
+    pub fn extract_traced_with<C: CostDomain>(
+        &self,
+        egraph: &EGraph,
+        model: &impl CostModel<C>,
+        mut on_choice: impl FnMut(usize, &ClassId, &NodeId, &C),
+    ) -> ExtractionResult {
+        let mut result = ExtractionResult::default();
+        fixpoint(
+            || class_nodes(egraph.classes().values()),
+            |node_id, costs| node_sum_cost_with(egraph, node_id, model, costs),
+            |iteration, class_id, node_id, cost| {
+                on_choice(iteration, class_id, node_id, cost);
+                result.choose(class_id.clone(), node_id.clone());
+            },
+        );
+        result
+    }
+}
+
+impl BottomUpExtractor {
+    /// The same fixpoint, streamed over `egraph.nodes` rather than the classes, so the only
+    /// thing it keeps besides the choices is one cost per class. It never builds the class
+    /// map, which for big egraphs holds a copy of every node id.
+    ///
+    /// The tree costs are the same as `extract`'s. Between equally cheap nodes it can choose
+    /// differently, as the nodes are costed in a different order, which `refit_ties` evens out.
+    pub fn extract_low_memory(&self, egraph: &EGraph) -> ExtractionResult {
         let mut result = ExtractionResult::default();
-        let mut costs = FxHashMap::<ClassId, Cost>::with_capacity_and_hasher(
-            egraph.classes().len(),
-            Default::default(),
+        fixpoint(
+            || {
+                egraph
+                    .nodes
+                    .iter()
+                    .map(|(node_id, node)| (&node.eclass, node_id))
+            },
+            |node_id, costs| node_sum_cost_with(egraph, node_id, &NodeCost, costs),
+            |_, class_id, node_id, _| result.choose(class_id.clone(), node_id.clone()),
         );
-        let mut did_something = false;
-
-        loop {
-            for class in egraph.classes().values() {
-                for node in &class.nodes {
-                    let cost = result.node_sum_cost(egraph, &egraph[node], &costs);
-                    if &cost < costs.get(&class.id).unwrap_or(&INFINITY) {
-                        result.choose(class.id.clone(), node.clone());
-                        costs.insert(class.id.clone(), cost);
-                        did_something = true;
-                    }
+        result
+    }
+}
+
+impl BottomUpExtractor {
+    /// The same fixpoint, with the nodes' costs taken as delays and a class costing its
+    /// node's delay plus its slowest child's, so each class gets the shortest critical path
+    /// rather than the cheapest tree.
+    pub fn extract_min_latency(&self, egraph: &EGraph) -> ExtractionResult {
+        let mut result = ExtractionResult::default();
+        fixpoint(
+            || class_nodes(egraph.classes().values()),
+            |node_id, latencies| {
+                let mut slowest_child = Cost::default();
+                for child in &egraph[node_id].children {
+                    slowest_child = slowest_child.max(*latencies.get(egraph.nid_to_cid(child))?);
                 }
-            }
+                Some(egraph[node_id].cost + slowest_child)
+            },
+            |_, class_id, node_id, _| result.choose(class_id.clone(), node_id.clone()),
+        );
+        result
+    }
+}
 
-            if did_something {
-                did_something = false;
-            } else {
-                break;
+/// The min tree cost of every extractable class, from the same fixpoint as
+/// `BottomUpExtractor` but without keeping track of which node gave each cost.
+pub fn min_tree_costs(egraph: &EGraph) -> FxHashMap<ClassId, Cost> {
+    fixpoint(
+        || class_nodes(egraph.classes().values()),
+        |node_id, costs| node_sum_cost_with(egraph, node_id, &NodeCost, costs),
+        |_, _, _, _| {},
+    )
+}
+
+/// The fixpoint the bottom-up extractors share. Each pass goes over `nodes`, pairs of a class
+/// and one of its nodes, and a node that `node_cost` can cost from the classes' costs so far
+/// becomes its class's choice if it's cheaper than what the class has, until a pass changes
+/// nothing. `on_choice` gets the pass (from 1), the class, the node and its cost for each
+/// new choice. Gives the classes' final costs.
+pub fn fixpoint<'a, C, I>(
+    nodes: impl Fn() -> I,
+    node_cost: impl Fn(&NodeId, &FxHashMap<ClassId, C>) -> Option<C>,
+    mut on_choice: impl FnMut(usize, &ClassId, &NodeId, &C),
+) -> FxHashMap<ClassId, C>
+where
+    C: PartialOrd,
+    I: IntoIterator<Item = (&'a ClassId, &'a NodeId)>,
+{
+    let mut costs = FxHashMap::<ClassId, C>::default();
+    let mut iteration = 0;
+    let mut did_something = true;
+    while did_something {
+        did_something = false;
+        iteration += 1;
+        for (class_id, node_id) in nodes() {
+            let Some(cost) = node_cost(node_id, &costs) else {
+                continue;
+            };
+            if costs.get(class_id).is_none_or(|prev| &cost < prev) {
+                on_choice(iteration, class_id, node_id, &cost);
+                costs.insert(class_id.clone(), cost);
+                did_something = true;
             }
         }
-
-        result
     }
+    costs
+}
+
+/// Each of the classes' nodes, with its class, as `fixpoint` takes them.
+fn class_nodes<'a>(
+    classes: impl IntoIterator<Item = &'a Class>,
+) -> impl Iterator<Item = (&'a ClassId, &'a NodeId)> {
+    classes
+        .into_iter()
+        .flat_map(|class| class.nodes.iter().map(move |node_id| (&class.id, node_id)))
 }
@@ -0,0 +1,74 @@
+/* A bottom-up extractor where no path from a root to a leaf has more than `max_depth` nodes.
+
+For each depth d it finds the cheapest tree of each class that is at most d deep, so a
+class can be given a shallower but costlier node when its parents need it. The roots get
+the full depth, and each chosen node's children get one less. A class shared between
+parents at different depths is extracted with the smallest depth it was given, which
+suits all of them.
+
+Roots that can't be extracted within the depth are left out of the result.
+*/
+
+use super::*;
+use rustc_hash::FxHashMap;
+
+pub struct BoundedDepthExtractor {
+    pub max_depth: usize,
+}
+
+impl Extractor for BoundedDepthExtractor {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        // No acyclic extraction is deeper than the number of classes.
+        let max_depth = self.max_depth.min(egraph.classes().len());
+
+        // best[d - 1] is the cheapest node of each class with a tree at most d deep.
+        let mut best: Vec<FxHashMap<ClassId, (Cost, NodeId)>> = Vec::with_capacity(max_depth);
+        for depth in 0..max_depth {
+            let mut costs = FxHashMap::<ClassId, (Cost, NodeId)>::default();
+            for (node_id, node) in &egraph.nodes {
+                let mut cost = node.cost;
+                for child in &node.children {
+                    let child_cost = depth
+                        .checked_sub(1)
+                        .and_then(|d| best[d].get(egraph.nid_to_cid(child)));
+                    match child_cost {
+                        Some((c, _)) => cost += *c,
+                        None => cost = INFINITY,
+                    }
+                }
+                if cost == INFINITY {
+                    continue;
+                }
+                if costs.get(&node.eclass).is_none_or(|(prev, _)| cost < *prev) {
+                    costs.insert(node.eclass.clone(), (cost, node_id.clone()));
+                }
+            }
+            best.push(costs);
+        }
+
+        // Give each class the smallest depth any of its parents needs, choosing again
+        // whenever that shrinks.
+        let mut depths = IndexMap::<ClassId, usize>::default();
+        let mut todo: Vec<(ClassId, usize)> =
+            roots.iter().map(|r| (r.clone(), max_depth)).collect();
+        while let Some((class_id, depth)) = todo.pop() {
+            if depth == 0 || depths.get(&class_id).is_some_and(|&d| d <= depth) {
+                continue;
+            }
+            let Some((_, node_id)) = best[depth - 1].get(&class_id) else {
+                continue;
+            };
+            depths.insert(class_id, depth);
+            for child in &egraph[node_id].children {
+                todo.push((egraph.nid_to_cid(child).clone(), depth - 1));
+            }
+        }
+
+        let mut result = ExtractionResult::default();
+        for (class_id, depth) in depths {
+            let node_id = best[depth - 1][&class_id].1.clone();
+            result.choose(class_id, node_id);
+        }
+        result
+    }
+}
@@ -0,0 +1,147 @@
+/* ILP on a contracted egraph, for egraphs too big to solve whole.
+
+A class is easy if only one of its nodes isn't dominated (see `dominance`), since a
+cheapest extraction never needs the others. Easy classes whose descendants are all easy
+make up fixed subtrees. The ILP only sees the rest: each fixed subtree that a class left to
+it uses becomes a leaf costing what the subtree does, and the dominated nodes are left out.
+The ILP's choices are then stitched together with the fixed subtrees.
+
+A leaf costs its whole subtree even where subtrees share classes, so the result can be worse
+than what the ILP finds on the whole egraph. If the ILP times out, faster-greedy-dag extracts
+the contracted egraph instead.
+*/
+
+use super::*;
+
+pub struct ContractedIlpExtractor;
+
+const TIMEOUT_IN_SECONDS: u32 = 10;
+
+impl Extractor for ContractedIlpExtractor {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let (contracted, fixed) = contract(egraph, roots);
+        log::info!(
+            "contracted-ilp: the ILP has {} of {} classes and {} of {} nodes",
+            contracted.classes().len(),
+            egraph.classes().len(),
+            contracted.nodes.len(),
+            egraph.nodes.len()
+        );
+
+        let solved = ilp_cbc::ConfiguredCbcExtractor {
+            config: Default::default(),
+            timeout_seconds: TIMEOUT_IN_SECONDS,
+        }
+        .extract(&contracted, roots);
+
+        let mut result = fixed;
+        for (class_id, node_id) in solved.choices {
+            result.choose(class_id, node_id);
+        }
+        result.restrict(egraph, roots)
+    }
+}
+
+/// The egraph left for the ILP, and the choices for the fixed subtrees. Node ids are kept,
+/// so a leaf has the id of the node at the top of its subtree.
+pub fn contract(egraph: &EGraph, roots: &[ClassId]) -> (EGraph, ExtractionResult) {
+    let dominated = dominance::dominated_nodes(egraph);
+    let mut only: FxHashMap<&ClassId, &NodeId> = FxHashMap::default();
+    for class in egraph.classes().values() {
+        let mut undominated = class.nodes.iter().filter(|n| !dominated.contains(*n));
+        if let (Some(node_id), None) = (undominated.next(), undominated.next()) {
+            only.insert(&class.id, node_id);
+        }
+    }
+
+    // An easy class is fixed once all its child classes are.
+    let mut parents: FxHashMap<&ClassId, Vec<&ClassId>> = FxHashMap::default();
+    let mut unfixed_children: FxHashMap<&ClassId, usize> = FxHashMap::default();
+    let mut todo: Vec<&ClassId> = vec![];
+    for (&class_id, &node_id) in &only {
+        let children: IndexSet<&ClassId> = egraph[node_id]
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c))
+            .collect();
+        if children.is_empty() {
+            todo.push(class_id);
+        }
+        unfixed_children.insert(class_id, children.len());
+        for child in children {
+            parents.entry(child).or_default().push(class_id);
+        }
+    }
+    let mut fixed = ExtractionResult::default();
+    while let Some(class_id) = todo.pop() {
+        fixed.choose(class_id.clone(), only[class_id].clone());
+        for &parent in parents.get(class_id).into_iter().flatten() {
+            let count = unfixed_children.get_mut(parent).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                todo.push(parent);
+            }
+        }
+    }
+
+    let kept: Vec<&NodeId> = egraph
+        .nodes
+        .iter()
+        .filter(|(id, node)| !dominated.contains(*id) && !fixed.choices.contains_key(&node.eclass))
+        .map(|(id, _)| id)
+        .collect();
+    let mut leaves: IndexSet<&ClassId> = roots
+        .iter()
+        .filter(|root| fixed.choices.contains_key(*root))
+        .collect();
+    for node_id in &kept {
+        for child in &egraph[*node_id].children {
+            let child_class = egraph.nid_to_cid(child);
+            if fixed.choices.contains_key(child_class) {
+                leaves.insert(child_class);
+            }
+        }
+    }
+
+    // Children are redirected to a node of their class that's still there.
+    let mut representative: FxHashMap<&ClassId, &NodeId> = FxHashMap::default();
+    for &class_id in &leaves {
+        representative.insert(class_id, &fixed.choices[class_id]);
+    }
+    for &node_id in &kept {
+        representative
+            .entry(egraph.nid_to_cid(node_id))
+            .or_insert(node_id);
+    }
+
+    let mut contracted = EGraph::default();
+    for &node_id in &kept {
+        let node = &egraph[node_id];
+        let children = node
+            .children
+            .iter()
+            .map(|c| representative[egraph.nid_to_cid(c)].clone())
+            .collect();
+        contracted.add_node(
+            node_id.clone(),
+            Node {
+                children,
+                ..node.clone()
+            },
+        );
+    }
+    for &class_id in &leaves {
+        let node_id = &fixed.choices[class_id];
+        contracted.add_node(
+            node_id.clone(),
+            Node {
+                children: vec![],
+                cost: fixed.dag_cost(egraph, std::slice::from_ref(class_id)),
+                ..egraph[node_id].clone()
+            },
+        );
+    }
+    contracted.root_eclasses = roots.to_vec();
+    contracted.class_data = egraph.class_data.clone();
+    (contracted, fixed)
+}
@@ -0,0 +1,37 @@
+/* Nodes that a sibling dominates: the sibling costs no more and its children are in some of
+the same classes and no others. Replacing a node with its dominator never adds a class or
+cost to an extraction, or a cycle, so no cheapest dag extraction needs a dominated node.
+*/
+
+use super::*;
+
+/// The dominated nodes of every class. Of nodes that dominate each other the first isn't
+/// counted as dominated.
+pub fn dominated_nodes(egraph: &EGraph) -> FxHashSet<NodeId> {
+    let mut dominated: FxHashSet<NodeId> = FxHashSet::default();
+    for class in egraph.classes().values() {
+        let mut nodes: Vec<(&NodeId, Cost, FxHashSet<&ClassId>)> = class
+            .nodes
+            .iter()
+            .map(|id| {
+                let node = &egraph[id];
+                let children = node.children.iter().map(|c| egraph.nid_to_cid(c)).collect();
+                (id, node.cost, children)
+            })
+            .collect();
+        // A dominator comes before the nodes it dominates, and dominates whatever the
+        // nodes it dominates do, so only the undominated ones need comparing.
+        nodes.sort_by_key(|(_, cost, children)| (*cost, children.len()));
+        let mut kept: Vec<(&NodeId, Cost, FxHashSet<&ClassId>)> = vec![];
+        for (id, cost, children) in nodes {
+            if kept.iter().any(|(_, kept_cost, kept_children)| {
+                *kept_cost <= cost && kept_children.is_subset(&children)
+            }) {
+                dominated.insert(id.clone());
+            } else {
+                kept.push((id, cost, children));
+            }
+        }
+    }
+    dominated
+}
@@ -0,0 +1,170 @@
+/* Optimal tree cost extraction that handles cycles explicitly.
+
+The classes are split into strongly connected components, which form a DAG that is
+extracted children first, so every class outside a component is already costed when the
+component is reached. Inside a component, classes are finalized one at a time, cheapest
+first, and a node can only be chosen once all its children in the component are final.
+The order classes are finalized in are their levels: a chosen node's children always have
+lower levels than its class, so the choices can't form a cycle.
+
+With non-negative costs the cheapest unfinalized class can't get any cheaper, so the
+result has the same tree cost as bottom-up, without bottom-up's risk of choosing a cycle
+of zero cost nodes.
+*/
+
+use super::*;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub struct DpSccExtractor;
+
+impl Extractor for DpSccExtractor {
+    fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
+        let classes = egraph.classes();
+        let mut result = ExtractionResult::default();
+        let mut costs = FxHashMap::<ClassId, Cost>::default();
+        for component in strongly_connected_components(&class_children(egraph)) {
+            let members: FxHashSet<&ClassId> = component
+                .iter()
+                .map(|&i| classes.get_index(i).unwrap().0)
+                .collect();
+
+            // Nodes wait for their children in the component, the rest are already costed.
+            let mut pending = FxHashMap::<&NodeId, usize>::default();
+            let mut waiting = FxHashMap::<&ClassId, Vec<&NodeId>>::default();
+            let mut heap = BinaryHeap::new();
+            for class_id in &members {
+                for node_id in &classes[*class_id].nodes {
+                    let mut inside: Vec<&ClassId> = egraph[node_id]
+                        .children
+                        .iter()
+                        .map(|c| egraph.nid_to_cid(c))
+                        .filter(|c| members.contains(c))
+                        .collect();
+                    inside.sort_unstable();
+                    inside.dedup();
+                    if inside.is_empty() {
+                        push_node(egraph, node_id, &costs, &mut heap);
+                        continue;
+                    }
+                    pending.insert(node_id, inside.len());
+                    for child in inside {
+                        waiting.entry(child).or_default().push(node_id);
+                    }
+                }
+            }
+
+            while let Some(Reverse((cost, position))) = heap.pop() {
+                let (node_id, node) = egraph.nodes.get_index(position).unwrap();
+                if costs.contains_key(&node.eclass) {
+                    continue;
+                }
+                costs.insert(node.eclass.clone(), cost);
+                result.choose(node.eclass.clone(), node_id.clone());
+                for parent in waiting.remove(&node.eclass).unwrap_or_default() {
+                    let count = pending.get_mut(parent).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        push_node(egraph, parent, &costs, &mut heap);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Queues a node whose children are all final, unless some of them can't be extracted.
+/// Ties go to the node that comes first in the egraph.
+fn push_node(
+    egraph: &EGraph,
+    node_id: &NodeId,
+    costs: &FxHashMap<ClassId, Cost>,
+    heap: &mut BinaryHeap<Reverse<(Cost, usize)>>,
+) {
+    if let Some(cost) = node_sum_cost_with(egraph, node_id, &NodeCost, costs) {
+        let position = egraph.nodes.get_index_of(node_id).unwrap();
+        heap.push(Reverse((cost, position)));
+    }
+}
+
+/// The positions in `egraph.classes()` of the children of each class, without repeats.
+fn class_children(egraph: &EGraph) -> Vec<Vec<usize>> {
+    let classes = egraph.classes();
+    let class_index = |node_id: &NodeId| classes.get_index_of(egraph.nid_to_cid(node_id));
+    classes
+        .values()
+        .map(|class| {
+            let mut children: Vec<usize> = class
+                .nodes
+                .iter()
+                .flat_map(|node_id| &egraph[node_id].children)
+                .filter_map(class_index)
+                .collect();
+            children.sort_unstable();
+            children.dedup();
+            children
+        })
+        .collect()
+}
+
+/// Tarjan's algorithm, without recursion so deep egraphs don't overflow the stack.
+/// Components come out after every component they can reach.
+fn strongly_connected_components(successors: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let n = successors.len();
+    let mut index: Vec<Option<usize>> = vec![None; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = vec![];
+    let mut components = vec![];
+    let mut next_index = 0;
+
+    for start in 0..n {
+        if index[start].is_some() {
+            continue;
+        }
+        let mut calls = vec![(start, 0)];
+        index[start] = Some(next_index);
+        low[start] = next_index;
+        next_index += 1;
+        stack.push(start);
+        on_stack[start] = true;
+
+        while let Some(&mut (v, ref mut next_child)) = calls.last_mut() {
+            if let Some(&w) = successors[v].get(*next_child) {
+                *next_child += 1;
+                match index[w] {
+                    None => {
+                        index[w] = Some(next_index);
+                        low[w] = next_index;
+                        next_index += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        calls.push((w, 0));
+                    }
+                    Some(w_index) if on_stack[w] => low[v] = low[v].min(w_index),
+                    Some(_) => {}
+                }
+                continue;
+            }
+
+            calls.pop();
+            if let Some(&(parent, _)) = calls.last() {
+                low[parent] = low[parent].min(low[v]);
+            }
+            if Some(low[v]) == index[v] {
+                let mut component = vec![];
+                loop {
+                    let w = stack.pop().unwrap();
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+    components
+}
@@ -18,6 +18,17 @@ pub struct FasterBottomUpExtractor;
 
 impl Extractor for FasterBottomUpExtractor {
     fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
+        self.extract_with(egraph, &NodeCost)
+    }
+}
+
+impl FasterBottomUpExtractor {
+    /// Extracts using the costs given by `model`, in any cost domain.
+    pub fn extract_with<C: CostDomain>(
+        &self,
+        egraph: &EGraph,
+        model: &impl CostModel<C>,
+    ) -> ExtractionResult {
         let mut parents = IndexMap::<ClassId, Vec<NodeId>>::with_capacity(egraph.classes().len());
         let n2c = |nid: &NodeId| egraph.nid_to_cid(nid);
         let mut analysis_pending = UniqueQueue::default();
@@ -41,17 +52,17 @@ impl Extractor for FasterBottomUpExtractor {
         }
 
         let mut result = ExtractionResult::default();
-        let mut costs = FxHashMap::<ClassId, Cost>::with_capacity_and_hasher(
+        let mut costs = FxHashMap::<ClassId, C>::with_capacity_and_hasher(
             egraph.classes().len(),
             Default::default(),
         );
 
         while let Some(node_id) = analysis_pending.pop() {
             let class_id = n2c(&node_id);
-            let node = &egraph[&node_id];
-            let prev_cost = costs.get(class_id).unwrap_or(&INFINITY);
-            let cost = result.node_sum_cost(egraph, node, &costs);
-            if cost < *prev_cost {
+            let Some(cost) = node_sum_cost_with(egraph, &node_id, model, &costs) else {
+                continue;
+            };
+            if costs.get(class_id).is_none_or(|prev| &cost < prev) {
                 result.choose(class_id.clone(), node_id.clone());
                 costs.insert(class_id.clone(), cost);
                 analysis_pending.extend(parents[class_id].iter().cloned());
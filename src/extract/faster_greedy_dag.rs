@@ -90,6 +90,16 @@ impl FasterGreedyDagExtractor {
 
 impl Extractor for FasterGreedyDagExtractor {
     fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
+        self.extract_with_totals(egraph).0
+    }
+}
+
+impl FasterGreedyDagExtractor {
+    /// Also returns the DAG cost the extractor tracked for each class while choosing.
+    pub fn extract_with_totals(
+        &self,
+        egraph: &EGraph,
+    ) -> (ExtractionResult, FxHashMap<ClassId, Cost>) {
         let mut parents = IndexMap::<ClassId, Vec<NodeId>>::with_capacity(egraph.classes().len());
         let n2c = |nid: &NodeId| egraph.nid_to_cid(nid);
         let mut analysis_pending = UniqueQueue::default();
@@ -136,14 +146,38 @@ impl Extractor for FasterGreedyDagExtractor {
             }
         }
 
+        let mut totals = FxHashMap::default();
         for (cid, cost_set) in costs {
-            result.choose(cid, cost_set.choice);
+            result.choose(cid.clone(), cost_set.choice);
+            totals.insert(cid, cost_set.total);
         }
 
-        result
+        (result, totals)
     }
 }
 
+/// The roots whose DAG cost, recomputed from the final choices, differs from the cost the
+/// extractor tracked for them, along with the recomputed and tracked costs. A root the
+/// extractor didn't reach comes with infinite costs.
+pub fn drifted_roots(
+    egraph: &EGraph,
+    roots: &[ClassId],
+    result: &ExtractionResult,
+    totals: &FxHashMap<ClassId, Cost>,
+) -> Vec<(ClassId, Cost, Cost)> {
+    roots
+        .iter()
+        .filter_map(|root| {
+            let Some(&tracked) = totals.get(root) else {
+                return Some((root.clone(), INFINITY, INFINITY));
+            };
+            let recomputed = result.dag_cost(egraph, std::slice::from_ref(root));
+            let drifted = (recomputed - tracked).abs() > EPSILON_ALLOWANCE;
+            drifted.then(|| (root.clone(), recomputed, tracked))
+        })
+        .collect()
+}
+
 /** A data structure to maintain a queue of unique elements.
 
 Notably, insert/pop operations have O(1) expected amortized runtime complexity.
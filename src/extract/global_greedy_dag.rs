@@ -9,6 +9,8 @@ type TermId = usize;
 #[derive(Clone, PartialEq, Eq, Hash)]
 struct Term {
     op: String,
+    // Nodes with the same op and children can still have different costs.
+    cost: Cost,
     children: Vec<TermId>,
 }
 
@@ -51,6 +53,7 @@ impl TermDag {
     ) -> Option<TermId> {
         let term = Term {
             op: node.op.clone(),
+            cost: node.cost,
             children: children.clone(),
         };
 
@@ -10,63 +10,116 @@ struct CostSet {
 pub struct GreedyDagExtractor;
 impl Extractor for GreedyDagExtractor {
     fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
-        let mut costs = FxHashMap::<ClassId, CostSet>::with_capacity_and_hasher(
-            egraph.classes().len(),
-            Default::default(),
-        );
+        extract_nodes(egraph, || egraph.nodes.iter(), |_, _, _, _| {})
+    }
+}
+
+/// Greedy-dag for costs that decay with reuse, as in `ExtractionResult::reuse_cost`. Each
+/// class in a cost set is charged its node's cost times `1 - decay`, plus `decay` times the
+/// costs of its children's nodes, one for each use, and the top class one more use. That's
+/// the reuse cost when the cost set's choices agree with each other.
+pub struct ReuseDecayExtractor {
+    pub decay: f64,
+}
+
+impl Extractor for ReuseDecayExtractor {
+    fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
+        extract_nodes_with_decay(egraph, || egraph.nodes.iter(), self.decay, |_, _, _, _| {})
+    }
+}
+
+impl GreedyDagExtractor {
+    /// Extracts, and calls `on_choice` with the pass over the nodes (from 1), the class,
+    /// the node and the dag cost of its cost set each time a class's choice improves.
+    pub fn extract_traced(
+        &self,
+        egraph: &EGraph,
+        on_choice: impl FnMut(usize, &ClassId, &NodeId, Cost),
+    ) -> ExtractionResult {
+        extract_nodes(egraph, || egraph.nodes.iter(), on_choice)
+    }
+}
+
+/// Runs the greedy fixpoint over just the given nodes, which must include every node
+/// of the children of each one.
+pub(crate) fn extract_nodes<'a, I>(
+    egraph: &'a EGraph,
+    nodes: impl Fn() -> I,
+    on_choice: impl FnMut(usize, &ClassId, &NodeId, Cost),
+) -> ExtractionResult
+where
+    I: Iterator<Item = (&'a NodeId, &'a Node)>,
+{
+    extract_nodes_with_decay(egraph, nodes, 0.0, on_choice)
+}
 
-        let mut keep_going = true;
+fn extract_nodes_with_decay<'a, I>(
+    egraph: &'a EGraph,
+    nodes: impl Fn() -> I,
+    decay: f64,
+    mut on_choice: impl FnMut(usize, &ClassId, &NodeId, Cost),
+) -> ExtractionResult
+where
+    I: Iterator<Item = (&'a NodeId, &'a Node)>,
+{
+    let mut costs = FxHashMap::<ClassId, CostSet>::default();
 
-        let mut i = 0;
-        while keep_going {
-            i += 1;
-            println!("iteration {}", i);
-            keep_going = false;
+    let mut keep_going = true;
 
-            'node_loop: for (node_id, node) in &egraph.nodes {
-                let cid = egraph.nid_to_cid(node_id);
-                let mut cost_set = CostSet {
-                    costs: Default::default(),
-                    total: Cost::default(),
-                    choice: node_id.clone(),
-                };
+    let mut i = 0;
+    while keep_going {
+        i += 1;
+        log::debug!("iteration {}", i);
+        keep_going = false;
 
-                // compute the cost set from the children
-                for child in &node.children {
-                    let child_cid = egraph.nid_to_cid(child);
-                    if let Some(child_cost_set) = costs.get(child_cid) {
-                        // prevent a cycle
-                        if child_cost_set.costs.contains_key(cid) {
-                            continue 'node_loop;
-                        }
-                        cost_set.costs.extend(child_cost_set.costs.clone());
-                    } else {
+        'node_loop: for (node_id, node) in nodes() {
+            let cid = egraph.nid_to_cid(node_id);
+            let mut cost_set = CostSet {
+                costs: Default::default(),
+                total: Cost::default(),
+                choice: node_id.clone(),
+            };
+
+            // compute the cost set from the children
+            let mut reused = 0.0;
+            for child in &node.children {
+                let child_cid = egraph.nid_to_cid(child);
+                if let Some(child_cost_set) = costs.get(child_cid) {
+                    // prevent a cycle
+                    if child_cost_set.costs.contains_key(cid) {
                         continue 'node_loop;
                     }
+                    cost_set.costs.extend(child_cost_set.costs.clone());
+                    reused += egraph[&child_cost_set.choice].cost.into_inner();
+                } else {
+                    continue 'node_loop;
                 }
+            }
 
-                // add this node
-                cost_set.costs.insert(cid.clone(), node.cost);
+            // add this node
+            let own = node.cost * (1.0 - decay) + reused * decay;
+            cost_set.costs.insert(cid.clone(), own);
 
-                cost_set.total = cost_set.costs.values().sum();
+            cost_set.total = cost_set.costs.values().sum::<Cost>() + node.cost * decay;
 
-                // if the cost set is better than the current one, update it
-                if let Some(old_cost_set) = costs.get(cid) {
-                    if cost_set.total < old_cost_set.total {
-                        costs.insert(cid.clone(), cost_set);
-                        keep_going = true;
-                    }
-                } else {
+            // if the cost set is better than the current one, update it
+            if let Some(old_cost_set) = costs.get(cid) {
+                if cost_set.total < old_cost_set.total {
+                    on_choice(i, cid, node_id, cost_set.total);
                     costs.insert(cid.clone(), cost_set);
                     keep_going = true;
                 }
+            } else {
+                on_choice(i, cid, node_id, cost_set.total);
+                costs.insert(cid.clone(), cost_set);
+                keep_going = true;
             }
         }
+    }
 
-        let mut result = ExtractionResult::default();
-        for (cid, cost_set) in costs {
-            result.choose(cid, cost_set.choice);
-        }
-        result
+    let mut result = ExtractionResult::default();
+    for (cid, cost_set) in costs {
+        result.choose(cid, cost_set.choice);
     }
+    result
 }
@@ -0,0 +1,82 @@
+/* An extractor that looks for an expensive extraction, to stress test consumers with the
+worst valid DAG.
+
+Each class is ranked by the round in which it first becomes extractable, working up from
+the leaves. A class only chooses between nodes whose children all have a lower rank,
+which keeps the extraction acyclic, and among those it takes the node with the most
+expensive tree.
+*/
+
+use super::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+pub struct GreedyMaxExtractor;
+
+impl Extractor for GreedyMaxExtractor {
+    fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
+        let mut parents = IndexMap::<ClassId, Vec<NodeId>>::default();
+        let mut pending = FxHashMap::<NodeId, usize>::default();
+        let mut frontier = vec![];
+
+        for (node_id, node) in &egraph.nodes {
+            let children: FxHashSet<&ClassId> =
+                node.children.iter().map(|c| egraph.nid_to_cid(c)).collect();
+            for child in &children {
+                parents
+                    .entry((*child).clone())
+                    .or_default()
+                    .push(node_id.clone());
+            }
+            pending.insert(node_id.clone(), children.len());
+            if children.is_empty() {
+                frontier.push(node.eclass.clone());
+            }
+        }
+
+        // Classes in order of rank.
+        let mut rank = FxHashMap::<ClassId, usize>::default();
+        let mut order = vec![];
+        let mut round = 0;
+        while !frontier.is_empty() {
+            let mut next = vec![];
+            for class_id in frontier {
+                if rank.contains_key(&class_id) {
+                    continue;
+                }
+                rank.insert(class_id.clone(), round);
+                order.push(class_id.clone());
+                for parent in parents.get(&class_id).into_iter().flatten() {
+                    let count = pending.get_mut(parent).unwrap();
+                    *count -= 1;
+                    if *count == 0 {
+                        next.push(egraph[parent].eclass.clone());
+                    }
+                }
+            }
+            frontier = next;
+            round += 1;
+        }
+
+        let mut costs = FxHashMap::<ClassId, Cost>::default();
+        let mut result = ExtractionResult::default();
+        for class_id in &order {
+            let class_rank = rank[class_id];
+            let (node_id, cost) = egraph[class_id]
+                .nodes
+                .iter()
+                .filter(|n| {
+                    egraph[*n].children.iter().all(|c| {
+                        rank.get(egraph.nid_to_cid(c))
+                            .is_some_and(|&r| r < class_rank)
+                    })
+                })
+                .map(|n| (n, result.node_sum_cost(egraph, &egraph[n], &costs)))
+                .max_by_key(|(_, cost)| *cost)
+                .unwrap();
+            costs.insert(class_id.clone(), cost);
+            result.choose(class_id.clone(), node_id.clone());
+        }
+
+        result
+    }
+}
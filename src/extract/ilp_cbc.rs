@@ -2,23 +2,67 @@
 
 This extractor is simple so that it's easy to see that it's correct.
 
-If the timeout is reached, it will return the result of the faster-greedy-dag extractor.
+If the timeout is reached, it will return the result of the faster-greedy-dag extractor
+(or greedy-max, when maximizing). With a limit on the distinct ops, exclusive groups of
+ops, linked groups of nodes, a target shape, nodes to cover or a node budget it returns the
+solver's best solution so far instead, as the heuristics don't keep to those.
 */
 
+use super::shape::Shape;
 use super::*;
 use coin_cbc::{Col, Model, Sense};
 use indexmap::IndexSet;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
 
 struct ClassVars {
     active: Col,
     nodes: Vec<Col>,
 }
 
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    /// Find the most expensive extraction rather than the cheapest.
+    pub maximize: bool,
+    /// The most nodes allowed on any path from a root to a leaf.
+    pub max_depth: Option<usize>,
+    /// The most different ops the extraction may use.
+    pub max_distinct_ops: Option<usize>,
+    /// Groups of ops of which the extraction may use at most one each.
+    pub exclusive_groups: Vec<Vec<String>>,
+    /// Groups of nodes the extraction has to use all or none of.
+    pub linked_groups: Vec<Vec<NodeId>>,
+    /// The structure each root's extraction must have.
+    pub target_shape: Option<Shape>,
+    /// Nodes the extraction has to use.
+    pub cover: Vec<NodeId>,
+    /// The most nodes the extraction may use.
+    pub node_budget: Option<usize>,
+    /// Extract as many roots as possible, rather than all of them, leaving out the rest.
+    pub maximize_roots: bool,
+    /// Where to write the model in LP format before solving it.
+    pub export_lp: Option<PathBuf>,
+    /// Where to write the model in MPS format before solving it.
+    pub export_mps: Option<PathBuf>,
+}
+
+impl Config {
+    /// Whether the heuristics used after a timeout would ignore some of the constraints.
+    fn beyond_heuristics(&self) -> bool {
+        self.max_distinct_ops.is_some()
+            || !self.exclusive_groups.is_empty()
+            || !self.linked_groups.is_empty()
+            || self.target_shape.is_some()
+            || !self.cover.is_empty()
+            || self.node_budget.is_some()
+    }
+}
+
 pub struct CbcExtractorWithTimeout<const TIMEOUT_IN_SECONDS: u32>;
 
 impl<const TIMEOUT_IN_SECONDS: u32> Extractor for CbcExtractorWithTimeout<TIMEOUT_IN_SECONDS> {
     fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
-        return extract(egraph, roots, TIMEOUT_IN_SECONDS);
+        return extract(egraph, roots, &Config::default(), TIMEOUT_IN_SECONDS);
     }
 }
 
@@ -26,14 +70,217 @@ pub struct CbcExtractor;
 
 impl Extractor for CbcExtractor {
     fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
-        return extract(egraph, roots, std::u32::MAX);
+        return extract(egraph, roots, &Config::default(), std::u32::MAX);
     }
 }
 
-fn extract(egraph: &EGraph, roots: &[ClassId], timeout_seconds: u32) -> ExtractionResult {
-    let mut model = Model::default();
+pub struct ConfiguredCbcExtractor {
+    pub config: Config,
+    pub timeout_seconds: u32,
+}
+
+impl Extractor for ConfiguredCbcExtractor {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        return extract(egraph, roots, &self.config, self.timeout_seconds);
+    }
+}
+
+/// What the solver found within its time limit.
+pub enum IlpSolution {
+    /// A proven optimal extraction.
+    Optimal(ExtractionResult),
+    /// The best extraction found before the timeout, which might not be optimal.
+    Incumbent(ExtractionResult),
+    /// The solver stopped before finding any extraction.
+    NoSolution,
+    /// There is no extraction that meets the constraints.
+    Infeasible,
+}
+
+fn extract(
+    egraph: &EGraph,
+    roots: &[ClassId],
+    config: &Config,
+    timeout_seconds: u32,
+) -> ExtractionResult {
+    let solution = solve(egraph, roots, config, timeout_seconds);
+    result_or_fallback(solution, egraph, roots, config, timeout_seconds)
+}
+
+/// The extraction to use for what the solver found, falling back to a heuristic if it
+/// timed out.
+pub fn result_or_fallback(
+    solution: IlpSolution,
+    egraph: &EGraph,
+    roots: &[ClassId],
+    config: &Config,
+    timeout_seconds: u32,
+) -> ExtractionResult {
+    match solution {
+        IlpSolution::Optimal(result) => result,
+        IlpSolution::Infeasible => {
+            log::info!("Infeasible, returning empty solution");
+            ExtractionResult::default()
+        }
+        // The heuristics can't keep to the op limit or shape, so use what the solver found.
+        IlpSolution::Incumbent(result) if config.beyond_heuristics() => result,
+        IlpSolution::NoSolution if config.beyond_heuristics() => {
+            log::info!("Unfinished CBC solution, returning empty solution");
+            ExtractionResult::default()
+        }
+        IlpSolution::Incumbent(_) | IlpSolution::NoSolution => {
+            assert!(timeout_seconds != std::u32::MAX);
+
+            let initial_result = if config.maximize {
+                super::greedy_max::GreedyMaxExtractor.extract(egraph, roots)
+            } else {
+                super::faster_greedy_dag::FasterGreedyDagExtractor.extract(egraph, roots)
+            };
+            log::info!("Unfinished CBC solution");
+            initial_result
+        }
+    }
+}
+
+/// Solves with a timeout that doubles from `initial_seconds` until the extraction is proven
+/// optimal (or infeasible), or the timeout reaches `max_seconds`. Returns what was found
+/// with the last timeout tried.
+pub fn solve_escalating(
+    egraph: &EGraph,
+    roots: &[ClassId],
+    config: &Config,
+    initial_seconds: u32,
+    max_seconds: u32,
+) -> (IlpSolution, u32) {
+    escalate(initial_seconds, max_seconds, |timeout_seconds| {
+        solve(egraph, roots, config, timeout_seconds)
+    })
+}
 
+/// `solve_escalating` with the solver given as a function of the timeout.
+pub fn escalate(
+    initial_seconds: u32,
+    max_seconds: u32,
+    mut solve: impl FnMut(u32) -> IlpSolution,
+) -> (IlpSolution, u32) {
+    let mut timeout_seconds = initial_seconds.min(max_seconds);
+    loop {
+        let solution = solve(timeout_seconds);
+        let proven = matches!(solution, IlpSolution::Optimal(_) | IlpSolution::Infeasible);
+        if proven || timeout_seconds >= max_seconds {
+            return (solution, timeout_seconds);
+        }
+        let next = timeout_seconds.saturating_mul(2).min(max_seconds);
+        log::info!("CBC timed out after {timeout_seconds}s, retrying with {next}s");
+        timeout_seconds = next;
+    }
+}
+
+/// The size of the ILP for an egraph, as `(variables, constraints)`, without solving it.
+pub fn estimate(egraph: &EGraph, roots: &[ClassId], config: &Config) -> (u32, u32) {
+    let (model, _) = build_model(egraph, roots, config);
+    (model.num_cols(), model.num_rows())
+}
+
+/// Solves for an optimal extraction, keeping the solver's incumbent if it times out.
+pub fn solve(
+    egraph: &EGraph,
+    roots: &[ClassId],
+    config: &Config,
+    timeout_seconds: u32,
+) -> IlpSolution {
+    let (mut model, vars) = build_model(egraph, roots, config);
     model.set_parameter("seconds", &timeout_seconds.to_string());
+    export(&model, config, timeout_seconds);
+
+    let solution = model.solve();
+    log::info!(
+        "CBC status {:?}, {:?}, obj = {}",
+        solution.raw().status(),
+        solution.raw().secondary_status(),
+        solution.raw().obj_value(),
+    );
+
+    if solution.raw().is_proven_infeasible() {
+        return IlpSolution::Infeasible;
+    }
+
+    let finished = solution.raw().status() == coin_cbc::raw::Status::Finished;
+
+    let mut result = ExtractionResult::default();
+
+    for (id, var) in &vars {
+        let active = solution.col(var.active) > 0.0;
+        if active {
+            // Without a solution to report, the solver's columns needn't be consistent.
+            let Some(node_idx) = var.nodes.iter().position(|&n| solution.col(n) > 0.0) else {
+                assert!(!finished);
+                return IlpSolution::NoSolution;
+            };
+            let node_id = egraph[id].nodes[node_idx].clone();
+            result.choose(id.clone(), node_id);
+        }
+    }
+
+    if finished {
+        IlpSolution::Optimal(result)
+    } else if config.maximize_roots || roots.iter().all(|root| result.choices.contains_key(root)) {
+        IlpSolution::Incumbent(result)
+    } else {
+        IlpSolution::NoSolution
+    }
+}
+
+/// A lower bound on the dag cost of any extraction, from the ILP with every variable
+/// allowed to take fractional values, or `None` if even that is infeasible.
+///
+/// The relaxation is a linear program, which CBC solves without branching, so it's quick
+/// where the ILP isn't. The cycle constraints are loose once the nodes can be partly
+/// active, so the bound can be well below the optimum.
+pub fn lp_bound(egraph: &EGraph, roots: &[ClassId], config: &Config) -> Option<f64> {
+    assert!(!config.maximize, "The relaxation only bounds a minimum");
+    let (model, _) = build_model(egraph, roots, config);
+    let mut raw = model.to_raw();
+    for col in 0..raw.number_columns() {
+        raw.set_continuous(col);
+    }
+    raw.solve();
+    log::info!(
+        "LP relaxation status {:?}, obj = {}",
+        raw.status(),
+        raw.obj_value()
+    );
+    if raw.is_proven_infeasible() {
+        return None;
+    }
+    Some(raw.obj_value())
+}
+
+/// Writes the model about to be solved to the files in the config. Neither format has room
+/// for the solver's parameters, so the time limit is only logged.
+fn export(model: &Model, config: &Config, timeout_seconds: u32) {
+    // CBC adds the extension itself.
+    let stem = |path: &Path| CString::new(path.with_extension("").to_string_lossy().as_ref());
+    if let Some(path) = &config.export_lp {
+        model.to_raw().write_lp(&stem(path).unwrap());
+        log::info!("Wrote the ILP to {}", path.display());
+    }
+    if let Some(path) = &config.export_mps {
+        model.to_raw().write_mps(&stem(path).unwrap());
+        log::info!("Wrote the ILP to {}", path.display());
+    }
+    if config.export_lp.is_some() || config.export_mps.is_some() {
+        log::info!("Solving it with a time limit of {timeout_seconds}s");
+    }
+}
+
+/// The ILP for an extraction, with the variables for each class.
+fn build_model(
+    egraph: &EGraph,
+    roots: &[ClassId],
+    config: &Config,
+) -> (Model, IndexMap<ClassId, ClassVars>) {
+    let mut model = Model::default();
 
     let vars: IndexMap<ClassId, ClassVars> = egraph
         .classes()
@@ -79,7 +326,15 @@ fn extract(egraph: &EGraph, roots: &[ClassId], timeout_seconds: u32) -> Extracti
         }
     }
 
-    model.set_obj_sense(Sense::Minimize);
+    // Covered and linked nodes have to be reachable from a root, not just active.
+    if config.maximize || !config.cover.is_empty() || !config.linked_groups.is_empty() {
+        require_active_parent(&mut model, &vars, egraph, roots);
+    }
+    if config.maximize {
+        model.set_obj_sense(Sense::Maximize);
+    } else {
+        model.set_obj_sense(Sense::Minimize);
+    }
     for class in egraph.classes().values() {
         for (node_id, &node_active) in class.nodes.iter().zip(&vars[&class.id].nodes) {
             let node = &egraph[node_id];
@@ -92,45 +347,288 @@ fn extract(egraph: &EGraph, roots: &[ClassId], timeout_seconds: u32) -> Extracti
         }
     }
 
-    for root in roots {
-        model.set_col_lower(vars[root].active, 1.0);
+    if config.maximize_roots {
+        assert!(
+            !config.maximize,
+            "Can't maximize the roots and the cost together"
+        );
+        maximize_roots(&mut model, &vars, egraph, roots);
+    } else {
+        for root in roots {
+            model.set_col_lower(vars[root].active, 1.0);
+        }
+    }
+    if let Some(budget) = config.node_budget {
+        // sum(node_active) <= budget
+        let row = model.add_row();
+        model.set_row_upper(row, budget as f64);
+        for class in vars.values() {
+            for &node_active in &class.nodes {
+                model.set_weight(row, node_active, 1.0);
+            }
+        }
+    }
+    for node_id in &config.cover {
+        let class_id = egraph.nid_to_cid(node_id);
+        let index = egraph[class_id]
+            .nodes
+            .iter()
+            .position(|n| n == node_id)
+            .unwrap();
+        model.set_col_lower(vars[class_id].nodes[index], 1.0);
     }
 
-    block_cycles(&mut model, &vars, &egraph);
+    if let Some(max_distinct_ops) = config.max_distinct_ops {
+        limit_distinct_ops(&mut model, &vars, egraph, max_distinct_ops);
+    }
+    for group in &config.exclusive_groups {
+        exclude_ops(&mut model, &vars, egraph, group);
+    }
+    for group in &config.linked_groups {
+        link_nodes(&mut model, &vars, egraph, group);
+    }
 
-    let solution = model.solve();
-    log::info!(
-        "CBC status {:?}, {:?}, obj = {}",
-        solution.raw().status(),
-        solution.raw().secondary_status(),
-        solution.raw().obj_value(),
-    );
+    if let Some(shape) = &config.target_shape {
+        let mut shapes = ShapeVars {
+            egraph,
+            vars: &vars,
+            matched: FxHashMap::default(),
+        };
+        for root in roots {
+            let matched = model.add_binary();
+            model.set_col_lower(matched, 1.0);
+            shapes.constrain(&mut model, shape, vec![], root, matched);
+        }
+    }
+
+    block_cycles(&mut model, &vars, &egraph, config.max_depth);
 
-    if solution.raw().status() != coin_cbc::raw::Status::Finished {
-        assert!(timeout_seconds != std::u32::MAX);
+    (model, vars)
+}
+
+/*
+ A class that has to match a shape has to choose a node with the shape's op and number of
+ children. When it does, each child that has to match a part of the shape gets a variable
+ for that, forced on by the node being active along with its parent's variable:
+   node_active + matched - child_matched <= 1
+ There's a variable for each position in the shape and class that can end up there.
+*/
+
+struct ShapeVars<'a> {
+    egraph: &'a EGraph,
+    vars: &'a IndexMap<ClassId, ClassVars>,
+    matched: FxHashMap<(Vec<usize>, ClassId), Col>,
+}
+
+impl ShapeVars<'_> {
+    fn constrain(
+        &mut self,
+        model: &mut Model,
+        shape: &Shape,
+        path: Vec<usize>,
+        class_id: &ClassId,
+        matched: Col,
+    ) {
+        let Shape::Node(op, children) = shape else {
+            return;
+        };
+
+        // matched <= sum(node_active for nodes that fit)
+        let row = model.add_row();
+        model.set_row_upper(row, 0.0);
+        model.set_weight(row, matched, 1.0);
+        let egraph = self.egraph;
+        for (node_id, &node_active) in egraph[class_id]
+            .nodes
+            .iter()
+            .zip(&self.vars[class_id].nodes)
+        {
+            let node = &egraph[node_id];
+            if node.op != *op || node.children.len() != children.len() {
+                continue;
+            }
+            model.set_weight(row, node_active, -1.0);
+
+            for (i, (child, child_shape)) in node.children.iter().zip(children).enumerate() {
+                if *child_shape == Shape::Any {
+                    continue;
+                }
+                let child_class = egraph.nid_to_cid(child);
+                let mut child_path = path.clone();
+                child_path.push(i);
+                let key = (child_path.clone(), child_class.clone());
+                let child_matched = match self.matched.get(&key) {
+                    Some(&col) => col,
+                    None => {
+                        let col = model.add_binary();
+                        self.matched.insert(key, col);
+                        self.constrain(model, child_shape, child_path, child_class, col);
+                        col
+                    }
+                };
 
-        let initial_result =
-            super::faster_greedy_dag::FasterGreedyDagExtractor.extract(egraph, roots);
-        log::info!("Unfinished CBC solution");
-        return initial_result;
+                let row = model.add_row();
+                model.set_row_upper(row, 1.0);
+                model.set_weight(row, node_active, 1.0);
+                model.set_weight(row, matched, 1.0);
+                model.set_weight(row, child_matched, -1.0);
+            }
+        }
     }
+}
 
-    let mut result = ExtractionResult::default();
+/*
+ When minimizing, the solver has no reason to make a class active unless a root needs it.
+ When maximizing it would make every class it could active, and a covered node is active
+ whether or not a root needs it, so each active class other than a root must have an
+ active parent node. Along with blocking cycles, this means
+ every active class is reachable from a root.
+*/
 
-    for (id, var) in &vars {
-        let active = solution.col(var.active) > 0.0;
-        if active {
-            let node_idx = var
-                .nodes
-                .iter()
-                .position(|&n| solution.col(n) > 0.0)
-                .unwrap();
-            let node_id = egraph[id].nodes[node_idx].clone();
-            result.choose(id.clone(), node_id);
+fn require_active_parent(
+    model: &mut Model,
+    vars: &IndexMap<ClassId, ClassVars>,
+    egraph: &EGraph,
+    roots: &[ClassId],
+) {
+    let mut parents: IndexMap<ClassId, IndexSet<Col>> = Default::default();
+    for (class_id, class) in vars {
+        for (node_id, &node_active) in egraph[class_id].nodes.iter().zip(&class.nodes) {
+            for child in &egraph[node_id].children {
+                parents
+                    .entry(egraph.nid_to_cid(child).clone())
+                    .or_default()
+                    .insert(node_active);
+            }
+        }
+    }
+
+    for (class_id, class) in vars {
+        if roots.contains(class_id) {
+            continue;
+        }
+        // class_active <= sum(parent nodes active)
+        let row = model.add_row();
+        model.set_row_upper(row, 0.0);
+        model.set_weight(row, class.active, 1.0);
+        for &parent_active in parents.get(class_id).into_iter().flatten() {
+            model.set_weight(row, parent_active, -1.0);
         }
     }
+}
+
+/*
+ Without every root forced active, each active root is worth more than all the nodes'
+ costs together, so the solver extracts as many roots as it can and only then looks at
+ the cost.
+*/
+
+fn maximize_roots(
+    model: &mut Model,
+    vars: &IndexMap<ClassId, ClassVars>,
+    egraph: &EGraph,
+    roots: &[ClassId],
+) {
+    let total: f64 = egraph.nodes.values().map(|n| n.cost.into_inner()).sum();
+    for root in roots.iter().collect::<IndexSet<_>>() {
+        model.set_obj_coeff(vars[root].active, -(total + 1.0));
+    }
+}
+
+/*
+ Each op gets a variable that must be set if any node with that op is active, and at
+ most `max_distinct_ops` of them can be set.
+*/
+
+fn limit_distinct_ops(
+    model: &mut Model,
+    vars: &IndexMap<ClassId, ClassVars>,
+    egraph: &EGraph,
+    max_distinct_ops: usize,
+) {
+    let mut ops: IndexMap<&str, Col> = Default::default();
+    for (class_id, class) in vars {
+        for (node_id, &node_active) in egraph[class_id].nodes.iter().zip(&class.nodes) {
+            let op_used = *ops
+                .entry(egraph[node_id].op.as_str())
+                .or_insert_with(|| model.add_binary());
+            // node_active <= op_used
+            let row = model.add_row();
+            model.set_row_upper(row, 0.0);
+            model.set_weight(row, node_active, 1.0);
+            model.set_weight(row, op_used, -1.0);
+        }
+    }
+
+    // sum(op_used) <= max_distinct_ops
+    let row = model.add_row();
+    model.set_row_upper(row, max_distinct_ops as f64);
+    for &op_used in ops.values() {
+        model.set_weight(row, op_used, 1.0);
+    }
+}
+
+/*
+ Like the distinct ops, but each op of the group gets a variable and at most one of them
+ can be set. Ops the egraph doesn't have are left out.
+*/
 
-    return result;
+fn exclude_ops(
+    model: &mut Model,
+    vars: &IndexMap<ClassId, ClassVars>,
+    egraph: &EGraph,
+    group: &[String],
+) {
+    let mut ops: IndexMap<&str, Col> = Default::default();
+    for (class_id, class) in vars {
+        for (node_id, &node_active) in egraph[class_id].nodes.iter().zip(&class.nodes) {
+            let op = egraph[node_id].op.as_str();
+            if !group.iter().any(|g| g == op) {
+                continue;
+            }
+            let op_used = *ops.entry(op).or_insert_with(|| model.add_binary());
+            // node_active <= op_used
+            let row = model.add_row();
+            model.set_row_upper(row, 0.0);
+            model.set_weight(row, node_active, 1.0);
+            model.set_weight(row, op_used, -1.0);
+        }
+    }
+
+    // sum(op_used) <= 1
+    let row = model.add_row();
+    model.set_row_upper(row, 1.0);
+    for &op_used in ops.values() {
+        model.set_weight(row, op_used, 1.0);
+    }
+}
+
+/*
+ Each node of a linked group is active exactly when the next one is:
+   node_active - next_active == 0
+*/
+
+fn link_nodes(
+    model: &mut Model,
+    vars: &IndexMap<ClassId, ClassVars>,
+    egraph: &EGraph,
+    group: &[NodeId],
+) {
+    let node_active = |node_id: &NodeId| {
+        let class_id = egraph.nid_to_cid(node_id);
+        let index = egraph[class_id]
+            .nodes
+            .iter()
+            .position(|n| n == node_id)
+            .unwrap();
+        vars[class_id].nodes[index]
+    };
+    for pair in group.windows(2) {
+        let row = model.add_row();
+        model.set_row_equal(row, 0.0);
+        model.set_weight(row, node_active(&pair[0]), 1.0);
+        model.set_weight(row, node_active(&pair[1]), -1.0);
+    }
 }
 
 /*
@@ -144,9 +642,21 @@ fn extract(egraph: &EGraph, roots: &[ClassId], timeout_seconds: u32) -> Extracti
  given a two class cycle: if class A, has level 'l', and class B has level 'm', then
  'l' must be less than 'm', but because there is also an active node in class B that
  has class A as a child, 'm' must be less than 'l', which is a contradiction.
+
+ The levels along a path from a root increase by at least one per node, so capping
+ them to [0, max_depth - 1] also bounds the depth of the extraction.
 */
 
-fn block_cycles(model: &mut Model, vars: &IndexMap<ClassId, ClassVars>, egraph: &EGraph) {
+fn block_cycles(
+    model: &mut Model,
+    vars: &IndexMap<ClassId, ClassVars>,
+    egraph: &EGraph,
+    max_depth: Option<usize>,
+) {
+    // No acyclic extraction is deeper than the number of classes, and a bigger cap
+    // would need a bigger weight on the opposite variables below.
+    let max_depth = max_depth.filter(|&depth| depth <= vars.len());
+
     let mut levels: IndexMap<ClassId, Col> = Default::default();
     for c in vars.keys() {
         let var = model.add_col();
@@ -154,6 +664,10 @@ fn block_cycles(model: &mut Model, vars: &IndexMap<ClassId, ClassVars>, egraph:
         //model.set_col_lower(var, 0.0);
         // It solves the benchmarks about 5% faster without this
         //model.set_col_upper(var, vars.len() as f64);
+        if let Some(depth) = max_depth {
+            model.set_col_lower(var, 0.0);
+            model.set_col_upper(var, depth as f64 - 1.0);
+        }
     }
 
     // If n.variable is true, opposite_col will be false and vice versa.
@@ -0,0 +1,82 @@
+/* Extraction of sequential circuits, where a cycle is fine as long as it goes through a
+latch node (a register), because the latch holds the value from the previous cycle.
+
+A latch node's children are its next-state inputs rather than something its value is
+computed from, so the extraction is made acyclic by cutting them off: latch nodes become
+leaves, and the classes of their children become extra roots, until every latch the
+extraction uses has its inputs extracted.
+*/
+
+use super::*;
+use indexmap::IndexSet;
+
+pub struct LatchExtractor<E> {
+    pub inner: E,
+    pub latch_op: String,
+}
+
+impl<E: Extractor> Extractor for LatchExtractor<E> {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let cut = cut_latches(egraph, &self.latch_op);
+        let mut all_roots: IndexSet<ClassId> = roots.iter().cloned().collect();
+        loop {
+            let roots: Vec<ClassId> = all_roots.iter().cloned().collect();
+            let result = self.inner.extract(&cut, &roots);
+            let before = all_roots.len();
+            all_roots.extend(latch_inputs(egraph, &result, &roots, &self.latch_op));
+            if all_roots.len() == before {
+                return result.restrict(egraph, &roots);
+            }
+        }
+    }
+}
+
+/// The egraph with the children of every latch node removed.
+pub fn cut_latches(egraph: &EGraph, latch_op: &str) -> EGraph {
+    let mut cut = EGraph::default();
+    for (node_id, node) in &egraph.nodes {
+        let node = if node.op == latch_op {
+            Node {
+                children: vec![],
+                ..node.clone()
+            }
+        } else {
+            node.clone()
+        };
+        cut.add_node(node_id.clone(), node);
+    }
+    cut.root_eclasses = egraph.root_eclasses.clone();
+    cut.class_data = egraph.class_data.clone();
+    cut
+}
+
+/// The classes of the children of the latch nodes the roots use.
+pub fn latch_inputs(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+    latch_op: &str,
+) -> Vec<ClassId> {
+    let mut inputs = IndexSet::<ClassId>::default();
+    for node_id in result.restrict(egraph, roots).choices.values() {
+        let node = &egraph[node_id];
+        if node.op == latch_op {
+            inputs.extend(node.children.iter().map(|c| egraph.nid_to_cid(c).clone()));
+        }
+    }
+    inputs.into_iter().collect()
+}
+
+impl ExtractionResult {
+    /// Like `check`, but a cycle is fine if it goes through a latch node.
+    pub fn check_with_latches(&self, egraph: &EGraph, latch_op: &str) {
+        let mut cut = cut_latches(egraph, latch_op);
+        let inputs = latch_inputs(egraph, self, &egraph.root_eclasses, latch_op);
+        for input in inputs {
+            if !cut.root_eclasses.contains(&input) {
+                cut.root_eclasses.push(input);
+            }
+        }
+        self.check(&cut);
+    }
+}
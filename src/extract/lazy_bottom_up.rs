@@ -0,0 +1,60 @@
+/* Bottom-up that only costs the classes the roots can reach.
+
+The fixpoint is the same as bottom-up's, but it runs over just the reachable classes, visited
+children first so that without cycles one pass settles them. For one root of an egraph with
+many outputs that's a small part of the graph. The result only has choices for the reachable
+classes, with the same tree costs bottom-up finds for them.
+*/
+
+use super::*;
+use rustc_hash::FxHashSet;
+
+pub struct LazyBottomUpExtractor;
+impl Extractor for LazyBottomUpExtractor {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let classes = reachable_children_first(egraph, roots);
+
+        let mut result = ExtractionResult::default();
+        bottom_up::fixpoint(
+            || {
+                classes.iter().flat_map(|&class_id| {
+                    egraph[class_id]
+                        .nodes
+                        .iter()
+                        .map(move |node_id| (class_id, node_id))
+                })
+            },
+            |node_id, costs| node_sum_cost_with(egraph, node_id, &NodeCost, costs),
+            |_, class_id, node_id, _| result.choose(class_id.clone(), node_id.clone()),
+        );
+        result
+    }
+}
+
+/// The classes reachable from the roots, each after the classes its nodes use unless a
+/// cycle gets in the way.
+fn reachable_children_first<'a>(egraph: &'a EGraph, roots: &'a [ClassId]) -> Vec<&'a ClassId> {
+    let mut seen: FxHashSet<&ClassId> = FxHashSet::default();
+    let mut order = vec![];
+    // Each entry is a class and whether its children have been pushed already.
+    let mut stack: Vec<(&ClassId, bool)> = roots.iter().rev().map(|r| (r, false)).collect();
+    while let Some((class_id, expanded)) = stack.pop() {
+        if expanded {
+            order.push(class_id);
+            continue;
+        }
+        if !seen.insert(class_id) {
+            continue;
+        }
+        stack.push((class_id, true));
+        for node_id in &egraph[class_id].nodes {
+            for child in &egraph[node_id].children {
+                let child = egraph.nid_to_cid(child);
+                if !seen.contains(child) {
+                    stack.push((child, false));
+                }
+            }
+        }
+    }
+    order
+}
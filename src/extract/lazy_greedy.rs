@@ -0,0 +1,34 @@
+/* Greedy-dag that only looks at the part of the egraph the roots can reach.
+
+Choosing a class needs the cost of every class below any of its nodes, so classes that the
+roots can reach are all costed, but classes that nothing reachable points to (dead
+structure left behind by rewriting) are never visited. The result only has choices for the
+classes the chosen nodes use, which are the same choices greedy-dag makes for them.
+*/
+
+use super::*;
+use rustc_hash::FxHashSet;
+
+pub struct LazyGreedyExtractor;
+impl Extractor for LazyGreedyExtractor {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let mut reachable: FxHashSet<&ClassId> = FxHashSet::default();
+        let mut nodes: Vec<(&NodeId, &Node)> = vec![];
+        let mut todo: Vec<&ClassId> = roots.iter().collect();
+        while let Some(class_id) = todo.pop() {
+            if !reachable.insert(class_id) {
+                continue;
+            }
+            for node_id in &egraph[class_id].nodes {
+                let node = &egraph[node_id];
+                nodes.push((node_id, node));
+                todo.extend(node.children.iter().map(|c| egraph.nid_to_cid(c)));
+            }
+        }
+        // greedy-dag breaks ties by which node it sees first
+        nodes.sort_by_key(|(node_id, _)| egraph.nodes.get_index_of(*node_id));
+
+        let greedy = greedy_dag::extract_nodes(egraph, || nodes.iter().copied(), |_, _, _, _| {});
+        greedy.restrict(egraph, roots)
+    }
+}
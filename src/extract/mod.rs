@@ -1,18 +1,32 @@
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use rustc_hash::{FxHashMap, FxHashSet};
 use std::collections::HashMap;
 
 pub use crate::*;
 
 pub mod bottom_up;
+pub mod bounded_depth;
+#[cfg(feature = "ilp-cbc")]
+pub mod contracted_ilp;
+pub mod dominance;
+pub mod dp_scc;
 pub mod faster_bottom_up;
 pub mod faster_greedy_dag;
 #[cfg(feature = "ilp-cbc")]
 pub mod faster_ilp_cbc;
 pub mod global_greedy_dag;
 pub mod greedy_dag;
+pub mod greedy_max;
 #[cfg(feature = "ilp-cbc")]
 pub mod ilp_cbc;
+pub mod latch;
+pub mod lazy_bottom_up;
+pub mod lazy_greedy;
+pub mod node_budget;
+pub mod ops;
+pub mod perturbed_greedy;
+pub mod recording;
+pub mod shape;
 
 // Allowance for floating point values to be considered equal
 pub const EPSILON_ALLOWANCE: f64 = 0.00001;
@@ -28,6 +42,12 @@ pub trait Extractor: Sync {
     }
 }
 
+impl<E: Extractor + ?Sized> Extractor for &E {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        (**self).extract(egraph, roots)
+    }
+}
+
 pub trait MapGet<K, V> {
     fn get(&self, key: &K) -> Option<&V>;
 }
@@ -59,18 +79,339 @@ where
     }
 }
 
+/// A numeric type costs can be computed in.
+///
+/// `Cost` (`NotNan<f64>`) is the default everywhere; the generic entry points
+/// (e.g. `BottomUpExtractor::extract_with`) accept any other domain, such as
+/// exact rationals with the `rational` feature.
+pub trait CostDomain:
+    Clone + Ord + std::ops::Add<Output = Self> + std::iter::Sum + std::fmt::Debug
+{
+    fn zero() -> Self;
+}
+
+impl CostDomain for Cost {
+    fn zero() -> Self {
+        Cost::default()
+    }
+}
+
+#[cfg(feature = "rational")]
+impl CostDomain for num_rational::Ratio<i64> {
+    fn zero() -> Self {
+        num_rational::Ratio::from_integer(0)
+    }
+}
+
+/// Gives the cost of each node, in some cost domain `C`.
+pub trait CostModel<C> {
+    fn node_cost(&self, egraph: &EGraph, node_id: &NodeId) -> C;
+}
+
+/// The default cost model, which uses the cost stored on each node,
+/// so nodes with the same op can have different costs.
+pub struct NodeCost;
+
+impl CostModel<Cost> for NodeCost {
+    fn node_cost(&self, egraph: &EGraph, node_id: &NodeId) -> Cost {
+        egraph[node_id].cost
+    }
+}
+
+/// Every node costs one, so the cost of an extraction counts its nodes.
+pub struct UnitCost;
+
+impl CostModel<Cost> for UnitCost {
+    fn node_cost(&self, _egraph: &EGraph, _node_id: &NodeId) -> Cost {
+        Cost::new(1.0).unwrap()
+    }
+}
+
+/// Explicit costs for every node, e.g. exact costs that can't be stored on the egraph.
+impl<C: Clone> CostModel<C> for IndexMap<NodeId, C> {
+    fn node_cost(&self, _egraph: &EGraph, node_id: &NodeId) -> C {
+        self[node_id].clone()
+    }
+}
+
+/// The cost of a node plus the costs of its children's classes,
+/// or `None` if some child's class doesn't have a cost yet.
+pub fn node_sum_cost_with<C, M>(
+    egraph: &EGraph,
+    node_id: &NodeId,
+    model: &impl CostModel<C>,
+    costs: &M,
+) -> Option<C>
+where
+    C: CostDomain,
+    M: MapGet<ClassId, C>,
+{
+    let mut cost = model.node_cost(egraph, node_id);
+    for child in &egraph[node_id].children {
+        cost = cost + costs.get(egraph.nid_to_cid(child))?.clone();
+    }
+    Some(cost)
+}
+
 #[derive(Default, Clone)]
 pub struct ExtractionResult {
     pub choices: IndexMap<ClassId, NodeId>,
 }
 
+/// Results are equal when they make the same choices, in whatever order they made them.
+impl PartialEq for ExtractionResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.choices.len() == other.choices.len()
+            && self
+                .choices
+                .iter()
+                .all(|(class_id, node_id)| other.choices.get(class_id) == Some(node_id))
+    }
+}
+
+impl Eq for ExtractionResult {}
+
+/// Lists the choices sorted by class, so two results can be compared by eye.
+impl std::fmt::Debug for ExtractionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut choices: Vec<_> = self.choices.iter().collect();
+        choices.sort();
+        f.debug_struct("ExtractionResult")
+            .field("choices", &DebugMap(&choices))
+            .finish()
+    }
+}
+
+struct DebugMap<'a>(&'a [(&'a ClassId, &'a NodeId)]);
+
+impl std::fmt::Debug for DebugMap<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.0.iter().copied()).finish()
+    }
+}
+
+/// How the cost of a class shared between several roots is split between them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CostAttribution {
+    /// Each root using the class pays the same share.
+    Equal,
+    /// Each root pays in proportion to how many times its part of the DAG references the class.
+    Proportional,
+}
+
+impl std::str::FromStr for CostAttribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "equal" => Ok(CostAttribution::Equal),
+            "proportional" => Ok(CostAttribution::Proportional),
+            _ => Err(format!("Unknown cost attribution: {s}")),
+        }
+    }
+}
+
+/// Which node the bottom-up extractors choose when several in a class are equally cheap.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TieFit {
+    /// The first of them in the class.
+    #[default]
+    First,
+    /// The last of them in the class.
+    Last,
+    /// The one with the smallest id.
+    SmallestId,
+}
+
+impl std::str::FromStr for TieFit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(TieFit::First),
+            "last" => Ok(TieFit::Last),
+            "smallest-id" => Ok(TieFit::SmallestId),
+            _ => Err(format!("Unknown tie fit: {s}")),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 enum Status {
     Doing,
     Done,
 }
 
+/// Callbacks for walking an extraction with `ExtractionResult::visit`. They all do nothing
+/// by default, and an error from one stops the walk.
+///
+/// The roots are walked in order, each depth first. A class's chosen node is entered, then
+/// for each of its children in order there's the edge to the child's chosen node followed by
+/// the walk of the child, and then the node is left. A node that was already entered, through
+/// an earlier root or another parent, only gets the edge, so each node is entered and left
+/// once, and its children are left before it. After each root's walk, the root is left too,
+/// even if it had been walked already.
+pub trait DagVisitor {
+    fn enter_node(&mut self, _class_id: &ClassId, _node_id: &NodeId) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn on_edge(&mut self, _parent: &NodeId, _child: &NodeId) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn leave_node(&mut self, _class_id: &ClassId, _node_id: &NodeId) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn leave_root(&mut self, _root: &ClassId) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// How many times the roots and the chosen nodes use each class of an extraction, kept up
+/// to date as its choices change. The dag cost change of a swap then only visits the classes
+/// that start or stop being used, instead of the whole extraction.
+pub struct DagUses {
+    uses: FxHashMap<ClassId, usize>,
+}
+
+impl DagUses {
+    pub fn new(egraph: &EGraph, result: &ExtractionResult, roots: &[ClassId]) -> Self {
+        let mut uses: FxHashMap<ClassId, usize> = FxHashMap::default();
+        let mut todo: Vec<&ClassId> = roots.iter().collect();
+        while let Some(cid) = todo.pop() {
+            let count = uses.entry(cid.clone()).or_default();
+            *count += 1;
+            if *count == 1 {
+                todo.extend(result.children_classes(egraph, cid));
+            }
+        }
+        DagUses { uses }
+    }
+
+    /// How much the dag cost would change if `class_id` chose `new_node` instead. That's
+    /// infinite if the new node would make a cycle or needs a class without a choice, and
+    /// otherwise zero if the roots don't use the class.
+    pub fn delta(
+        &self,
+        egraph: &EGraph,
+        result: &ExtractionResult,
+        class_id: &ClassId,
+        new_node: &NodeId,
+    ) -> Cost {
+        self.swap(egraph, result, class_id, new_node).0
+    }
+
+    /// Makes `class_id` choose `new_node`, and updates the uses to match. Returns the change
+    /// in dag cost, as `delta` does. If that's infinite, nothing changes.
+    pub fn change(
+        &mut self,
+        egraph: &EGraph,
+        result: &mut ExtractionResult,
+        class_id: &ClassId,
+        new_node: &NodeId,
+    ) -> Cost {
+        let (delta, changes) = self.swap(egraph, result, class_id, new_node);
+        if delta == INFINITY {
+            return delta;
+        }
+        for (cid, change) in changes {
+            let count = self.uses.entry(cid.clone()).or_default();
+            *count = count.checked_add_signed(change).unwrap();
+            if *count == 0 {
+                self.uses.remove(&cid);
+            }
+        }
+        result.choose(class_id.clone(), new_node.clone());
+        delta
+    }
+
+    /// The dag cost change of a swap, and how the uses of each class it touches change.
+    fn swap(
+        &self,
+        egraph: &EGraph,
+        result: &ExtractionResult,
+        class_id: &ClassId,
+        new_node: &NodeId,
+    ) -> (Cost, FxHashMap<ClassId, isize>) {
+        let mut changes: FxHashMap<ClassId, isize> = FxHashMap::default();
+        let old_node = &result.choices[class_id];
+        if old_node == new_node {
+            return (Cost::default(), changes);
+        }
+
+        // the new node can't reach its own class, even if the roots don't use it yet
+        let mut seen: FxHashSet<&ClassId> = FxHashSet::default();
+        let mut todo: Vec<&ClassId> = egraph[new_node]
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c))
+            .collect();
+        while let Some(cid) = todo.pop() {
+            if cid == class_id || !result.choices.contains_key(cid) {
+                return (INFINITY, changes);
+            }
+            if seen.insert(cid) {
+                todo.extend(result.children_classes(egraph, cid));
+            }
+        }
+        if !self.uses.contains_key(class_id) {
+            return (Cost::default(), changes);
+        }
+
+        let count = |changes: &FxHashMap<ClassId, isize>, cid: &ClassId| {
+            self.uses.get(cid).copied().unwrap_or(0) as isize
+                + changes.get(cid).copied().unwrap_or(0)
+        };
+        let mut delta = egraph[new_node].cost - egraph[old_node].cost;
+        let mut todo: Vec<&ClassId> = egraph[new_node]
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c))
+            .collect();
+        while let Some(cid) = todo.pop() {
+            *changes.entry(cid.clone()).or_default() += 1;
+            if count(&changes, cid) == 1 {
+                delta += egraph[&result.choices[cid]].cost;
+                todo.extend(result.children_classes(egraph, cid));
+            }
+        }
+        let mut todo: Vec<&ClassId> = egraph[old_node]
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c))
+            .collect();
+        while let Some(cid) = todo.pop() {
+            *changes.entry(cid.clone()).or_default() -= 1;
+            if count(&changes, cid) == 0 {
+                delta -= egraph[&result.choices[cid]].cost;
+                todo.extend(result.children_classes(egraph, cid));
+            }
+        }
+        (delta, changes)
+    }
+}
+
 impl ExtractionResult {
+    /// The union of the choices of several results over the same egraph, e.g. of different
+    /// roots extracted separately. Fails if two of them choose different nodes for a class.
+    pub fn merge(results: &[ExtractionResult]) -> anyhow::Result<ExtractionResult> {
+        let mut merged = ExtractionResult::default();
+        for result in results {
+            for (class_id, node_id) in &result.choices {
+                match merged.choices.get(class_id) {
+                    Some(chosen) if chosen != node_id => anyhow::bail!(
+                        "Class {class_id} is {chosen} in one result and {node_id} in another"
+                    ),
+                    Some(_) => {}
+                    None => merged.choose(class_id.clone(), node_id.clone()),
+                }
+            }
+        }
+        Ok(merged)
+    }
+
     pub fn check(&self, egraph: &EGraph) {
         // should be a root
         assert!(!egraph.root_eclasses.is_empty());
@@ -108,6 +449,70 @@ impl ExtractionResult {
         self.choices.insert(class_id, node_id);
     }
 
+    /// Walks the nodes the roots use, calling the visitor as `DagVisitor` describes. Fails
+    /// if a class on the way has no choice. With a cycle, the node that closes it is only
+    /// given the edge, as it was entered already.
+    pub fn visit<V: DagVisitor>(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+        visitor: &mut V,
+    ) -> anyhow::Result<()> {
+        let mut entered: FxHashSet<&ClassId> = FxHashSet::default();
+        for root in roots {
+            self.visit_class(egraph, root, visitor, &mut entered)?;
+            visitor.leave_root(root)?;
+        }
+        Ok(())
+    }
+
+    fn visit_class<'a, V: DagVisitor>(
+        &'a self,
+        egraph: &'a EGraph,
+        class_id: &'a ClassId,
+        visitor: &mut V,
+        entered: &mut FxHashSet<&'a ClassId>,
+    ) -> anyhow::Result<()> {
+        if !entered.insert(class_id) {
+            return Ok(());
+        }
+        let Some(node_id) = self.choices.get(class_id) else {
+            anyhow::bail!("Class {class_id} has no choice");
+        };
+        visitor.enter_node(class_id, node_id)?;
+        for child in &egraph[node_id].children {
+            let child_class = egraph.nid_to_cid(child);
+            let Some(child_node) = self.choices.get(child_class) else {
+                anyhow::bail!("Class {child_class} has no choice");
+            };
+            visitor.on_edge(node_id, child_node)?;
+            self.visit_class(egraph, child_class, visitor, entered)?;
+        }
+        visitor.leave_node(class_id, node_id)
+    }
+
+    /// Just the choices reachable from `roots`.
+    pub fn restrict(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let mut result = ExtractionResult::default();
+        let mut todo: Vec<&ClassId> = roots.iter().collect();
+        while let Some(class_id) = todo.pop() {
+            if result.choices.contains_key(class_id) {
+                continue;
+            }
+            let Some(node_id) = self.choices.get(class_id) else {
+                continue;
+            };
+            result.choose(class_id.clone(), node_id.clone());
+            todo.extend(
+                egraph[node_id]
+                    .children
+                    .iter()
+                    .map(|c| egraph.nid_to_cid(c)),
+            );
+        }
+        result
+    }
+
     pub fn find_cycles(&self, egraph: &EGraph, roots: &[ClassId]) -> Vec<ClassId> {
         // let mut status = vec![Status::Todo; egraph.classes().len()];
         let mut status = IndexMap::<ClassId, Status>::default();
@@ -142,50 +547,437 @@ impl ExtractionResult {
         }
     }
 
+    /// The nodes in `nodes` that aren't chosen for a class the roots use.
+    pub fn uncovered<'a>(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+        nodes: &'a [NodeId],
+    ) -> Vec<&'a NodeId> {
+        let mut used: FxHashSet<&NodeId> = FxHashSet::default();
+        let mut todo: Vec<&ClassId> = roots.iter().collect();
+        while let Some(class_id) = todo.pop() {
+            let node_id = &self.choices[class_id];
+            if used.insert(node_id) {
+                todo.extend(
+                    egraph[node_id]
+                        .children
+                        .iter()
+                        .map(|c| egraph.nid_to_cid(c)),
+                );
+            }
+        }
+        nodes.iter().filter(|n| !used.contains(n)).collect()
+    }
+
     pub fn tree_cost(&self, egraph: &EGraph, roots: &[ClassId]) -> Cost {
+        self.tree_cost_with(egraph, roots, &NodeCost)
+    }
+
+    pub fn tree_cost_with<C: CostDomain>(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+        model: &impl CostModel<C>,
+    ) -> C {
+        // A root that is repeated is still only one tree.
         let node_roots = roots
             .iter()
+            .collect::<IndexSet<&ClassId>>()
+            .into_iter()
             .map(|cid| self.choices[cid].clone())
             .collect::<Vec<NodeId>>();
-        self.tree_cost_rec(egraph, &node_roots, &mut HashMap::new())
+        self.tree_cost_rec(egraph, model, &node_roots, &mut HashMap::new())
     }
 
-    fn tree_cost_rec(
+    fn tree_cost_rec<C: CostDomain>(
         &self,
         egraph: &EGraph,
+        model: &impl CostModel<C>,
         roots: &[NodeId],
-        memo: &mut HashMap<NodeId, Cost>,
-    ) -> Cost {
-        let mut cost = Cost::default();
+        memo: &mut HashMap<NodeId, C>,
+    ) -> C {
+        let mut cost = C::zero();
         for root in roots {
             if let Some(c) = memo.get(root) {
-                cost += *c;
+                cost = cost + c.clone();
                 continue;
             }
             let class = egraph.nid_to_cid(root);
-            let node = &egraph[&self.choices[class]];
-            let inner = node.cost + self.tree_cost_rec(egraph, &node.children, memo);
-            memo.insert(root.clone(), inner);
-            cost += inner;
+            let node_id = &self.choices[class];
+            let inner = model.node_cost(egraph, node_id)
+                + self.tree_cost_rec(egraph, model, &egraph[node_id].children, memo);
+            memo.insert(root.clone(), inner.clone());
+            cost = cost + inner;
         }
         cost
     }
 
+    /// The most cost along any path of chosen nodes from a root to a leaf, e.g. the delay
+    /// of the slowest path through a circuit when the model gives each node's delay.
+    pub fn critical_path_with<C: CostDomain>(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+        model: &impl CostModel<C>,
+    ) -> C {
+        fn rec<C: CostDomain>(
+            result: &ExtractionResult,
+            egraph: &EGraph,
+            model: &impl CostModel<C>,
+            class_id: &ClassId,
+            memo: &mut HashMap<ClassId, C>,
+        ) -> C {
+            if let Some(c) = memo.get(class_id) {
+                return c.clone();
+            }
+            let node_id = &result.choices[class_id];
+            let slowest_child = egraph[node_id]
+                .children
+                .iter()
+                .map(|child| rec(result, egraph, model, egraph.nid_to_cid(child), memo))
+                .max()
+                .unwrap_or_else(C::zero);
+            let path = model.node_cost(egraph, node_id) + slowest_child;
+            memo.insert(class_id.clone(), path.clone());
+            path
+        }
+
+        let mut memo = HashMap::new();
+        roots
+            .iter()
+            .map(|root| rec(self, egraph, model, root, &mut memo))
+            .max()
+            .unwrap_or_else(C::zero)
+    }
+
     // this will loop if there are cycles
     pub fn dag_cost(&self, egraph: &EGraph, roots: &[ClassId]) -> Cost {
-        let mut costs: IndexMap<ClassId, Cost> = IndexMap::new();
+        self.dag_cost_with(egraph, roots, &NodeCost)
+    }
+
+    // this will loop if there are cycles
+    pub fn dag_cost_with<C: CostDomain>(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+        model: &impl CostModel<C>,
+    ) -> C {
+        let mut costs: IndexMap<ClassId, C> = IndexMap::new();
         let mut todo: Vec<ClassId> = roots.to_vec();
         while let Some(cid) = todo.pop() {
             let node_id = &self.choices[&cid];
-            let node = &egraph[node_id];
-            if costs.insert(cid.clone(), node.cost).is_some() {
+            if costs
+                .insert(cid.clone(), model.node_cost(egraph, node_id))
+                .is_some()
+            {
                 continue;
             }
-            for child in &node.children {
+            for child in &egraph[node_id].children {
                 todo.push(egraph.nid_to_cid(child).clone());
             }
         }
-        costs.values().sum()
+        costs.into_values().sum()
+    }
+
+    /// The cost when a node's cost decays with reuse: its first use costs its cost and each
+    /// further use `decay` times that. A use is a chosen node with it as a child, or a root.
+    /// A decay of 0 gives the dag cost.
+    pub fn reuse_cost(&self, egraph: &EGraph, roots: &[ClassId], decay: f64) -> Cost {
+        let mut uses: IndexMap<&ClassId, usize> = IndexMap::new();
+        let mut todo: Vec<&ClassId> = roots.iter().collect::<IndexSet<_>>().into_iter().collect();
+        for &root in &todo {
+            uses.insert(root, 1);
+        }
+        while let Some(cid) = todo.pop() {
+            for child in &egraph[&self.choices[cid]].children {
+                let child_cid = egraph.nid_to_cid(child);
+                let count = uses.entry(child_cid).or_insert(0);
+                *count += 1;
+                if *count == 1 {
+                    todo.push(child_cid);
+                }
+            }
+        }
+        uses.into_iter()
+            .map(|(cid, uses)| {
+                let cost = egraph[&self.choices[cid]].cost;
+                cost * (1.0 + (uses - 1) as f64 * decay)
+            })
+            .sum()
+    }
+
+    /// How much the dag cost of the roots would change if `class_id` chose `new_node`
+    /// instead, as `DagUses::delta` finds it. For several swaps of one extraction, make the
+    /// `DagUses` once.
+    pub fn cost_delta_if_changed(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+        class_id: &ClassId,
+        new_node: &NodeId,
+    ) -> Cost {
+        DagUses::new(egraph, self, roots).delta(egraph, self, class_id, new_node)
+    }
+
+    fn children_classes<'a>(
+        &'a self,
+        egraph: &'a EGraph,
+        class_id: &ClassId,
+    ) -> impl Iterator<Item = &'a ClassId> {
+        egraph[&self.choices[class_id]]
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c))
+    }
+
+    // Splits the dag cost between the roots, so the per-root figures sum to the dag cost.
+    // this will loop if there are cycles
+    pub fn attribute_cost(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+        attribution: CostAttribution,
+    ) -> IndexMap<ClassId, Cost> {
+        // For each class, the number of references to it from each root's part of the DAG.
+        let mut usage: IndexMap<ClassId, IndexMap<ClassId, usize>> = IndexMap::new();
+        let mut per_root: IndexMap<ClassId, Cost> = IndexMap::new();
+        for root in roots {
+            if per_root.insert(root.clone(), Cost::default()).is_some() {
+                continue;
+            }
+            *usage
+                .entry(root.clone())
+                .or_default()
+                .entry(root.clone())
+                .or_default() += 1;
+
+            let mut todo: Vec<ClassId> = vec![root.clone()];
+            let mut visited: FxHashSet<ClassId> = Default::default();
+            while let Some(cid) = todo.pop() {
+                if !visited.insert(cid.clone()) {
+                    continue;
+                }
+                for child in &egraph[&self.choices[&cid]].children {
+                    let child_cid = egraph.nid_to_cid(child);
+                    *usage
+                        .entry(child_cid.clone())
+                        .or_default()
+                        .entry(root.clone())
+                        .or_default() += 1;
+                    todo.push(child_cid.clone());
+                }
+            }
+        }
+
+        for (cid, users) in &usage {
+            let cost = egraph[&self.choices[cid]].cost;
+            let total: usize = match attribution {
+                CostAttribution::Equal => users.len(),
+                CostAttribution::Proportional => users.values().sum(),
+            };
+            for (root, &refs) in users {
+                let share = match attribution {
+                    CostAttribution::Equal => 1,
+                    CostAttribution::Proportional => refs,
+                };
+                per_root[root] += cost * (share as f64 / total as f64);
+            }
+        }
+        per_root
+    }
+
+    /// Switches each class to the best of the nodes with the same tree cost as its choice,
+    /// unless that would make a cycle: the one `priority` ranks highest, then the one with
+    /// the fewest nodes in its dag, then the one `tie_fit` prefers. The tree cost doesn't
+    /// change, but the choice no longer depends on the order an extractor happened to visit
+    /// nodes in, and a region of free nodes isn't extracted any bigger than it has to be.
+    ///
+    /// A node's dag is counted under the choices it starts from, so the sizes stay put while
+    /// classes switch. Only ties in tree cost are refit, so it's for the extractors that
+    /// minimize tree cost; for the others, switching could make the dag cost worse.
+    pub fn refit_ties(&mut self, egraph: &EGraph, tie_fit: TieFit) {
+        self.refit_ties_with(egraph, tie_fit, |_| 0)
+    }
+
+    /// `refit_ties` with a priority for each node, like one from --tie-break-key.
+    pub fn refit_ties_with(
+        &mut self,
+        egraph: &EGraph,
+        tie_fit: TieFit,
+        priority: impl Fn(&Node) -> i64,
+    ) {
+        let mut costs = FxHashMap::<ClassId, Cost>::default();
+        for class_id in self.choices.keys() {
+            self.class_tree_cost(egraph, class_id, &mut costs);
+        }
+        let is_tied = |class_id: &ClassId, node_id: &NodeId| {
+            node_sum_cost_with(egraph, node_id, &NodeCost, &costs) == Some(costs[class_id])
+        };
+
+        // Each class's tied nodes, best first.
+        let mut ranked: Vec<(&ClassId, Vec<&NodeId>)> = vec![];
+        for (class_id, class) in egraph.classes() {
+            if !self.choices.contains_key(class_id) {
+                continue;
+            }
+            let mut tied: Vec<(i64, usize, usize, &NodeId)> = class
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, n)| is_tied(class_id, n))
+                .map(|(i, n)| (priority(&egraph[n]), 0, i, n))
+                .collect();
+            if tied.len() < 2 {
+                continue;
+            }
+            for (_, size, _, node_id) in &mut tied {
+                *size = self.node_dag_size(egraph, node_id);
+            }
+            tied.sort_by(|a, b| {
+                b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(match tie_fit {
+                    TieFit::First => a.2.cmp(&b.2),
+                    TieFit::Last => b.2.cmp(&a.2),
+                    TieFit::SmallestId => a.3.cmp(b.3),
+                })
+            });
+            ranked.push((class_id, tied.into_iter().map(|(.., n)| n).collect()));
+        }
+
+        loop {
+            let mut changed = false;
+            for (class_id, tied) in &ranked {
+                let current = self.choices[*class_id].clone();
+                for &node_id in tied {
+                    if *node_id == current {
+                        break;
+                    }
+                    if !self.reaches(egraph, node_id, class_id) {
+                        self.choose((*class_id).clone(), node_id.clone());
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// How many nodes a dag rooted at `node_id` has under these choices.
+    fn node_dag_size(&self, egraph: &EGraph, node_id: &NodeId) -> usize {
+        let mut seen = FxHashSet::<&ClassId>::default();
+        let mut todo: Vec<&ClassId> = egraph[node_id]
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c))
+            .collect();
+        while let Some(class_id) = todo.pop() {
+            if !seen.insert(class_id) {
+                continue;
+            }
+            if let Some(chosen) = self.choices.get(class_id) {
+                todo.extend(egraph[chosen].children.iter().map(|c| egraph.nid_to_cid(c)));
+            }
+        }
+        1 + seen.len()
+    }
+
+    // this will loop if there are cycles
+    fn class_tree_cost(
+        &self,
+        egraph: &EGraph,
+        class_id: &ClassId,
+        costs: &mut FxHashMap<ClassId, Cost>,
+    ) -> Cost {
+        if let Some(cost) = costs.get(class_id) {
+            return *cost;
+        }
+        let node = &egraph[&self.choices[class_id]];
+        let mut cost = node.cost;
+        for child in &node.children {
+            cost += self.class_tree_cost(egraph, egraph.nid_to_cid(child), costs);
+        }
+        costs.insert(class_id.clone(), cost);
+        cost
+    }
+
+    /// For each class the roots use that has other nodes, the cheapest node it didn't
+    /// choose and how much more that costs locally: the node's cost plus its children's tree
+    /// costs under these choices, against the same for the chosen node. A small increase
+    /// means the choice is fragile. Nodes that would need a class without a choice, or reach
+    /// back to their own class, aren't alternatives.
+    // this will loop if there are cycles
+    pub fn alternatives(
+        &self,
+        egraph: &EGraph,
+        roots: &[ClassId],
+    ) -> IndexMap<ClassId, (NodeId, Cost)> {
+        let mut used: indexmap::IndexSet<ClassId> = Default::default();
+        let mut todo: Vec<ClassId> = roots.to_vec();
+        while let Some(cid) = todo.pop() {
+            if used.insert(cid.clone()) {
+                for child in &egraph[&self.choices[&cid]].children {
+                    todo.push(egraph.nid_to_cid(child).clone());
+                }
+            }
+        }
+
+        let mut tree_costs: FxHashMap<ClassId, Cost> = FxHashMap::default();
+        let mut alternatives = IndexMap::new();
+        for cid in &used {
+            let chosen = &self.choices[cid];
+            let mut local = |node_id: &NodeId| -> Option<Cost> {
+                if self.reaches(egraph, node_id, cid) {
+                    return None;
+                }
+                let mut cost = egraph[node_id].cost;
+                for child in &egraph[node_id].children {
+                    let child = egraph.nid_to_cid(child);
+                    if !self.choices.contains_key(child) {
+                        return None;
+                    }
+                    cost += *tree_costs
+                        .entry(child.clone())
+                        .or_insert_with(|| self.tree_cost(egraph, std::slice::from_ref(child)));
+                }
+                Some(cost)
+            };
+            let chosen_cost = local(chosen).unwrap();
+            let best = egraph[cid]
+                .nodes
+                .iter()
+                .filter(|node_id| *node_id != chosen)
+                .filter_map(|node_id| Some((node_id, local(node_id)?)))
+                .min_by_key(|(_, cost)| *cost);
+            if let Some((node_id, cost)) = best {
+                alternatives.insert(cid.clone(), (node_id.clone(), cost - chosen_cost));
+            }
+        }
+        alternatives
+    }
+
+    /// Whether `target` is below `node_id`, following the chosen nodes.
+    fn reaches(&self, egraph: &EGraph, node_id: &NodeId, target: &ClassId) -> bool {
+        let mut seen = FxHashSet::<&ClassId>::default();
+        let mut todo: Vec<&ClassId> = egraph[node_id]
+            .children
+            .iter()
+            .map(|c| egraph.nid_to_cid(c))
+            .collect();
+        while let Some(class_id) = todo.pop() {
+            if class_id == target {
+                return true;
+            }
+            if !seen.insert(class_id) {
+                continue;
+            }
+            if let Some(chosen) = self.choices.get(class_id) {
+                todo.extend(egraph[chosen].children.iter().map(|c| egraph.nid_to_cid(c)));
+            }
+        }
+        false
     }
 
     pub fn node_sum_cost<M>(&self, egraph: &EGraph, node: &Node, costs: &M) -> Cost
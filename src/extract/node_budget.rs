@@ -0,0 +1,54 @@
+/* Extracts as many roots as fit in at most `budget` nodes.
+
+It extracts all the roots with faster-greedy-dag, then takes them in increasing order of
+their own dag cost, keeping each root whose nodes not already kept still fit in the budget.
+Nodes shared with roots kept earlier don't count again. Roots that don't fit are left out of
+the result.
+*/
+
+use super::*;
+
+pub struct NodeBudgetExtractor {
+    pub budget: usize,
+}
+
+impl Extractor for NodeBudgetExtractor {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let full = faster_greedy_dag::FasterGreedyDagExtractor.extract(egraph, roots);
+
+        let mut by_cost: Vec<(Cost, &ClassId)> = roots
+            .iter()
+            .filter(|root| full.choices.contains_key(*root))
+            .map(|root| (full.dag_cost(egraph, std::slice::from_ref(root)), root))
+            .collect();
+        by_cost.sort();
+
+        let mut kept: FxHashSet<ClassId> = FxHashSet::default();
+        for (_, root) in by_cost {
+            let mut new = vec![];
+            let mut todo = vec![root];
+            let mut seen = FxHashSet::default();
+            while let Some(class_id) = todo.pop() {
+                if kept.contains(class_id) || !seen.insert(class_id) {
+                    continue;
+                }
+                new.push(class_id);
+                let node = &egraph[&full.choices[class_id]];
+                todo.extend(node.children.iter().map(|c| egraph.nid_to_cid(c)));
+            }
+            if kept.len() + new.len() <= self.budget {
+                kept.extend(new.into_iter().cloned());
+            } else {
+                log::info!("Root {root} doesn't fit in the node budget");
+            }
+        }
+
+        let mut result = ExtractionResult::default();
+        for (class_id, node_id) in &full.choices {
+            if kept.contains(class_id) {
+                result.choose(class_id.clone(), node_id.clone());
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,31 @@
+/* Aliases between the names different front-ends give the same op. */
+
+use super::*;
+
+/// Renames ops by their aliases, from the name different front-ends give an op to the one
+/// the rest of the gym knows it by, like `bvadd` to `Add`. Only the name before any `(` is
+/// matched, the rest of the op is kept.
+pub fn apply_aliases(egraph: &EGraph, aliases: &IndexMap<String, String>) -> EGraph {
+    let mut result = EGraph::default();
+    for (node_id, node) in &egraph.nodes {
+        let (name, rest) = node.op.split_at(node.op.find('(').unwrap_or(node.op.len()));
+        let op = match aliases.get(name) {
+            Some(alias) => format!("{alias}{rest}"),
+            None => node.op.clone(),
+        };
+        result.add_node(node_id.clone(), Node { op, ..node.clone() });
+    }
+    result.root_eclasses = egraph.root_eclasses.clone();
+    result.class_data = egraph.class_data.clone();
+    result
+}
+
+/// Parses an alias given as `alias=op`.
+pub fn parse_alias(alias: &str) -> anyhow::Result<(String, String)> {
+    match alias.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_string(), to.to_string()))
+        }
+        _ => anyhow::bail!("An alias should be alias=op, not {alias}"),
+    }
+}
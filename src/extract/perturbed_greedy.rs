@@ -0,0 +1,88 @@
+/* Faster-greedy-dag with some of its choices changed at random, for extractions that are
+cheap but differ from each other, like the members of a portfolio.
+
+Each class the greedy extraction uses is switched to another of its nodes with probability
+`fraction`, decided by a random number generator seeded with the seed and the class, so the
+same seed gives the same result. Only nodes that don't reach their own class are picked, and every class keeps its
+greedy choice until it's switched, so the new children of a switched node are already
+chosen and the result stays valid. Then each class that wasn't switched takes whichever of
+its nodes lowers the dag cost the most, once, to win back some of what the switches cost.
+*/
+
+use super::*;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::hash::{Hash, Hasher};
+
+pub struct PerturbedGreedyExtractor {
+    pub fraction: f64,
+    pub seed: u64,
+}
+
+impl Default for PerturbedGreedyExtractor {
+    fn default() -> Self {
+        Self {
+            fraction: 0.1,
+            seed: 0,
+        }
+    }
+}
+
+impl Extractor for PerturbedGreedyExtractor {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let mut result = faster_greedy_dag::FasterGreedyDagExtractor.extract(egraph, roots);
+        let used: Vec<ClassId> = result.restrict(egraph, roots).choices.into_keys().collect();
+
+        let mut perturbed: FxHashSet<&ClassId> = FxHashSet::default();
+        for class_id in &used {
+            let mut rng = self.rng(class_id);
+            if egraph[class_id].nodes.len() < 2 || rng.gen::<f64>() >= self.fraction {
+                continue;
+            }
+            let alternatives: Vec<&NodeId> = egraph[class_id]
+                .nodes
+                .iter()
+                .filter(|node_id| **node_id != result.choices[class_id])
+                .filter(|node_id| {
+                    egraph[*node_id]
+                        .children
+                        .iter()
+                        .all(|c| result.choices.contains_key(egraph.nid_to_cid(c)))
+                })
+                .filter(|node_id| !result.reaches(egraph, node_id, class_id))
+                .collect();
+            if let Some(node_id) = alternatives.choose(&mut rng) {
+                result.choose(class_id.clone(), (*node_id).clone());
+                perturbed.insert(class_id);
+            }
+        }
+
+        // repair what the switches cost, without undoing them
+        let used: Vec<ClassId> = result.restrict(egraph, roots).choices.into_keys().collect();
+        let mut uses = DagUses::new(egraph, &result, roots);
+        for class_id in used.iter().filter(|c| !perturbed.contains(c)) {
+            let best = egraph[class_id]
+                .nodes
+                .iter()
+                .map(|node_id| (uses.delta(egraph, &result, class_id, node_id), node_id))
+                .min_by_key(|(delta, _)| *delta);
+            if let Some((delta, node_id)) = best {
+                if delta < Cost::default() {
+                    uses.change(egraph, &mut result, class_id, node_id);
+                }
+            }
+        }
+
+        result.restrict(egraph, roots)
+    }
+}
+
+impl PerturbedGreedyExtractor {
+    /// The random numbers for a class depend only on the seed and the class, so extracting
+    /// disjoint roots separately perturbs the same classes.
+    fn rng(&self, class_id: &ClassId) -> rand::rngs::StdRng {
+        let mut hasher = rustc_hash::FxHasher::default();
+        class_id.hash(&mut hasher);
+        rand::rngs::StdRng::seed_from_u64(self.seed ^ hasher.finish())
+    }
+}
@@ -0,0 +1,87 @@
+/* Recording an extractor's choices to a log, and replaying them later without it.
+
+The log has a line per choice, in the order the extractor first made them:
+
+    class<TAB>chosen node<TAB>candidate=score<TAB>candidate=score...
+
+with every node of the class as a candidate. Extractors don't expose how they scored
+nodes, so a candidate's score is its cost plus the min tree cost of its children, which is
+the same for every extractor. Lines starting with `#` are comments.
+*/
+
+use super::*;
+use std::io::Write;
+use std::path::PathBuf;
+
+pub struct RecordingExtractor<E> {
+    pub inner: E,
+    pub path: PathBuf,
+}
+
+impl<E: Extractor> Extractor for RecordingExtractor<E> {
+    fn extract(&self, egraph: &EGraph, roots: &[ClassId]) -> ExtractionResult {
+        let result = self.inner.extract(egraph, roots);
+        let log = to_log(egraph, &result);
+        std::fs::File::create(&self.path)
+            .and_then(|mut file| file.write_all(log.as_bytes()))
+            .unwrap_or_else(|e| panic!("Failed to write {}: {e}", self.path.display()));
+        result
+    }
+}
+
+pub fn to_log(egraph: &EGraph, result: &ExtractionResult) -> String {
+    let min_costs = bottom_up::min_tree_costs(egraph);
+    let mut log = String::from("# class\tchosen\tcandidate=score...\n");
+    for (class_id, chosen) in &result.choices {
+        log.push_str(&format!("{class_id}\t{chosen}"));
+        for node_id in &egraph[class_id].nodes {
+            let node = &egraph[node_id];
+            let score = node.children.iter().fold(node.cost, |score, child| {
+                score
+                    + min_costs
+                        .get(egraph.nid_to_cid(child))
+                        .copied()
+                        .unwrap_or(INFINITY)
+            });
+            log.push_str(&format!("\t{node_id}={score}"));
+        }
+        log.push('\n');
+    }
+    log
+}
+
+/// Makes the choices in a log written by `RecordingExtractor`, whatever the roots.
+pub struct ReplayExtractor {
+    pub choices: Vec<(ClassId, NodeId)>,
+}
+
+impl ReplayExtractor {
+    pub fn from_log(log: &str) -> anyhow::Result<Self> {
+        let choices = log
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let mut fields = line.split('\t');
+                match (fields.next(), fields.next()) {
+                    (Some(class_id), Some(node_id)) => Ok((class_id.into(), node_id.into())),
+                    _ => anyhow::bail!("Expected a class and a node in {line:?}"),
+                }
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(ReplayExtractor { choices })
+    }
+}
+
+impl Extractor for ReplayExtractor {
+    fn extract(&self, egraph: &EGraph, _roots: &[ClassId]) -> ExtractionResult {
+        let mut result = ExtractionResult::default();
+        for (class_id, node_id) in &self.choices {
+            match egraph.nodes.get(node_id) {
+                Some(node) if node.eclass == *class_id => {}
+                _ => panic!("The egraph has no node {node_id} in class {class_id} to replay"),
+            }
+            result.choose(class_id.clone(), node_id.clone());
+        }
+        result
+    }
+}
@@ -0,0 +1,48 @@
+/* A template for the structure of an extraction, like `(Add ? (Mul ? ?))`.
+
+A list is a node with that op and one child per element, a bare op is a node without
+children, and `?` matches anything.
+*/
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Any,
+    Node(String, Vec<Shape>),
+}
+
+impl std::str::FromStr for Shape {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spaced = s.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = spaced.split_whitespace().peekable();
+        let shape = parse(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            return Err(format!("Unexpected {token} after the end of shape {s:?}"));
+        }
+        Ok(shape)
+    }
+}
+
+fn parse<'a>(
+    tokens: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> Result<Shape, String> {
+    match tokens.next() {
+        None => Err("The shape ended early".to_string()),
+        Some(")") => Err("Unexpected )".to_string()),
+        Some("?") => Ok(Shape::Any),
+        Some("(") => {
+            let op = match tokens.next() {
+                Some("(" | ")" | "?") | None => return Err("Expected an op after (".to_string()),
+                Some(op) => op.to_string(),
+            };
+            let mut children = vec![];
+            while tokens.peek() != Some(&")") {
+                children.push(parse(tokens)?);
+            }
+            tokens.next();
+            Ok(Shape::Node(op, children))
+        }
+        Some(op) => Ok(Shape::Node(op.to_string(), vec![])),
+    }
+}
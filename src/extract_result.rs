@@ -1,14 +1,48 @@
 mod extract;
 
-use extract::*;
-use egraph_serialize::*;
-use ordered_float::NotNan;
 use anyhow::Context;
+use egraph_serialize::*;
 use env_logger;
+use extract::*;
+use ordered_float::NotNan;
 
 pub type Cost = NotNan<f64>;
 pub const INFINITY: Cost = unsafe { NotNan::new_unchecked(std::f64::INFINITY) };
 
+// ANSI colors for the assignment lines: variables, operators and constants each get their own
+struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn var(&self, text: &str) -> String {
+        self.paint("36", text)
+    }
+
+    fn op(&self, text: &str) -> String {
+        self.paint("35", text)
+    }
+
+    fn constant(&self, text: &str) -> String {
+        self.paint("33", text)
+    }
+}
+
+// How --form tree expands the extraction: the size of the tree of each class written so far, and the classes being expanded, to catch cycles
+struct TreeExpansion {
+    max_nodes: usize,
+    sizes: std::collections::HashMap<ClassId, usize>,
+    expanding: std::collections::HashSet<ClassId>,
+}
+
 fn main() {
     env_logger::init();
 
@@ -19,6 +53,32 @@ fn main() {
         .unwrap()
         .unwrap_or_else(|| "faster-greedy-dag".into());
 
+    // Whether to color the assignments: auto (only when stdout is a terminal), always or never
+    let color: String = args
+        .opt_value_from_str("--color")
+        .unwrap()
+        .unwrap_or_else(|| "auto".into());
+
+    // Print shared sub-expressions once, as assignments (dag, the default), or repeat them in a tree for each output (tree)
+    let form: String = args
+        .opt_value_from_str("--form")
+        .unwrap()
+        .unwrap_or_else(|| "dag".into());
+
+    // The most nodes --form tree writes, since a tree can be exponentially bigger than its dag
+    let max_tree_nodes: usize = args
+        .opt_value_from_str("--max-tree-nodes")
+        .unwrap()
+        .unwrap_or(100_000);
+
+    // Print an op as another, given as alias=op, like bvadd=Add. Can be given more than once
+    let aliases: indexmap::IndexMap<String, String> = args
+        .values_from_str::<_, String>("--alias")
+        .unwrap()
+        .iter()
+        .map(|alias| extract::ops::parse_alias(alias).unwrap())
+        .collect();
+
     let filename: String = args.free_from_str().unwrap();
 
     let rest = args.finish();
@@ -26,9 +86,29 @@ fn main() {
         panic!("Unknown arguments: {:?}", rest);
     }
 
+    let palette = Palette {
+        enabled: match color.as_str() {
+            "auto" => std::io::IsTerminal::is_terminal(&std::io::stdout()),
+            "always" => true,
+            "never" => false,
+            _ => panic!("Unknown --color {}, expected auto, always or never", color),
+        },
+    };
+
+    let mut expansion = match form.as_str() {
+        "dag" => None,
+        "tree" => Some(TreeExpansion {
+            max_nodes: max_tree_nodes,
+            sizes: Default::default(),
+            expanding: Default::default(),
+        }),
+        _ => panic!("Unknown --form {}, expected tree or dag", form),
+    };
+
     let egraph = EGraph::from_json_file(&filename)
         .with_context(|| format!("Failed to parse {filename}"))
         .unwrap();
+    let egraph = extract::ops::apply_aliases(&egraph, &aliases);
 
     let extractor = match extractor_name.as_str() {
         "faster-greedy-dag" => extract::faster_greedy_dag::FasterGreedyDagExtractor.boxed(),
@@ -45,37 +125,40 @@ fn main() {
     // Print the extraction result
     // println!("Extraction Result:");
     // println!("-----------------");
-    
+
     // Build a map from class_id to node_id for easy lookup
-    let class_to_node: std::collections::HashMap<ClassId, NodeId> = result.choices.clone().into_iter().collect();
-    
+    let class_to_node: std::collections::HashMap<ClassId, NodeId> =
+        result.choices.clone().into_iter().collect();
+
     // Map to store variable names for sub-expressions
     let mut expr_vars = std::collections::HashMap::new();
-    
+
     // Function to recursively print assignments for sub-expressions
     fn print_assignments(
         egraph: &EGraph,
         class_to_node: &std::collections::HashMap<ClassId, NodeId>,
         class_id: &ClassId,
         expr_vars: &mut std::collections::HashMap<ClassId, String>,
+        palette: &Palette,
+        expansion: &mut Option<TreeExpansion>,
     ) -> String {
         // Check if we've already processed this class
         if let Some(var_name) = expr_vars.get(class_id) {
             return var_name.clone();
         }
-        
+
         let node_id = match class_to_node.get(class_id) {
             Some(id) => id,
             None => return format!("unknown_{}", class_id),
         };
-        
+
         let node = &egraph[node_id];
-        
+
         // Extract the node name to use as the variable name
         let var_name = if node.children.is_empty() {
             if node.op.starts_with("Var(") && node.op.ends_with(")") {
                 // Extract the variable name from Var("name")
-                let var_name_inner = &node.op[4..node.op.len()-1];
+                let var_name_inner = &node.op[4..node.op.len() - 1];
                 // Remove quotes if present
                 let var_name_clean = var_name_inner.trim_matches('"');
                 var_name_clean.to_string()
@@ -87,153 +170,285 @@ fn main() {
             // For non-leaf nodes, use node_id as name
             format!("{}", node_id)
         };
-        
-        // Process children and print assignments
+
+        let name = palette.var(&var_name);
+
         if node.children.is_empty() {
-            // Leaf node - no need to print assignment for variables
-            if !node.op.starts_with("Var(") {
-                println!("{} = {}", var_name, node.op);
-            }
-        } else {
-            // Process children first to ensure dependencies are handled
-            let mut child_vars = Vec::new();
-            for child in &node.children {
-                let child_class = egraph.nid_to_cid(child);
-                let child_var = print_assignments(egraph, class_to_node, child_class, expr_vars);
-                child_vars.push(child_var);
-            }
-            
-            // Format the assignment based on operation
-            if node.op.starts_with("Add") {
-                if child_vars.len() == 2 {
-                    println!("{} = {} + {}", var_name, child_vars[0], child_vars[1]);
-                } else {
-                    let joined = child_vars.join(" + ");
-                    println!("{} = {}", var_name, joined);
+            let expr = match expansion {
+                // A tree writes leaves in place
+                Some(expansion) => {
+                    expansion.sizes.insert(class_id.clone(), 1);
+                    if node.op.starts_with("Var(") {
+                        palette.var(&var_name)
+                    } else {
+                        palette.constant(&node.op)
+                    }
                 }
-            } else if node.op.starts_with("Not") {
-                if child_vars.len() == 1 {
-                    println!("{} = ~{}", var_name, child_vars[0]);
-                } else {
-                    println!("{} = ~({})", var_name, child_vars.join(", "));
+                None => {
+                    // Leaf node - no need to print assignment for variables
+                    if !node.op.starts_with("Var(") {
+                        println!("{} = {}", name, palette.constant(&node.op));
+                    }
+                    var_name
                 }
-            } else if node.op.starts_with("Or") {
-                if child_vars.len() == 2 {
-                    println!("{} = {} | {}", var_name, child_vars[0], child_vars[1]);
-                } else {
-                    let joined = child_vars.join(" | ");
-                    println!("{} = {}", var_name, joined);
+            };
+            expr_vars.insert(class_id.clone(), expr.clone());
+            return expr;
+        }
+
+        // Expanding a class inside itself would never end
+        if let Some(expansion) = expansion.as_mut() {
+            if !expansion.expanding.insert(class_id.clone()) {
+                eprintln!("Can't print class {} as a tree, it is on a cycle", class_id);
+                std::process::exit(1);
+            }
+        }
+
+        // Process children first to ensure dependencies are handled
+        let mut child_vars = Vec::new();
+        for child in &node.children {
+            let child_class = egraph.nid_to_cid(child);
+            let child_var = print_assignments(
+                egraph,
+                class_to_node,
+                child_class,
+                expr_vars,
+                palette,
+                expansion,
+            );
+            child_vars.push(child_var);
+        }
+
+        let args: Vec<String> = match expansion {
+            // In a tree the children are whole expressions, already colored, and need parentheses unless they are leaves
+            Some(expansion) => {
+                expansion.expanding.remove(class_id);
+                let mut size = 1;
+                for child in &node.children {
+                    size += expansion
+                        .sizes
+                        .get(egraph.nid_to_cid(child))
+                        .copied()
+                        .unwrap_or(1);
                 }
-            } else if node.op.starts_with("And") {
-                if child_vars.len() == 2 {
-                    println!("{} = {} & {}", var_name, child_vars[0], child_vars[1]);
-                } else {
-                    let joined = child_vars.join(" & ");
-                    println!("{} = {}", var_name, joined);
+                if size > expansion.max_nodes {
+                    eprintln!("The tree of class {} has more than {} nodes, raise --max-tree-nodes or print it with --form dag", class_id, expansion.max_nodes);
+                    std::process::exit(1);
                 }
-            } else if node.op.starts_with("Mul") {
-                // Check for Mul operation with a number constant
-                if node.op.contains("Num(") {
-                    // Extract the number from the operation string
-                    if let Some(start) = node.op.find("Num(") {
-                        if let Some(end) = node.op[start..].find(")") {
-                            let num_str = &node.op[start+4..start+end];
-                            println!("{} = {} * {}", var_name, child_vars[0], num_str);
+                expansion.sizes.insert(class_id.clone(), size);
+                node.children
+                    .iter()
+                    .zip(&child_vars)
+                    .map(|(child, expr)| {
+                        let is_leaf = class_to_node
+                            .get(egraph.nid_to_cid(child))
+                            .is_none_or(|n| egraph[n].children.is_empty());
+                        if is_leaf {
+                            expr.clone()
                         } else {
-                            // Fallback if parsing fails
-                            let joined = child_vars.join(" * ");
-                            println!("{} = {}", var_name, joined);
+                            format!("({})", expr)
                         }
+                    })
+                    .collect()
+            }
+            None => child_vars.iter().map(|v| palette.var(v)).collect(),
+        };
+
+        let mut lhs = name;
+        let rhs: String;
+        // Format the assignment based on operation
+        if node.op.starts_with("Add") {
+            if child_vars.len() == 2 {
+                rhs = format!("{} {} {}", args[0], palette.op("+"), args[1]);
+            } else {
+                let joined = args.join(&format!(" {} ", palette.op("+")));
+                rhs = joined;
+            }
+        } else if node.op.starts_with("Not") {
+            if child_vars.len() == 1 {
+                rhs = format!("{}{}", palette.op("~"), args[0]);
+            } else {
+                rhs = format!("{}({})", palette.op("~"), args.join(", "));
+            }
+        } else if node.op.starts_with("Or") {
+            if child_vars.len() == 2 {
+                rhs = format!("{} {} {}", args[0], palette.op("|"), args[1]);
+            } else {
+                let joined = args.join(&format!(" {} ", palette.op("|")));
+                rhs = joined;
+            }
+        } else if node.op.starts_with("And") {
+            if child_vars.len() == 2 {
+                rhs = format!("{} {} {}", args[0], palette.op("&"), args[1]);
+            } else {
+                let joined = args.join(&format!(" {} ", palette.op("&")));
+                rhs = joined;
+            }
+        } else if node.op.starts_with("Mul") {
+            // Check for Mul operation with a number constant
+            if node.op.contains("Num(") {
+                // Extract the number from the operation string
+                if let Some(start) = node.op.find("Num(") {
+                    if let Some(end) = node.op[start..].find(")") {
+                        let num_str = &node.op[start + 4..start + end];
+                        rhs = format!(
+                            "{} {} {}",
+                            args[0],
+                            palette.op("*"),
+                            palette.constant(num_str)
+                        );
                     } else {
                         // Fallback if parsing fails
-                        let joined = child_vars.join(" * ");
-                        println!("{} = {}", var_name, joined);
+                        let joined = args.join(&format!(" {} ", palette.op("*")));
+                        rhs = joined;
                     }
-                } else if child_vars.len() == 2 {
-                    println!("{} = {} * {}", var_name, child_vars[0], child_vars[1]);
-                } else {
-                    let joined = child_vars.join(" * ");
-                    println!("{} = {}", var_name, joined);
-                }
-            } else if node.op.starts_with("Shl") {
-                if child_vars.len() == 2 {
-                    println!("{} = {} << {}", var_name, child_vars[0], child_vars[1]);
                 } else {
-                    // Extract the shift amount
-                    if let Some(amount_start) = node.op.find(',') {
-                        if let Some(end) = node.op[amount_start..].find(")") {
-                            let amount = node.op[amount_start+1..amount_start+end].trim();
-                            println!("{} = {} << {}", var_name, child_vars[0], amount);
-                        } else {
-                            println!("{} = {} << 1", var_name, child_vars[0]);
-                        }
-                    } else {
-                        println!("{} = {} << 1", var_name, child_vars[0]);
-                    }
+                    // Fallback if parsing fails
+                    let joined = args.join(&format!(" {} ", palette.op("*")));
+                    rhs = joined;
                 }
-            } else if node.op.starts_with("Shr") {
-                if child_vars.len() == 2 {
-                    println!("{} = {} >> {}", var_name, child_vars[0], child_vars[1]);
-                } else {
-                    // Extract the shift amount
-                    if let Some(amount_start) = node.op.find(',') {
-                        if let Some(end) = node.op[amount_start..].find(")") {
-                            let amount = node.op[amount_start+1..amount_start+end].trim();
-                            println!("{} = {} >> {}", var_name, child_vars[0], amount);
-                        } else {
-                            println!("{} = {} >> 1", var_name, child_vars[0]);
-                        }
+            } else if child_vars.len() == 2 {
+                rhs = format!("{} {} {}", args[0], palette.op("*"), args[1]);
+            } else {
+                let joined = args.join(&format!(" {} ", palette.op("*")));
+                rhs = joined;
+            }
+        } else if node.op.starts_with("Shl") {
+            if child_vars.len() == 2 {
+                rhs = format!("{} {} {}", args[0], palette.op("<<"), args[1]);
+            } else {
+                // Extract the shift amount
+                if let Some(amount_start) = node.op.find(',') {
+                    if let Some(end) = node.op[amount_start..].find(")") {
+                        let amount = node.op[amount_start + 1..amount_start + end].trim();
+                        rhs = format!(
+                            "{} {} {}",
+                            args[0],
+                            palette.op("<<"),
+                            palette.constant(amount)
+                        );
                     } else {
-                        println!("{} = {} >> 1", var_name, child_vars[0]);
-                    }
-                }
-            } else if node.op.starts_with("MUXAR") {
-                if child_vars.len() == 3 {
-                    let b = &child_vars[0]; // The bit vector
-                    let a = &child_vars[1]; // First value
-                    let c = &child_vars[2]; // Second value
-                    
-                    // Create the expression: (b[0] ? a : c) << 0 + (b[1] ? a : c) << 1 + ... + (b[7] ? a : c) << 7
-                    let mut parts = Vec::new();
-                    for i in 0..8 {
-                        parts.push(format!("({}[{}] ? {} : {}) << {}", b, i, a, c, i));
+                        rhs = format!("{} {} {}", args[0], palette.op("<<"), palette.constant("1"));
                     }
-                    
-                    println!("{} = {}", var_name, parts.join(" + "));
                 } else {
-                    // Fallback for unexpected number of arguments
-                    println!("{} = MUXAR({})", var_name, child_vars.join(", "));
+                    rhs = format!("{} {} {}", args[0], palette.op("<<"), palette.constant("1"));
                 }
-            } else if node.op.starts_with("RootNode") {
-                if let Some(output_name_start) = node.op.find('"') {
-                    if let Some(output_name_end) = node.op[output_name_start+1..].find('"') {
-                        let output_name = &node.op[output_name_start+1..output_name_start+1+output_name_end];
-                        println!("{} = {}", output_name, child_vars[0]);
+            }
+        } else if node.op.starts_with("Shr") {
+            if child_vars.len() == 2 {
+                rhs = format!("{} {} {}", args[0], palette.op(">>"), args[1]);
+            } else {
+                // Extract the shift amount
+                if let Some(amount_start) = node.op.find(',') {
+                    if let Some(end) = node.op[amount_start..].find(")") {
+                        let amount = node.op[amount_start + 1..amount_start + end].trim();
+                        rhs = format!(
+                            "{} {} {}",
+                            args[0],
+                            palette.op(">>"),
+                            palette.constant(amount)
+                        );
                     } else {
-                        println!("{} = {}", var_name, child_vars[0]);
+                        rhs = format!("{} {} {}", args[0], palette.op(">>"), palette.constant("1"));
                     }
                 } else {
-                    println!("{} = {}", var_name, child_vars[0]);
+                    rhs = format!("{} {} {}", args[0], palette.op(">>"), palette.constant("1"));
                 }
+            }
+        } else if node.op.starts_with("MUXAR") {
+            if child_vars.len() == 3 {
+                let b = &args[0]; // The bit vector
+                let a = &args[1]; // First value
+                let c = &args[2]; // Second value
+
+                // Create the expression: (b[0] ? a : c) << 0 + (b[1] ? a : c) << 1 + ... + (b[7] ? a : c) << 7
+                let mut parts = Vec::new();
+                for i in 0..8 {
+                    parts.push(format!(
+                        "({}[{}] {} {} {} {}) {} {}",
+                        b,
+                        palette.constant(&i.to_string()),
+                        palette.op("?"),
+                        a,
+                        palette.op(":"),
+                        c,
+                        palette.op("<<"),
+                        palette.constant(&i.to_string())
+                    ));
+                }
+
+                rhs = parts.join(&format!(" {} ", palette.op("+")));
             } else {
-                if child_vars.is_empty() {
-                    println!("{} = {}", var_name, node.op);
-                } else {
-                    println!("{} = {}({})", var_name, node.op, child_vars.join(", "));
+                // Fallback for unexpected number of arguments
+                rhs = format!("{}({})", palette.op("MUXAR"), args.join(", "));
+            }
+        } else if node.op.starts_with("RootNode") {
+            if let Some(output_name_start) = node.op.find('"') {
+                if let Some(output_name_end) = node.op[output_name_start + 1..].find('"') {
+                    let output_name =
+                        &node.op[output_name_start + 1..output_name_start + 1 + output_name_end];
+                    lhs = palette.var(output_name);
                 }
             }
+            // The output is the whole expression, without parentheses
+            rhs = if expansion.is_some() {
+                child_vars[0].clone()
+            } else {
+                args[0].clone()
+            };
+        } else {
+            if child_vars.is_empty() {
+                rhs = palette.constant(&node.op);
+            } else {
+                // A call's arguments don't need parentheses
+                let call_args = if expansion.is_some() {
+                    &child_vars
+                } else {
+                    &args
+                };
+                rhs = format!("{}({})", palette.op(&node.op), call_args.join(", "));
+            }
         }
-        
-        // Store the variable name for this expression
-        expr_vars.insert(class_id.clone(), var_name.clone());
-        var_name
+
+        let expr = match expansion {
+            // A tree only assigns the outputs, everything else is written in place
+            Some(_) if !node.op.starts_with("RootNode") => rhs,
+            _ => {
+                println!("{} = {}", lhs, rhs);
+                var_name
+            }
+        };
+
+        // Store the variable name (or in a tree, the expression) for this class
+        expr_vars.insert(class_id.clone(), expr.clone());
+        expr
     }
-    
+
     // Print assignments for each root eclass
+    let mut tree_nodes = 0;
     for root_class in &egraph.root_eclasses {
         // println!("Root expression assignments:");
-        let result_var = print_assignments(&egraph, &class_to_node, root_class, &mut expr_vars);
+        let result_var = print_assignments(
+            &egraph,
+            &class_to_node,
+            root_class,
+            &mut expr_vars,
+            &palette,
+            &mut expansion,
+        );
         // println!("output = {}", result_var);
+        if let Some(expansion) = &expansion {
+            // A tree assigns each output, named after its node unless it is a RootNode
+            let node_id = &class_to_node[root_class];
+            if !egraph[node_id].op.starts_with("RootNode") {
+                println!("{} = {}", palette.var(&node_id.to_string()), result_var);
+            }
+            tree_nodes += expansion.sizes[root_class];
+            if tree_nodes > expansion.max_nodes {
+                eprintln!("The trees of the outputs have more than {} nodes, raise --max-tree-nodes or print them with --form dag", expansion.max_nodes);
+                std::process::exit(1);
+            }
+        }
     }
 
     // Print costs
@@ -241,4 +456,4 @@ fn main() {
     let dag = result.dag_cost(&egraph, &egraph.root_eclasses);
     // println!("\nTree cost: {}", tree);
     // println!("DAG cost: {}", dag);
-}
\ No newline at end of file
+}
@@ -1,4 +1,15 @@
+mod certificate;
 mod extract;
+mod objective;
+mod output;
+mod repl;
+mod schema;
+#[cfg(feature = "scripting")]
+mod script;
+mod selftest;
+mod stats;
+mod summary;
+mod transform;
 
 pub use extract::*;
 
@@ -10,11 +21,15 @@ use ordered_float::NotNan;
 use anyhow::Context;
 
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub type Cost = NotNan<f64>;
 pub const INFINITY: Cost = unsafe { NotNan::new_unchecked(std::f64::INFINITY) };
 
+// How long --gap gives the ILP solver, the same as ilp-cbc-timeout.
+#[cfg(feature = "ilp-cbc")]
+const GAP_TIMEOUT_IN_SECONDS: u32 = 10;
+
 #[derive(PartialEq, Eq)]
 enum Optimal {
     Tree,
@@ -22,9 +37,65 @@ enum Optimal {
     Neither,
 }
 
+/// What the extraction has to satisfy besides being cheap. The ILP extractors take these
+/// directly, other extractors can only handle some of them.
+#[derive(Clone, Debug, Default)]
+struct Constraints {
+    maximize: bool,
+    max_depth: Option<usize>,
+    max_distinct_ops: Option<usize>,
+    exclusive_groups: Vec<Vec<String>>,
+    /// The ILP keeps these together, other extractors are checked afterwards.
+    linked_groups: Vec<Vec<NodeId>>,
+    target_shape: Option<extract::shape::Shape>,
+    /// The ILP forces these nodes to be used, other extractors are checked afterwards.
+    cover: Vec<NodeId>,
+    node_budget: Option<usize>,
+    maximize_roots: bool,
+    /// Not constraints, but where the ILP extractors write their model, which reaches them
+    /// the same way.
+    export_lp: Option<PathBuf>,
+    export_mps: Option<PathBuf>,
+}
+
+impl Constraints {
+    fn is_empty(&self) -> bool {
+        !self.maximize
+            && self.max_depth.is_none()
+            && self.max_distinct_ops.is_none()
+            && self.exclusive_groups.is_empty()
+            && self.linked_groups.is_empty()
+            && self.target_shape.is_none()
+            && self.cover.is_empty()
+            && self.node_budget.is_none()
+            && !self.maximize_roots
+            && self.export_lp.is_none()
+            && self.export_mps.is_none()
+    }
+
+    #[cfg(feature = "ilp-cbc")]
+    fn ilp_config(&self) -> extract::ilp_cbc::Config {
+        extract::ilp_cbc::Config {
+            maximize: self.maximize,
+            max_depth: self.max_depth,
+            max_distinct_ops: self.max_distinct_ops,
+            exclusive_groups: self.exclusive_groups.clone(),
+            linked_groups: self.linked_groups.clone(),
+            target_shape: self.target_shape.clone(),
+            cover: self.cover.clone(),
+            node_budget: self.node_budget,
+            maximize_roots: self.maximize_roots,
+            export_lp: self.export_lp.clone(),
+            export_mps: self.export_mps.clone(),
+        }
+    }
+}
+
 struct ExtractorDetail {
     extractor: Box<dyn Extractor>,
     optimal: Optimal,
+    /// Solves the ILP, so it can take the constraints and needs a linear objective.
+    is_ilp: bool,
     use_for_bench: bool,
 }
 
@@ -35,6 +106,7 @@ fn extractors() -> IndexMap<&'static str, ExtractorDetail> {
             ExtractorDetail {
                 extractor: extract::bottom_up::BottomUpExtractor.boxed(),
                 optimal: Optimal::Tree,
+                is_ilp: false,
                 use_for_bench: true,
             },
         ),
@@ -43,6 +115,16 @@ fn extractors() -> IndexMap<&'static str, ExtractorDetail> {
             ExtractorDetail {
                 extractor: extract::faster_bottom_up::FasterBottomUpExtractor.boxed(),
                 optimal: Optimal::Tree,
+                is_ilp: false,
+                use_for_bench: true,
+            },
+        ),
+        (
+            "dp-scc",
+            ExtractorDetail {
+                extractor: extract::dp_scc::DpSccExtractor.boxed(),
+                optimal: Optimal::Tree,
+                is_ilp: false,
                 use_for_bench: true,
             },
         ),
@@ -51,14 +133,61 @@ fn extractors() -> IndexMap<&'static str, ExtractorDetail> {
             ExtractorDetail {
                 extractor: extract::faster_greedy_dag::FasterGreedyDagExtractor.boxed(),
                 optimal: Optimal::Neither,
+                is_ilp: false,
+                use_for_bench: true,
+            },
+        ),
+        (
+            "perturbed-greedy",
+            ExtractorDetail {
+                extractor: extract::perturbed_greedy::PerturbedGreedyExtractor::default().boxed(),
+                optimal: Optimal::Neither,
+                is_ilp: false,
+                use_for_bench: false, // for diverse results rather than the cheapest
+            },
+        ),
+        (
+            "greedy-dag",
+            ExtractorDetail {
+                extractor: extract::greedy_dag::GreedyDagExtractor.boxed(),
+                optimal: Optimal::Neither,
+                is_ilp: false,
+                use_for_bench: false, // faster-greedy-dag makes the same choices
+            },
+        ),
+        (
+            "reuse-decay-greedy",
+            ExtractorDetail {
+                extractor: extract::greedy_dag::ReuseDecayExtractor { decay: 0.0 }.boxed(),
+                optimal: Optimal::Neither,
+                is_ilp: false,
+                use_for_bench: false, // greedy-dag's choices until given a --reuse-decay
+            },
+        ),
+        (
+            "lazy-bottom-up",
+            ExtractorDetail {
+                extractor: extract::lazy_bottom_up::LazyBottomUpExtractor.boxed(),
+                optimal: Optimal::Tree,
+                is_ilp: false,
                 use_for_bench: true,
             },
         ),
+        (
+            "lazy-greedy",
+            ExtractorDetail {
+                extractor: extract::lazy_greedy::LazyGreedyExtractor.boxed(),
+                optimal: Optimal::Neither,
+                is_ilp: false,
+                use_for_bench: false, // same choices as faster-greedy-dag on what's reachable
+            },
+        ),
         /*(
             "global-greedy-dag",
             ExtractorDetail {
                 extractor: extract::global_greedy_dag::GlobalGreedyDagExtractor.boxed(),
                 optimal: Optimal::Neither,
+                is_ilp: false,
                 use_for_bench: true,
             },
         ),*/
@@ -68,6 +197,7 @@ fn extractors() -> IndexMap<&'static str, ExtractorDetail> {
             ExtractorDetail {
                 extractor: extract::ilp_cbc::CbcExtractorWithTimeout::<10>.boxed(),
                 optimal: Optimal::DAG,
+                is_ilp: true,
                 use_for_bench: true,
             },
         ),
@@ -77,15 +207,27 @@ fn extractors() -> IndexMap<&'static str, ExtractorDetail> {
             ExtractorDetail {
                 extractor: extract::ilp_cbc::CbcExtractor.boxed(),
                 optimal: Optimal::DAG,
+                is_ilp: true,
                 use_for_bench: false, // takes >10 hours sometimes
             },
         ),
         #[cfg(feature = "ilp-cbc")]
+        (
+            "contracted-ilp",
+            ExtractorDetail {
+                extractor: extract::contracted_ilp::ContractedIlpExtractor.boxed(),
+                optimal: Optimal::Neither,
+                is_ilp: true,
+                use_for_bench: true,
+            },
+        ),
+        #[cfg(feature = "ilp-cbc")]
         (
             "faster-ilp-cbc-timeout",
             ExtractorDetail {
                 extractor: extract::faster_ilp_cbc::FasterCbcExtractorWithTimeout::<10>.boxed(),
                 optimal: Optimal::DAG,
+                is_ilp: true,
                 use_for_bench: true,
             },
         ),
@@ -95,6 +237,7 @@ fn extractors() -> IndexMap<&'static str, ExtractorDetail> {
             ExtractorDetail {
                 extractor: extract::faster_ilp_cbc::FasterCbcExtractor.boxed(),
                 optimal: Optimal::DAG,
+                is_ilp: true,
                 use_for_bench: true,
             },
         ),
@@ -107,15 +250,17 @@ fn extractors() -> IndexMap<&'static str, ExtractorDetail> {
 fn main() {
     env_logger::init();
 
-    let mut extractors = extractors();
-    extractors.retain(|_, ed| ed.use_for_bench);
-
     let mut args = pico_args::Arguments::from_env();
 
-    let extractor_name: String = args
-        .opt_value_from_str("--extractor")
-        .unwrap()
-        .unwrap_or_else(|| "bottom-up".into());
+    let extractor_arg: Option<String> = args.opt_value_from_str("--extractor").unwrap();
+    let extractor_name = extractor_arg.clone().unwrap_or_else(|| "bottom-up".into());
+
+    // The ones not used for the benchmarks can still be named with --extractor.
+    let mut extractors = extractors();
+    extractors.retain(|name, ed| ed.use_for_bench || *name == extractor_name);
+    let is_ilp = extractors
+        .get(extractor_name.as_str())
+        .is_some_and(|ed| ed.is_ilp);
     if extractor_name == "print" {
         for name in extractors.keys() {
             println!("{}", name);
@@ -123,11 +268,603 @@ fn main() {
         return;
     }
 
+    // Run every compiled-in extractor on a few built-in egraphs and check the results.
+    if args.contains("--selftest") {
+        if !selftest::run() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Load an egraph and explore it with commands read from stdin, see `repl`.
+    let repl: Option<PathBuf> = args.opt_value_from_str("--repl").unwrap();
+    if let Some(path) = &repl {
+        let egraph = EGraph::from_json_file(path)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+            .unwrap();
+        let stdin = std::io::stdin();
+        repl::run(egraph, &extractors, stdin.lock(), std::io::stdout()).unwrap();
+        return;
+    }
+
     let out_filename: PathBuf = args
         .opt_value_from_str("--out")
         .unwrap()
         .unwrap_or_else(|| "out.json".into());
 
+    // Summarize the reports under this directory, per extractor, instead of extracting, and
+    // write the summary to --out.
+    let corpus_summary: Option<PathBuf> = args.opt_value_from_str("--corpus-summary").unwrap();
+    if let Some(dir) = &corpus_summary {
+        let reports = summary::read_reports(dir)
+            .with_context(|| format!("Failed to read the reports in {}", dir.display()))
+            .unwrap();
+        let summary = summary::summarize(&reports).unwrap();
+        println!(
+            "{:24}\tdag ratio median/p90/p99\tmicros median/p90/p99\ttimeouts",
+            "extractor"
+        );
+        for (extractor, stats) in summary["extractors"].as_object().unwrap() {
+            let row = |key: &str| {
+                let p = &stats[key];
+                format!("{}/{}/{}", p["median"], p["p90"], p["p99"])
+            };
+            println!(
+                "{extractor:24}\t{}\t{}\t{}",
+                row("dag_ratio"),
+                row("micros"),
+                stats["timeouts"]
+            );
+        }
+        std::fs::write(
+            &out_filename,
+            serde_json::to_string_pretty(&summary).unwrap() + "\n",
+        )
+        .unwrap();
+        return;
+    }
+
+    let attribute_cost: Option<CostAttribution> =
+        args.opt_value_from_str("--attribute-cost").unwrap();
+
+    // Also write the extraction itself to stdout, in this format.
+    let format: Option<output::OutputFormat> = args.opt_value_from_str("--format").unwrap();
+
+    // With --format verilog, also write a testbench for the module next to the --out file.
+    let verilog_testbench = args.contains("--verilog-testbench");
+    if verilog_testbench && format != Some(output::OutputFormat::Verilog) {
+        panic!("--verilog-testbench requires --format verilog");
+    }
+
+    // Write the extraction's time, costs and size to this Prometheus textfile.
+    let metrics_file: Option<PathBuf> = args.opt_value_from_str("--metrics-file").unwrap();
+
+    // Write a certificate that the extraction is valid, which --verify-certificate checks.
+    let certificate: Option<PathBuf> = args.opt_value_from_str("--certificate").unwrap();
+
+    // Check this certificate against the egraph instead of extracting it.
+    let verify_certificate: Option<PathBuf> =
+        args.opt_value_from_str("--verify-certificate").unwrap();
+
+    // Check the input's JSON against the schema in schema.rs before parsing it, for errors
+    // that say which field is missing or has the wrong type, and that no node id is defined
+    // twice.
+    let strict_input = args.contains("--strict-input");
+
+    // Print metrics about the egraph instead of extracting it.
+    let graph_stats = args.contains("--graph-stats");
+
+    // Print the number and sizes of the weakly connected components of the egraph, instead
+    // of extracting.
+    let components = args.contains("--components");
+
+    // Write the cost of each class reachable from the roots to this CSV file.
+    let heatmap: Option<PathBuf> = args.opt_value_from_str("--heatmap").unwrap();
+
+    // Also report the extraction's dag total of each of these numeric node fields,
+    // e.g. area,delay,power. Nodes without a field count as 0.
+    let report_dims: Vec<String> = args
+        .opt_value_from_str::<_, String>("--report-dims")
+        .unwrap()
+        .map(|dims| dims.split(',').map(String::from).collect())
+        .unwrap_or_default();
+
+    // Also report where the chosen nodes came from, using this node field, e.g. a source
+    // location like "foo.rs:42" that the frontend put on each node.
+    let with_source: Option<String> = args.opt_value_from_str("--with-source").unwrap();
+
+    // Also report the costs of each group of roots that share no classes.
+    let by_component = args.contains("--by-component");
+
+    // Only print the min tree cost of the roots, without extracting.
+    let count_only = args.contains("--count-only");
+
+    // Write the min tree cost of every class, from the bottom-up fixpoint, to this JSON file.
+    // Classes without an acyclic extraction get null.
+    let all_class_costs: Option<PathBuf> = args.opt_value_from_str("--all-class-costs").unwrap();
+
+    // A JSON file of named sets of roots, like {"q1": ["c1", "c2"]}, to extract one at a
+    // time from the same egraph instead of its roots.
+    let queries: Option<PathBuf> = args.opt_value_from_str("--queries").unwrap();
+
+    // Print the min tree cost of each root from one bottom-up pass, and write each root's
+    // choices to the --out file, instead of extracting the roots together.
+    let each_root = args.contains("--each-root");
+
+    // Extract groups of roots that share no classes on separate threads.
+    let parallel = args.contains("--parallel");
+
+    // Write the extractor's choices to this log, or make the choices in it instead of
+    // running an extractor.
+    let record: Option<PathBuf> = args.opt_value_from_str("--record").unwrap();
+    let replay: Option<PathBuf> = args.opt_value_from_str("--replay").unwrap();
+
+    // Comma-separated files from --format report-json, e.g. of different roots of the same
+    // egraph extracted separately, to merge the choices of instead of running an extractor.
+    let merge: Vec<PathBuf> = args
+        .opt_value_from_str::<_, String>("--merge")
+        .unwrap()
+        .map(|files| files.split(',').map(PathBuf::from).collect())
+        .unwrap_or_default();
+    if replay.is_some() && !merge.is_empty() {
+        panic!("--replay and --merge can't be used together");
+    }
+
+    // Write each choice bottom-up or greedy-dag makes as it runs, as its iteration, class,
+    // node and cost, to this file.
+    let choice_trace: Option<PathBuf> = args.opt_value_from_str("--choice-trace").unwrap();
+    if choice_trace.is_some() && !TRACED_EXTRACTORS.contains(&extractor_name.as_str()) {
+        panic!("--choice-trace only applies to bottom-up and greedy-dag");
+    }
+
+    // Run bottom-up over the nodes as they're stored, keeping one cost per class and no
+    // class map, for egraphs too big to cost the usual way.
+    let low_memory = args.contains("--low-memory");
+    if low_memory && extractor_name != "bottom-up" {
+        panic!("--low-memory only applies to bottom-up");
+    }
+
+    // Check faster-greedy-dag's running costs against the dag cost of what it chose.
+    let strict = args.contains("--strict");
+
+    // Also report the extraction's throughput, in egraph nodes and chosen nodes per second.
+    let benchmark = args.contains("--benchmark");
+
+    // Run the extractor twice and fail unless both runs make the same choices in the same
+    // order, with the same costs to the bit.
+    let check_determinism = args.contains("--check-determinism");
+    if check_determinism && record.is_some() {
+        panic!("--check-determinism would record the choices twice with --record");
+    }
+
+    // How many of the classes perturbed-greedy uses get a random other node, and the seed
+    // that picks them.
+    let perturb_fraction: Option<f64> = args.opt_value_from_str("--perturb-fraction").unwrap();
+    let seed: Option<u64> = args.opt_value_from_str("--seed").unwrap();
+    if perturb_fraction.is_some() || seed.is_some() {
+        if extractor_name != "perturbed-greedy" {
+            panic!("--perturb-fraction and --seed only apply to perturbed-greedy");
+        }
+        if perturb_fraction.is_some_and(|f| !(0.0..=1.0).contains(&f)) {
+            panic!("--perturb-fraction must be between 0 and 1");
+        }
+        let default = extract::perturbed_greedy::PerturbedGreedyExtractor::default();
+        extractors["perturbed-greedy"].extractor =
+            extract::perturbed_greedy::PerturbedGreedyExtractor {
+                fraction: perturb_fraction.unwrap_or(default.fraction),
+                seed: seed.unwrap_or(default.seed),
+            }
+            .boxed();
+    }
+
+    // Make each use of a node after the first cost this fraction of its cost, for resources
+    // that get cheaper per use the more they're shared. Only reuse-decay-greedy takes it into
+    // account, and the report gives the cost with it.
+    let reuse_decay: Option<f64> = args.opt_value_from_str("--reuse-decay").unwrap();
+    if let Some(decay) = reuse_decay {
+        if extractor_name != "reuse-decay-greedy" || strict {
+            panic!("--reuse-decay only applies to reuse-decay-greedy, without --strict");
+        }
+        if !(0.0..=1.0).contains(&decay) {
+            panic!("--reuse-decay must be between 0 and 1");
+        }
+        extractors["reuse-decay-greedy"].extractor =
+            extract::greedy_dag::ReuseDecayExtractor { decay }.boxed();
+    }
+
+    // Report how far the extraction's dag cost is above the ILP's optimum, as a fraction of
+    // it, or null if the ILP can't tell in time.
+    let gap = args.contains("--gap");
+
+    // Report the cheapest node each class didn't choose, and how much more it costs there.
+    let alternatives = args.contains("--alternatives");
+
+    // Compare a heuristic extraction with the LP relaxation of the ILP, a lower bound that
+    // doesn't need the integer solve. The report gets the bound and the gap to it.
+    let with_lp_bound = args.contains("--with-lp-bound");
+    if with_lp_bound && is_ilp {
+        panic!("--with-lp-bound is for the extractors that don't solve the ILP");
+    }
+
+    // Exit non-zero, after writing the report, if the dag cost of the extraction is more
+    // than this.
+    let max_acceptable_cost: Option<f64> =
+        args.opt_value_from_str("--max-acceptable-cost").unwrap();
+    if max_acceptable_cost.is_some_and(f64::is_nan) {
+        panic!("--max-acceptable-cost can't be NaN");
+    }
+
+    // Find the most expensive extraction, for stress testing what consumes it.
+    let maximize = args.contains("--maximize");
+
+    // The most nodes allowed on any path from a root to a leaf, however long --delay-table
+    // says they take.
+    let max_depth: Option<usize> = args.opt_value_from_str("--max-depth").unwrap();
+
+    // Rerun the ILP with a doubled timeout, from the first number of seconds up to the
+    // second, until it proves its extraction optimal.
+    let escalate_timeout: Option<(u32, u32)> = args
+        .opt_value_from_str::<_, String>("--escalate-timeout")
+        .unwrap()
+        .map(|timeouts| {
+            let (initial, max) = timeouts
+                .split_once(',')
+                .expect("--escalate-timeout takes initial,max seconds");
+            (initial.parse().unwrap(), max.parse().unwrap())
+        });
+    if escalate_timeout.is_some_and(|(initial, max)| initial == 0 || initial > max) {
+        panic!("--escalate-timeout needs 0 < initial <= max");
+    }
+
+    // Comma-separated timeouts in seconds, like 1,5,10,30, to solve the ILP with each and
+    // compare the costs it reaches instead of extracting once.
+    let ilp_timeout_sweep: Vec<u32> = args
+        .opt_value_from_str::<_, String>("--ilp-timeout-sweep")
+        .unwrap()
+        .map(|timeouts| {
+            timeouts
+                .split(',')
+                .map(|t| t.trim().parse().expect("--ilp-timeout-sweep takes seconds"))
+                .collect()
+        })
+        .unwrap_or_default();
+    if ilp_timeout_sweep.contains(&0) {
+        panic!("--ilp-timeout-sweep needs timeouts of at least 1s");
+    }
+    if !ilp_timeout_sweep.is_empty()
+        && (escalate_timeout.is_some()
+            || !matches!(extractor_name.as_str(), "ilp-cbc-timeout" | "ilp-cbc"))
+    {
+        panic!("--ilp-timeout-sweep only applies to ilp-cbc and ilp-cbc-timeout, without --escalate-timeout");
+    }
+
+    // Extract with faster-greedy-dag and faster-bottom-up instead of the --extractor, and
+    // only write a report if their dag costs differ by more than the --screen-threshold
+    // ratio (1.1 by default), to find the egraphs worth an exact solve.
+    let screen = args.contains("--screen");
+    if screen && extractor_arg.is_some() {
+        panic!("--screen extracts with faster-greedy-dag and faster-bottom-up, not an --extractor");
+    }
+    let screen_threshold: Option<f64> = args.opt_value_from_str("--screen-threshold").unwrap();
+    if screen_threshold.is_some() && !screen {
+        panic!("--screen-threshold only applies with --screen");
+    }
+    let screen_threshold = screen_threshold.unwrap_or(1.1);
+    if screen_threshold.is_nan() || screen_threshold < 1.0 {
+        panic!("--screen-threshold needs a ratio of at least 1");
+    }
+
+    // Print the number of variables and constraints in ilp-cbc's ILP, without solving it.
+    let ilp_estimate = args.contains("--ilp-estimate");
+
+    // Write the ILP that ilp-cbc solves, in LP or MPS format, for reading or another solver.
+    let export_lp: Option<PathBuf> = args.opt_value_from_str("--export-lp").unwrap();
+    let export_mps: Option<PathBuf> = args.opt_value_from_str("--export-mps").unwrap();
+    for (path, extension) in [(&export_lp, "lp"), (&export_mps, "mps")] {
+        if path
+            .as_ref()
+            .is_some_and(|p| p.extension() != Some(extension.as_ref()))
+        {
+            panic!("--export-{extension} needs a file ending in .{extension}");
+        }
+    }
+    if (export_lp.is_some() || export_mps.is_some())
+        && !matches!(extractor_name.as_str(), "ilp-cbc-timeout" | "ilp-cbc")
+    {
+        panic!("--export-lp and --export-mps only apply to ilp-cbc and ilp-cbc-timeout");
+    }
+
+    // Remove the nodes a sibling dominates, costing no more with children in fewer classes,
+    // to shrink the ILP without changing its optimum.
+    let prune_dominated = args.contains("--prune-dominated");
+    if prune_dominated && !is_ilp {
+        panic!("--prune-dominated only applies to the ILP extractors");
+    }
+
+    // The most different ops the extraction may use, only for the ILP extractors.
+    let max_distinct_ops: Option<usize> = args.opt_value_from_str("--max-distinct-ops").unwrap();
+
+    // Comma-separated ops of which the extraction may use at most one, like ops competing for
+    // the same resource, only for the ILP extractors. Can be given more than once.
+    let exclusive_groups: Vec<Vec<String>> = args
+        .values_from_str::<_, String>("--exclusive-group")
+        .unwrap()
+        .into_iter()
+        .map(|group| {
+            let ops: Vec<String> = group.split(',').map(|op| op.trim().to_string()).collect();
+            if ops.len() < 2 || ops.iter().any(String::is_empty) {
+                panic!("--exclusive-group needs at least two ops, like Mul_a,Mul_b: {group}");
+            }
+            ops
+        })
+        .collect();
+
+    // Comma-separated nodes the extraction has to use all or none of, like the parts of a
+    // fused op spread over several classes, only for the ILP extractors. Can be given more
+    // than once.
+    let linked_groups: Vec<Vec<NodeId>> = args
+        .values_from_str::<_, String>("--linked-group")
+        .unwrap()
+        .into_iter()
+        .map(|group| {
+            let nodes: Vec<&str> = group.split(',').map(str::trim).collect();
+            if nodes.len() < 2 || nodes.iter().any(|n| n.is_empty()) {
+                panic!("--linked-group needs at least two nodes, like n1,n2: {group}");
+            }
+            nodes.into_iter().map(NodeId::from).collect()
+        })
+        .collect();
+
+    // The structure each root's extraction must have, like "(Add ? (Mul ? ?))" where ? is
+    // anything, only for the ILP extractors.
+    let target_shape: Option<extract::shape::Shape> =
+        args.opt_value_from_str("--target-shape").unwrap();
+
+    // A file listing nodes, one per line, that the extraction has to use.
+    let cover: Vec<NodeId> = args
+        .opt_value_from_str::<_, PathBuf>("--cover")
+        .unwrap()
+        .map(|path| {
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))
+                .unwrap()
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(NodeId::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // The most nodes the extraction may use.
+    let node_budget: Option<usize> = args.opt_value_from_str("--node-budget").unwrap();
+
+    // Extract as many roots as fit in the --node-budget instead of failing when they all
+    // don't, and report the roots that were dropped.
+    let maximize_roots = args.contains("--maximize-roots");
+    if maximize_roots && node_budget.is_none() {
+        panic!("--maximize-roots requires --node-budget");
+    }
+    if maximize_roots && maximize {
+        panic!("--maximize-roots can't be used with --maximize");
+    }
+
+    // Allow cycles through nodes with this op, like a register in a sequential circuit,
+    // whose children are its next state rather than what its value is computed from.
+    let latch_op: Option<String> = args.opt_value_from_str("--latch-op").unwrap();
+    if latch_op.is_some()
+        && (escalate_timeout.is_some() || low_memory || choice_trace.is_some() || strict)
+    {
+        panic!("--latch-op can't be used with --escalate-timeout, --low-memory, --choice-trace or --strict");
+    }
+
+    let constraints = Constraints {
+        maximize,
+        max_depth,
+        max_distinct_ops,
+        exclusive_groups,
+        linked_groups,
+        target_shape,
+        cover,
+        node_budget,
+        maximize_roots,
+        export_lp,
+        export_mps,
+    };
+    if screen && !constraints.is_empty() {
+        panic!("--screen compares the heuristics without constraints");
+    }
+    // A dominated node can still be the one with the most cost, the only one with its op, or
+    // one that has to be covered.
+    if prune_dominated
+        && (constraints.maximize
+            || constraints.max_distinct_ops.is_some()
+            || !constraints.exclusive_groups.is_empty()
+            || !constraints.linked_groups.is_empty()
+            || constraints.target_shape.is_some()
+            || !constraints.cover.is_empty())
+    {
+        panic!(
+            "--prune-dominated can't be used with --maximize, --max-distinct-ops, --exclusive-group, --linked-group, --target-shape or --cover"
+        );
+    }
+
+    // How to choose between equally cheap nodes, lowest-id (the default) or input order.
+    let tie_break: transform::TieBreak = args
+        .opt_value_from_str("--tie-break")
+        .unwrap()
+        .unwrap_or_default();
+
+    // Ops to prefer among equally cheap nodes, like Shl=2 to prefer shifts, matching the whole
+    // op, its name, or a prefix like Mul*. A node matching several keys takes the highest
+    // priority, and nodes matching none have priority 0. Ties go to the highest priority,
+    // then for the extractors that minimize tree cost to the fewest nodes in the dag, then
+    // as --tie-break says. Can be given more than once.
+    let tie_break_keys: Vec<transform::TieBreakKey> =
+        args.values_from_str("--tie-break-key").unwrap();
+
+    // Which of several equally cheap nodes the extractors that minimize tree cost choose,
+    // after the ones with the fewest nodes in their dag: the first (the default) or last in
+    // their class, or the one with the smallest id. The others keep the node they found.
+    let tie_fit: TieFit = args
+        .opt_value_from_str("--tie-fit")
+        .unwrap()
+        .unwrap_or_default();
+    if !tie_break_keys.is_empty() && tie_fit != TieFit::First {
+        panic!("--tie-break-key orders the nodes for --tie-fit first, the others would ignore it");
+    }
+
+    // Only extract classes at most this many hops from a root.
+    let max_distance: Option<usize> = args.opt_value_from_str("--max-distance").unwrap();
+
+    // Ops whose children can be reordered, e.g. Add,Mul,And,Or.
+    let commutative: Vec<String> = args
+        .opt_value_from_str::<_, String>("--canon-commutative")
+        .unwrap()
+        .map(|ops| ops.split(',').map(String::from).collect())
+        .unwrap_or_default();
+
+    // Remove this percentage of the most expensive nodes before extracting.
+    let exclude_top_cost_percent: Option<f64> = args
+        .opt_value_from_str("--exclude-top-cost-percent")
+        .unwrap();
+    if exclude_top_cost_percent.is_some_and(|percent| !(0.0..=100.0).contains(&percent)) {
+        panic!("--exclude-top-cost-percent must be between 0 and 100");
+    }
+
+    // Comma-separated ops the extraction may use, like the cells of a technology library.
+    // Nodes with other ops are removed, except leaves.
+    let allowed_ops: Vec<String> = args
+        .opt_value_from_str::<_, String>("--allowed-ops")
+        .unwrap()
+        .map(|ops| ops.split(',').map(|op| op.trim().to_string()).collect())
+        .unwrap_or_default();
+
+    // Extract with each node's cost replaced by this expression over its metrics,
+    // e.g. "cost + 0.1*depth".
+    let objective: Option<objective::Objective> = args.opt_value_from_str("--objective").unwrap();
+
+    // Comma-separated cost models, sum, count, latency or an objective, to extract under
+    // each in turn and compare in a table instead of extracting once.
+    let cost_models: Vec<(String, objective::CostModelSpec)> = args
+        .opt_value_from_str::<_, String>("--cost-model")
+        .unwrap()
+        .map(|models| {
+            models
+                .split(',')
+                .map(|name| (name.trim().to_string(), name.parse().unwrap()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if !cost_models.is_empty() && (objective.is_some() || queries.is_some()) {
+        panic!("--cost-model can't be used with --objective or --queries");
+    }
+
+    // A JSON file from node id to cost, replacing the costs in the egraph's file. Nodes that
+    // aren't in it keep their cost from the egraph, which then has to give one.
+    let costs: Option<String> = args.opt_value_from_str("--costs").unwrap();
+
+    // A JSON file giving the cost of some ops, as a number or an expression like
+    // "ceil(log2(n))" over the node's metrics, where n is its number of children. Keys are
+    // whole ops, prefixes ending in * like "Mul*", or * for the rest; the most specific wins.
+    let cost_table: Option<String> = args.opt_value_from_str("--cost-table").unwrap();
+
+    // A Rhai script defining cost(op, num_children, children_ops), which gives the cost of
+    // every node. Needs the scripting feature.
+    let cost_script: Option<PathBuf> = args.opt_value_from_str("--cost-script").unwrap();
+
+    // The node field holding a constant's literal value, like "value": 3. Nodes with it cost
+    // nothing and are written as that literal, whatever their op.
+    let const_field: Option<String> = args.opt_value_from_str("--const-field").unwrap();
+
+    // A JSON file giving the delay of some ops, in the same form as --cost-table, to also
+    // report the extraction's critical path. Other ops have a delay of 1. The delays are
+    // only reported: they don't change what's extracted, and --max-depth still counts nodes.
+    let delay_table: Option<String> = args.opt_value_from_str("--delay-table").unwrap();
+
+    // Add this weight times each node's iteration field to its cost while extracting.
+    let iteration_bias: Option<f64> = args.opt_value_from_str("--iteration-bias").unwrap();
+
+    // Add this weight divided by the number of nodes with the same op to each node's cost
+    // while extracting, to favor the ops the egraph has many of.
+    let rarity_penalty: Option<f64> = args.opt_value_from_str("--rarity-penalty").unwrap();
+    if rarity_penalty.is_some_and(|lambda| lambda < 0.0 || lambda.is_nan()) {
+        panic!("--rarity-penalty can't be negative");
+    }
+
+    // A root's weight as class=weight, multiplying the cost of everything it can use while
+    // extracting, so heavier roots win what they share with the others. Other roots have a
+    // weight of 1. Can be given more than once.
+    let root_weights: IndexMap<ClassId, f64> = args
+        .values_from_str::<_, String>("--root-weight")
+        .unwrap()
+        .into_iter()
+        .map(|weight| {
+            let (class_id, weight) = weight
+                .split_once('=')
+                .expect("--root-weight takes class=weight");
+            (ClassId::from(class_id), weight.parse().unwrap())
+        })
+        .collect();
+
+    // Extract just this root class instead of all of them. bottom-up then only costs the
+    // classes it can reach, as lazy-bottom-up does.
+    let root: Option<ClassId> = args
+        .opt_value_from_str::<_, String>("--root")
+        .unwrap()
+        .map(ClassId::from);
+    if root.is_some() && extractor_name == "bottom-up" {
+        log::info!("Extracting the one root with lazy-bottom-up");
+        extractors["bottom-up"].extractor = extract::lazy_bottom_up::LazyBottomUpExtractor.boxed();
+    }
+
+    // Keep only the first of roots that are repeated, so each is extracted and written out
+    // once.
+    let dedup_roots = args.contains("--dedup-roots");
+
+    // Treat an op as another, given as alias=op, like bvadd=Add, before anything looks at
+    // the ops. Can be given more than once.
+    let aliases: IndexMap<String, String> = args
+        .values_from_str::<_, String>("--alias")
+        .unwrap()
+        .iter()
+        .map(|alias| extract::ops::parse_alias(alias).unwrap())
+        .collect();
+
+    // Classes the extraction can't use, even indirectly. Can be given more than once.
+    let exclude_classes: Vec<ClassId> = args
+        .values_from_str::<_, String>("--exclude-class")
+        .unwrap()
+        .into_iter()
+        .map(ClassId::from)
+        .collect();
+
+    // Classes whose value is already computed, so they cost nothing to use.
+    let available: Vec<ClassId> = args
+        .opt_value_from_str::<_, String>("--available")
+        .unwrap()
+        .map(|classes| classes.split(',').map(ClassId::from).collect())
+        .unwrap_or_default();
+
+    // Comma-separated nodes the extraction has to choose, each in its own class. Fails if
+    // that leaves a root without an extraction.
+    let must_use: Vec<NodeId> = args
+        .opt_value_from_str::<_, String>("--must-use")
+        .unwrap()
+        .map(|nodes| nodes.split(',').map(NodeId::from).collect())
+        .unwrap_or_default();
+
+    // A command to check the extraction against a reference expression. It is run with
+    // the extracted s-expression file and the reference file appended as arguments,
+    // and a non-zero exit status means they are not equivalent.
+    let verify_command: Option<String> = args
+        .opt_value_from_str("--extract-then-verify-equivalence")
+        .unwrap();
+    let reference: Option<PathBuf> = args.opt_value_from_str("--reference").unwrap();
+    if verify_command.is_some() && reference.is_none() {
+        panic!("--extract-then-verify-equivalence requires --reference");
+    }
+
     let filename: String = args.free_from_str().unwrap();
 
     let rest = args.finish();
@@ -135,38 +872,1454 @@ fn main() {
         panic!("Unknown arguments: {:?}", rest);
     }
 
-    let mut out_file = std::fs::File::create(out_filename).unwrap();
+    // Parsing keeps one of a node id's definitions, so the text is checked first.
+    if strict_input {
+        let json = std::fs::read_to_string(&filename)
+            .with_context(|| format!("Failed to read {filename}"))
+            .unwrap();
+        let duplicates = schema::duplicate_node_ids(&json);
+        if !duplicates.is_empty() {
+            eprintln!("Invalid egraph {filename}: nodes {duplicates:?} are defined more than once");
+            std::process::exit(1);
+        }
+        let json: serde_json::Value = serde_json::from_str(&json)
+            .with_context(|| format!("{filename} isn't JSON"))
+            .unwrap();
+        if let Err(e) = schema::validate(&json) {
+            eprintln!("Invalid egraph {filename}: {e}");
+            std::process::exit(1);
+        }
+    }
 
     let egraph = EGraph::from_json_file(&filename)
         .with_context(|| format!("Failed to parse {filename}"))
         .unwrap();
+    let egraph = if aliases.is_empty() {
+        egraph
+    } else {
+        extract::ops::apply_aliases(&egraph, &aliases)
+    };
+
+    let mut roots = indexmap::IndexSet::new();
+    let repeated: Vec<String> = egraph
+        .root_eclasses
+        .iter()
+        .filter(|root| !roots.insert(*root))
+        .map(|root| root.to_string())
+        .collect();
+    let egraph = if repeated.is_empty() {
+        egraph
+    } else if dedup_roots {
+        log::warn!("Collapsed the repeated roots {}", repeated.join(", "));
+        let roots = roots.into_iter().cloned().collect();
+        let mut egraph = egraph;
+        egraph.root_eclasses = roots;
+        egraph
+    } else {
+        log::warn!(
+            "Roots {} are repeated, --dedup-roots collapses them",
+            repeated.join(", ")
+        );
+        egraph
+    };
+
+    let egraph = match root {
+        Some(root) => {
+            if !egraph.classes().contains_key(&root) {
+                panic!("--root {root} is not a class of the egraph");
+            }
+            let mut egraph = egraph;
+            egraph.root_eclasses = vec![root];
+            egraph
+        }
+        None => egraph,
+    };
+
+    if let Some(path) = &verify_certificate {
+        let json = std::fs::read_to_string(path).unwrap();
+        let cert: serde_json::Value = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+            .unwrap();
+        if let Err(e) = certificate::verify_certificate(&egraph, &cert) {
+            eprintln!("Invalid certificate {}: {e}", path.display());
+            std::process::exit(1);
+        }
+        println!("{filename:40}\tcertificate valid");
+        return;
+    }
+
+    if graph_stats {
+        let stats = stats::EGraphStats::compute(&egraph);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&stats.to_json()).unwrap()
+        );
+        return;
+    }
+
+    if components {
+        let sizes = stats::weak_components(&egraph);
+        let json = serde_json::json!({
+            "count": sizes.len(),
+            "components": sizes
+                .iter()
+                .map(|(classes, nodes)| serde_json::json!({"classes": classes, "nodes": nodes}))
+                .collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+        return;
+    }
+
+    let egraph = match &costs {
+        Some(costs) => {
+            let costs = transform::read_node_costs(costs)
+                .with_context(|| format!("Failed to read the costs {costs}"))
+                .unwrap();
+            let embedded = transform::read_node_field(&filename, "cost")
+                .with_context(|| format!("Failed to read the node costs of {filename}"))
+                .unwrap();
+            let embedded = embedded.into_keys().collect();
+            transform::cost_by_node(&egraph, &costs, &embedded).unwrap_or_else(|e| {
+                eprintln!("Can't apply the costs: {e}");
+                std::process::exit(1);
+            })
+        }
+        None => egraph,
+    };
+    let egraph = match &cost_table {
+        Some(cost_table) => {
+            let table = objective::read_cost_table(cost_table)
+                .with_context(|| format!("Failed to read the cost table {cost_table}"))
+                .unwrap();
+            transform::cost_by_table(&egraph, &table).unwrap()
+        }
+        None => egraph,
+    };
+    let egraph = match &cost_script {
+        Some(path) => cost_by_script(&egraph, path),
+        None => egraph,
+    };
+    let egraph = match &const_field {
+        Some(field) => {
+            let values = transform::read_node_values(&filename, field)
+                .with_context(|| format!("Failed to read the node {field}s of {filename}"))
+                .unwrap();
+            transform::mark_constants(&egraph, &values).unwrap_or_else(|e| {
+                eprintln!("Can't mark the constants: {e}");
+                std::process::exit(1);
+            })
+        }
+        None => egraph,
+    };
+    let egraph = match tie_break {
+        transform::TieBreak::LowestId => transform::sort_by_node_id(&egraph),
+        transform::TieBreak::Input => egraph,
+    };
+    let egraph = if tie_break_keys.is_empty() {
+        egraph
+    } else {
+        transform::sort_by_tie_break_keys(&egraph, &tie_break_keys)
+    };
+    let egraph = match max_distance {
+        Some(max_distance) => transform::within_distance(&egraph, max_distance),
+        None => egraph,
+    };
+    let egraph = if commutative.is_empty() {
+        egraph
+    } else {
+        transform::canonicalize_commutative(&egraph, &commutative)
+    };
+    let mut exclusion = None;
+    let egraph = if let Some(percent) = exclude_top_cost_percent {
+        let excluded = transform::exclude_top_cost(&egraph, percent);
+        let removed_nodes = egraph.nodes.len() - excluded.nodes.len();
+        let unextractable =
+            transform::extractable_classes(&egraph) - transform::extractable_classes(&excluded);
+        log::info!(
+            "Excluding the top {percent}% cost nodes removed {removed_nodes} nodes, \
+             {unextractable} classes became unextractable"
+        );
+        exclusion = Some((removed_nodes, unextractable));
+        excluded
+    } else {
+        egraph
+    };
+    let mut disallowed = vec![];
+    let egraph = if allowed_ops.is_empty() {
+        egraph
+    } else {
+        let allowed = transform::allow_ops(&egraph, &allowed_ops);
+        disallowed = transform::newly_unextractable(&egraph, &allowed);
+        log::info!(
+            "Allowing only {} removed {} nodes, {} classes became unextractable",
+            allowed_ops.join(","),
+            egraph.nodes.len() - allowed.nodes.len(),
+            disallowed.len()
+        );
+        let roots: Vec<&ClassId> = allowed
+            .root_eclasses
+            .iter()
+            .filter(|root| disallowed.contains(root))
+            .collect();
+        if !roots.is_empty() {
+            eprintln!(
+                "Roots {roots:?} can't be built from the allowed ops, which leave {} classes \
+                 unextractable",
+                disallowed.len()
+            );
+            std::process::exit(1);
+        }
+        allowed
+    };
+    let egraph = if exclude_classes.is_empty() {
+        egraph
+    } else {
+        transform::exclude_classes(&egraph, &exclude_classes).unwrap_or_else(|e| {
+            eprintln!("Can't exclude the classes: {e}");
+            std::process::exit(1);
+        })
+    };
+    let egraph = if available.is_empty() {
+        egraph
+    } else {
+        transform::make_available(&egraph, &available).unwrap()
+    };
+    let egraph = if must_use.is_empty() {
+        egraph
+    } else {
+        transform::must_use(&egraph, &must_use).unwrap()
+    };
+    // So an extraction that uses the class of a node to cover has to choose it.
+    let egraph = if constraints.cover.is_empty() {
+        egraph
+    } else {
+        transform::must_use(&egraph, &constraints.cover).unwrap_or_else(|e| {
+            eprintln!("Can't cover all the nodes: {e}");
+            std::process::exit(1);
+        })
+    };
+
+    for group in &constraints.linked_groups {
+        if let Err(e) = transform::check_linked_group(&egraph, group) {
+            eprintln!("Can't link {group:?}: {e}");
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &all_class_costs {
+        let costs = extract::bottom_up::min_tree_costs(&egraph);
+        let json: serde_json::Map<String, serde_json::Value> = egraph
+            .classes()
+            .keys()
+            .map(|class_id| {
+                let cost = costs.get(class_id).map(|c| c.into_inner());
+                (class_id.to_string(), cost.into())
+            })
+            .collect();
+        std::fs::write(path, serde_json::to_string_pretty(&json).unwrap() + "\n").unwrap();
+        log::info!(
+            "Wrote the min tree costs of {} classes to {}",
+            costs.len(),
+            path.display()
+        );
+    }
+
+    if count_only {
+        let costs = extract::bottom_up::min_tree_costs(&egraph);
+        let unextractable: Vec<&ClassId> = egraph
+            .root_eclasses
+            .iter()
+            .filter(|root| !costs.contains_key(*root))
+            .collect();
+        if !unextractable.is_empty() {
+            eprintln!("Roots {unextractable:?} can't be extracted");
+            std::process::exit(1);
+        }
+        let tree: Cost = egraph.root_eclasses.iter().map(|root| costs[root]).sum();
+        println!("{filename:40}\t{tree:5}");
+        return;
+    }
+
+    if ilp_estimate {
+        let (variables, constraints) = estimate_ilp(&egraph, &constraints);
+        println!("{filename:40}\tvariables: {variables}\tconstraints: {constraints}");
+        return;
+    }
+
+    let mut out_file = std::fs::File::create(&out_filename).unwrap();
+
+    if each_root {
+        let result = extract::bottom_up::BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+        let mut roots = vec![];
+        for root in &egraph.root_eclasses {
+            if !result.choices.contains_key(root) {
+                eprintln!("Root {root} can't be extracted");
+                std::process::exit(1);
+            }
+            let tree = result.tree_cost(&egraph, std::slice::from_ref(root));
+            println!("{root:40}\t{tree:5}");
+            let choices: serde_json::Map<String, serde_json::Value> = result
+                .restrict(&egraph, std::slice::from_ref(root))
+                .choices
+                .iter()
+                .map(|(class_id, node_id)| (class_id.to_string(), node_id.to_string().into()))
+                .collect();
+            roots.push(serde_json::json!({
+                "root": root.to_string(),
+                "tree": tree.into_inner(),
+                "choices": choices,
+            }));
+        }
+        let report = serde_json::json!({
+            "name": filename,
+            "extractor": "bottom-up",
+            "roots": roots,
+        });
+        writeln!(
+            out_file,
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap()
+        )
+        .unwrap();
+        return;
+    }
 
     let ed = extractors
         .get(extractor_name.as_str())
         .with_context(|| format!("Unknown extractor: {extractor_name}"))
         .unwrap();
 
+    let constrained = constrained_extractor(&extractor_name, &constraints);
+    let replayed = replay.map(|path| {
+        let log = std::fs::read_to_string(&path).unwrap();
+        extract::recording::ReplayExtractor::from_log(&log)
+            .with_context(|| format!("Failed to read the choices in {}", path.display()))
+            .unwrap()
+            .boxed()
+    });
+    let merged = (!merge.is_empty()).then(|| {
+        let results: Vec<ExtractionResult> = merge
+            .iter()
+            .map(|path| {
+                let json = std::fs::read_to_string(path).unwrap();
+                serde_json::from_str(&json)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|json| output::report_json::read_choices(&json))
+                    .with_context(|| format!("Failed to read the choices in {}", path.display()))
+                    .unwrap()
+            })
+            .collect();
+        let merged = ExtractionResult::merge(&results).unwrap_or_else(|e| {
+            eprintln!("Can't merge {merge:?}: {e}");
+            std::process::exit(1);
+        });
+        extract::recording::ReplayExtractor {
+            choices: merged.choices.into_iter().collect(),
+        }
+        .boxed()
+    });
+    // Whether the choices come from somewhere other than the selected extractor.
+    let substituted = replayed.is_some() || merged.is_some() || constrained.is_some();
+    let extractor = replayed
+        .as_ref()
+        .or(merged.as_ref())
+        .or(constrained.as_ref())
+        .unwrap_or(&ed.extractor);
+    let recording = record.map(|path| extract::recording::RecordingExtractor {
+        inner: extractor.as_ref(),
+        path,
+    });
+
+    if objective.as_ref().is_some_and(|o| !o.is_linear()) && is_ilp {
+        panic!(
+            "The ILP extractors need a linear --objective, without products of metrics or dividing by a metric"
+        );
+    }
+
+    // The objective, the bias, the rarity penalty and the root weights only steer the
+    // extraction, its cost is reported without them.
+    let mut weighted = objective
+        .as_ref()
+        .map(|objective| transform::weight_by_objective(&egraph, objective).unwrap());
+    if let Some(weight) = iteration_bias {
+        let iterations = transform::read_node_field(&filename, transform::ITERATION_FIELD)
+            .with_context(|| format!("Failed to read the node iterations of {filename}"))
+            .unwrap();
+        let base = weighted.as_ref().unwrap_or(&egraph);
+        weighted = Some(transform::bias_by_iteration(base, &iterations, weight));
+    }
+    if let Some(lambda) = rarity_penalty {
+        let base = weighted.as_ref().unwrap_or(&egraph);
+        weighted = Some(transform::penalize_rare_ops(base, lambda));
+    }
+    if !root_weights.is_empty() {
+        let base = weighted.as_ref().unwrap_or(&egraph);
+        weighted = Some(transform::weight_by_roots(base, &root_weights).unwrap());
+    }
+    // After weighting, as dominance depends on the costs the extractor sees.
+    let pruned = prune_dominated.then(|| {
+        let base = weighted.as_ref().unwrap_or(&egraph);
+        let pruned = transform::prune_dominated(base);
+        log::info!(
+            "Pruned {} dominated nodes",
+            base.nodes.len() - pruned.nodes.len()
+        );
+        pruned
+    });
+    let extraction_egraph = pruned.as_ref().or(weighted.as_ref()).unwrap_or(&egraph);
+
+    if let Some(path) = &queries {
+        let queries = read_queries(path)
+            .with_context(|| format!("Failed to read the queries in {}", path.display()))
+            .unwrap();
+        let extractor: &dyn Extractor = match &recording {
+            Some(recording) => recording,
+            None => extractor.as_ref(),
+        };
+        let per_query = extract_queries(extractor, &egraph, extraction_egraph, &queries)
+            .unwrap_or_else(|e| {
+                eprintln!("{e}");
+                std::process::exit(1);
+            });
+        let report = serde_json::json!({
+            "name": filename,
+            "extractor": extractor_name,
+            "queries": per_query,
+        });
+        writeln!(
+            out_file,
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap()
+        )
+        .unwrap();
+        return;
+    }
+
+    if !cost_models.is_empty() {
+        let per_model = compare_cost_models(extractor.as_ref(), extraction_egraph, &cost_models);
+        let report = serde_json::json!({
+            "name": filename,
+            "extractor": extractor_name,
+            "cost_models": per_model,
+        });
+        writeln!(
+            out_file,
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap()
+        )
+        .unwrap();
+        return;
+    }
+
+    if !ilp_timeout_sweep.is_empty() {
+        let per_timeout = sweep_ilp_timeouts(extraction_egraph, &constraints, &ilp_timeout_sweep);
+        let report = serde_json::json!({
+            "name": filename,
+            "extractor": extractor_name,
+            "ilp_timeout_sweep": per_timeout,
+        });
+        writeln!(
+            out_file,
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap()
+        )
+        .unwrap();
+        return;
+    }
+
+    if screen {
+        let (greedy_dag, bottom_up_dag) = screen_heuristics(&extractors, extraction_egraph);
+        let (low, high) = if greedy_dag < bottom_up_dag {
+            (greedy_dag, bottom_up_dag)
+        } else {
+            (bottom_up_dag, greedy_dag)
+        };
+        let ratio = if high == 0.0 { 1.0 } else { high / low };
+        if ratio <= screen_threshold {
+            log::info!("{filename:40}\tdag ratio {ratio:.3}, skipped");
+            drop(out_file);
+            if let Err(e) = std::fs::remove_file(&out_filename) {
+                eprintln!("Can't remove {}: {e}", out_filename.display());
+                std::process::exit(1);
+            }
+            return;
+        }
+        log::info!("{filename:40}\t{greedy_dag:10.3}\t{bottom_up_dag:10.3}\t{ratio:.3}");
+        let report = serde_json::json!({
+            "name": filename,
+            "screen": {
+                "faster-greedy-dag": greedy_dag,
+                "faster-bottom-up": bottom_up_dag,
+                "ratio": if ratio.is_finite() { ratio.into() } else { serde_json::Value::Null },
+                "threshold": screen_threshold,
+            },
+        });
+        writeln!(
+            out_file,
+            "{}",
+            serde_json::to_string_pretty(&report).unwrap()
+        )
+        .unwrap();
+        return;
+    }
+
+    let extract = |egraph: &EGraph| {
+        let extractor: &dyn Extractor = match &recording {
+            Some(recording) => recording,
+            None => extractor.as_ref(),
+        };
+        let latched = latch_op
+            .as_ref()
+            .map(|latch_op| extract::latch::LatchExtractor {
+                inner: extractor,
+                latch_op: latch_op.clone(),
+            });
+        let extractor: &dyn Extractor = match &latched {
+            Some(latched) => latched,
+            None => extractor,
+        };
+        if parallel {
+            extract_in_parallel(extractor, egraph)
+        } else {
+            extractor.extract(egraph, &egraph.root_eclasses)
+        }
+    };
+
+    let run = || {
+        let mut escalation = None;
+        let mut drifted = vec![];
+        let mut refit = false;
+        let result = match escalate_timeout {
+            Some((initial, max)) => {
+                let (result, timeout, optimal) = extract_escalating(
+                    &extractor_name,
+                    extraction_egraph,
+                    &constraints,
+                    initial,
+                    max,
+                );
+                escalation = Some((timeout, optimal));
+                result
+            }
+            // Refitting ties would follow the cycles through the latches.
+            None if latch_op.is_some() => extract(extraction_egraph),
+            None if strict && extractor_name == "faster-greedy-dag" && !substituted => {
+                use extract::faster_greedy_dag::{drifted_roots, FasterGreedyDagExtractor};
+                let (result, totals) =
+                    FasterGreedyDagExtractor.extract_with_totals(extraction_egraph);
+                drifted = drifted_roots(extraction_egraph, &egraph.root_eclasses, &result, &totals);
+                result
+            }
+            None if low_memory && !substituted => {
+                refit = true;
+                extract::bottom_up::BottomUpExtractor.extract_low_memory(extraction_egraph)
+            }
+            None if choice_trace.is_some() && !substituted => {
+                let path = choice_trace.as_ref().unwrap();
+                let (result, trace) = extract_traced(&extractor_name, extraction_egraph);
+                refit = ed.optimal == Optimal::Tree;
+                std::fs::write(path, trace).unwrap();
+                log::info!("Wrote choice trace to {}", path.display());
+                result
+            }
+            None if ed.optimal == Optimal::Tree && !substituted => {
+                refit = true;
+                extract(extraction_egraph)
+            }
+            None => extract(extraction_egraph),
+        };
+        (result, escalation, drifted, refit)
+    };
+    // Evening out the ties isn't part of the extraction, so it's done after the timing.
+    let tie_priority = |node: &Node| transform::tie_break_priority(&tie_break_keys, node);
+    let refit_ties = |(mut result, escalation, drifted, refit): (ExtractionResult, _, _, bool)| {
+        if refit {
+            result.refit_ties_with(extraction_egraph, tie_fit, tie_priority);
+        }
+        (result, escalation, drifted)
+    };
+
     let start_time = std::time::Instant::now();
-    let result = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+    let ran = run();
     let us = start_time.elapsed().as_micros();
+    let (result, escalation, drifted) = refit_ties(ran);
+
+    let rerun = check_determinism.then(|| refit_ties(run()));
+    if let Some((again, _, _)) = &rerun {
+        let differing = differing_choices(&result, again);
+        if !differing.is_empty() {
+            eprintln!("Extraction of {filename} isn't deterministic: two runs chose differently for classes {differing:?}");
+            std::process::exit(1);
+        }
+        if !result.choices.keys().eq(again.choices.keys()) {
+            eprintln!("Extraction of {filename} isn't deterministic: two runs made the same choices in a different order");
+            std::process::exit(1);
+        }
+    }
+
+    if !drifted.is_empty() {
+        let stem = out_filename.file_stem().unwrap().to_string_lossy();
+        let dump_filename = out_filename.with_file_name(format!("{stem}_drifted_egraph.json"));
+        extraction_egraph.to_json_file(&dump_filename).unwrap();
+        for (root, recomputed, tracked) in &drifted {
+            if *tracked == INFINITY {
+                eprintln!("Root {root} wasn't extracted by faster-greedy-dag");
+            } else {
+                eprintln!(
+                    "Root {root} has dag cost {recomputed}, but faster-greedy-dag tracked {tracked}"
+                );
+            }
+        }
+        eprintln!("Wrote the egraph to {}", dump_filename.display());
+        std::process::exit(1);
+    }
+
+    // With --maximize-roots, the roots that didn't fit in the budget are dropped and the
+    // rest of the report is about the roots that did.
+    let mut dropped = vec![];
+    let egraph = if constraints.maximize_roots {
+        let (covered, missing): (Vec<ClassId>, Vec<ClassId>) = egraph
+            .root_eclasses
+            .iter()
+            .cloned()
+            .partition(|root| result.choices.contains_key(root));
+        if covered.is_empty() {
+            eprintln!("No root fits in a budget of {} nodes", node_budget.unwrap());
+            std::process::exit(1);
+        }
+        for root in &missing {
+            log::info!("Dropped root {root}");
+        }
+        dropped = missing;
+        let mut covered_egraph = egraph.clone();
+        covered_egraph.root_eclasses = covered;
+        std::borrow::Cow::Owned(covered_egraph)
+    } else {
+        std::borrow::Cow::Borrowed(&egraph)
+    };
+
+    // With --latch-op, the rest of the report is about the egraph with the latches cut off,
+    // whose roots include the inputs of the latches the extraction uses.
+    let egraph = match &latch_op {
+        Some(latch_op) => {
+            let mut cut = extract::latch::cut_latches(&egraph, latch_op);
+            for input in
+                extract::latch::latch_inputs(&egraph, &result, &egraph.root_eclasses, latch_op)
+            {
+                if !cut.root_eclasses.contains(&input) {
+                    cut.root_eclasses.push(input);
+                }
+            }
+            std::borrow::Cow::Owned(cut)
+        }
+        None => egraph,
+    };
+
+    let unextracted: Vec<&ClassId> = egraph
+        .root_eclasses
+        .iter()
+        .filter(|root| !result.choices.contains_key(*root))
+        .collect();
+    if !unextracted.is_empty() {
+        if let Some((removed_nodes, unextractable)) = exclusion {
+            eprintln!("Excluding nodes removed {removed_nodes} nodes, {unextractable} classes became unextractable");
+        }
+        eprintln!("No extraction of roots {unextracted:?} meets the constraints");
+        std::process::exit(1);
+    }
 
     result.check(&egraph);
 
+    let uncovered = result.uncovered(&egraph, &egraph.root_eclasses, &constraints.cover);
+    if !uncovered.is_empty() {
+        eprintln!("The extraction doesn't use nodes {uncovered:?}, which it has to cover");
+        if !is_ilp {
+            eprintln!("Only the ILP extractors make sure to use them");
+        }
+        std::process::exit(1);
+    }
+
+    for group in &constraints.linked_groups {
+        let unused = result.uncovered(&egraph, &egraph.root_eclasses, group);
+        if !unused.is_empty() && unused.len() < group.len() {
+            eprintln!(
+                "The extraction uses only some of the linked nodes {group:?}, not {unused:?}"
+            );
+            std::process::exit(1);
+        }
+    }
+
     let tree = result.tree_cost(&egraph, &egraph.root_eclasses);
     let dag = result.dag_cost(&egraph, &egraph.root_eclasses);
 
+    if let Some((again, _, _)) = &rerun {
+        let tree_again = again.tree_cost(&egraph, &egraph.root_eclasses);
+        let dag_again = again.dag_cost(&egraph, &egraph.root_eclasses);
+        if tree.to_bits() != tree_again.to_bits() || dag.to_bits() != dag_again.to_bits() {
+            eprintln!("Extraction of {filename} isn't deterministic: the same choices cost {tree}/{dag} and then {tree_again}/{dag_again} (tree/dag)");
+            std::process::exit(1);
+        }
+    }
+
     log::info!("{filename:40}\t{extractor_name:10}\t{tree:5}\t{dag:5}\t{us:5}");
+
+    let mut report = serde_json::json!({
+        "name": filename,
+        "extractor": extractor_name,
+        "tree": tree.into_inner(),
+        "dag": dag.into_inner(),
+        "micros": us as u64,
+    });
+
+    if check_determinism {
+        report["deterministic"] = true.into();
+    }
+
+    if constraints.maximize_roots {
+        let names = |roots: &[ClassId]| roots.iter().map(|r| r.to_string()).collect::<Vec<_>>();
+        report["covered_roots"] = names(&egraph.root_eclasses).into();
+        report["dropped_roots"] = names(&dropped).into();
+    }
+
+    if let Some((removed_nodes, unextractable)) = exclusion {
+        report["removed_nodes"] = removed_nodes.into();
+        report["unextractable_classes"] = unextractable.into();
+    }
+
+    if objective.is_some() || rarity_penalty.is_some() || !root_weights.is_empty() {
+        let objective_dag = result.dag_cost(extraction_egraph, &egraph.root_eclasses);
+        report["objective_dag"] = objective_dag.into_inner().into();
+    }
+
+    if !allowed_ops.is_empty() {
+        report["unextractable_with_allowed_ops"] = disallowed
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .into();
+    }
+
+    if let Some(decay) = reuse_decay {
+        let reuse_cost = result.reuse_cost(&egraph, &egraph.root_eclasses, decay);
+        log::info!("{filename:40}\treuse cost: {reuse_cost:5}");
+        report["reuse_cost"] = reuse_cost.into_inner().into();
+    }
+
+    if let Some((timeout, optimal)) = escalation {
+        report["ilp_timeout"] = timeout.into();
+        report["ilp_optimal"] = optimal.into();
+    }
+
+    if by_component {
+        let components = stats::root_components(&egraph);
+        report["components"] = components
+            .iter()
+            .map(|roots| {
+                let tree = result.tree_cost(&egraph, roots);
+                let dag = result.dag_cost(&egraph, roots);
+                let names: Vec<String> = roots.iter().map(|r| r.to_string()).collect();
+                log::info!("{:40}\t{tree:5}\t{dag:5}", names.join(","));
+                serde_json::json!({
+                    "roots": names,
+                    "tree": tree.into_inner(),
+                    "dag": dag.into_inner(),
+                })
+            })
+            .collect();
+    }
+
+    if benchmark {
+        // a run too fast to time still counts as a microsecond
+        let seconds = us.max(1) as f64 / 1e6;
+        let chosen = result
+            .restrict(&egraph, &egraph.root_eclasses)
+            .choices
+            .len();
+        let nodes_per_second = extraction_egraph.nodes.len() as f64 / seconds;
+        let chosen_per_second = chosen as f64 / seconds;
+        log::info!(
+            "{filename:40}\t{nodes_per_second:.0} nodes/s\t{chosen_per_second:.0} chosen nodes/s"
+        );
+        report["throughput"] = serde_json::json!({
+            "nodes": extraction_egraph.nodes.len(),
+            "chosen_nodes": chosen,
+            "seconds": seconds,
+            "nodes_per_second": nodes_per_second,
+            "chosen_nodes_per_second": chosen_per_second,
+        });
+    }
+
+    if let Some(field) = &with_source {
+        let sources = transform::read_node_values(&filename, field)
+            .with_context(|| format!("Failed to read the node {field}s of {filename}"))
+            .unwrap();
+        let used = result.restrict(&egraph, &egraph.root_eclasses);
+        let mut chosen = serde_json::Map::new();
+        for (class_id, node_id) in &used.choices {
+            let Some(source) = sources.get(node_id) else {
+                continue;
+            };
+            chosen.insert(
+                node_id.to_string(),
+                serde_json::json!({
+                    "class": class_id.to_string(),
+                    "op": egraph[node_id].op,
+                    "source": source,
+                }),
+            );
+        }
+        log::info!(
+            "{filename:40}\t{} of {} chosen nodes have a {field}",
+            chosen.len(),
+            used.choices.len()
+        );
+        report["sources"] = chosen.into();
+    }
+
+    if !report_dims.is_empty() {
+        let dims: Vec<&str> = report_dims.iter().map(String::as_str).collect();
+        let dim_values = transform::read_node_fields(&filename, &dims)
+            .with_context(|| format!("Failed to read the --report-dims fields of {filename}"))
+            .unwrap();
+        let mut totals = serde_json::Map::new();
+        for (dim, values) in report_dims.iter().zip(dim_values) {
+            let model: IndexMap<NodeId, Cost> = egraph
+                .nodes
+                .keys()
+                .map(|id| {
+                    let value = values.get(id).copied().unwrap_or(0.0);
+                    (id.clone(), Cost::new(value).unwrap())
+                })
+                .collect();
+            let total = result.dag_cost_with(&egraph, &egraph.root_eclasses, &model);
+            log::info!("{filename:40}\t{dim}: {total:5}");
+            totals.insert(dim.clone(), total.into_inner().into());
+        }
+        report["dims"] = totals.into();
+    }
+
+    if let Some(delay_table) = &delay_table {
+        let table = objective::read_cost_table(delay_table)
+            .with_context(|| format!("Failed to read the delay table {delay_table}"))
+            .unwrap();
+        let delays = transform::delays_by_table(&egraph, &table).unwrap();
+        let critical_path = result.critical_path_with(&egraph, &egraph.root_eclasses, &delays);
+        log::info!("{filename:40}\tcritical path: {critical_path:5}");
+        report["critical_path"] = critical_path.into_inner().into();
+    }
+
+    if let Some(attribution) = attribute_cost {
+        let per_root = result.attribute_cost(&egraph, &egraph.root_eclasses, attribution);
+        for (root, cost) in &per_root {
+            log::info!("{root:40}\t{cost:5}");
+        }
+        report["attribution"] = per_root
+            .iter()
+            .map(|(root, cost)| (root.to_string(), cost.into_inner().into()))
+            .collect::<serde_json::Map<String, serde_json::Value>>()
+            .into();
+    }
+
+    if let Some(path) = &certificate {
+        let cert = certificate::to_certificate(&egraph, &result, &egraph.root_eclasses).unwrap();
+        std::fs::write(path, serde_json::to_string_pretty(&cert).unwrap() + "\n").unwrap();
+        log::info!("Wrote certificate to {}", path.display());
+    }
+
+    if let Some(metrics_file) = &metrics_file {
+        let metrics = output::prometheus::to_prometheus(
+            &egraph,
+            &result,
+            &egraph.root_eclasses,
+            &extractor_name,
+            us,
+        );
+        output::prometheus::write_metrics_file(metrics_file, &metrics).unwrap();
+        log::info!("Wrote metrics to {}", metrics_file.display());
+    }
+
+    if alternatives {
+        let per_class = result.alternatives(&egraph, &egraph.root_eclasses);
+        report["alternatives"] = per_class
+            .iter()
+            .map(|(class_id, (node_id, delta))| {
+                log::info!("{class_id:40}\t{node_id:10}\t{delta:+}");
+                let alternative = serde_json::json!({
+                    "chosen": result.choices[class_id].to_string(),
+                    "alternative": node_id.to_string(),
+                    "delta": delta.into_inner(),
+                });
+                (class_id.to_string(), alternative)
+            })
+            .collect::<serde_json::Map<String, serde_json::Value>>()
+            .into();
+    }
+
+    if let Some(heatmap) = &heatmap {
+        let csv = output::heatmap::to_heatmap_csv(&egraph, &result, &egraph.root_eclasses);
+        std::fs::write(heatmap, csv).unwrap();
+        log::info!("Wrote heatmap to {}", heatmap.display());
+    }
+
+    if gap {
+        report_gap(&egraph, dag, &mut report);
+    }
+    if with_lp_bound {
+        report_lp_bound(&egraph, dag, &constraints, &mut report);
+    }
+
+    let mut equivalent = true;
+    if let (Some(command), Some(reference)) = (&verify_command, &reference) {
+        let sexpr_filename = out_filename.with_extension("sexpr");
+        let sexprs = output::sexpr::to_sexprs(&egraph, &result, &egraph.root_eclasses);
+        std::fs::write(&sexpr_filename, sexprs).unwrap();
+        equivalent = verify_equivalence(command, &sexpr_filename, reference);
+        log::info!("{filename:40}\tequivalent: {equivalent}");
+        report["equivalent"] = equivalent.into();
+    }
+
+    // Last, so report-json includes everything in the report.
+    if verilog_testbench {
+        let verilog = output::verilog::to_verilog(&egraph, &result, &egraph.root_eclasses).unwrap();
+        print!("{}", verilog.module);
+        let stem = out_filename.file_stem().unwrap().to_string_lossy();
+        let tb_filename = out_filename.with_file_name(format!("{stem}_tb.v"));
+        std::fs::write(&tb_filename, output::verilog::testbench(&verilog.ports)).unwrap();
+        log::info!("Wrote testbench to {}", tb_filename.display());
+    } else if let Some(format) = format {
+        print!(
+            "{}",
+            output::render(format, &egraph, &result, &egraph.root_eclasses, &report).unwrap()
+        );
+    }
+
     writeln!(
         out_file,
-        r#"{{ 
-    "name": "{filename}",
-    "extractor": "{extractor_name}", 
-    "tree": {tree}, 
-    "dag": {dag}, 
-    "micros": {us}
-}}"#
+        "{}",
+        serde_json::to_string_pretty(&report).unwrap()
     )
     .unwrap();
+
+    if !equivalent {
+        eprintln!("Extraction of {filename} is not equivalent to the reference");
+        std::process::exit(1);
+    }
+
+    if let Some(threshold) = max_acceptable_cost {
+        if dag.into_inner() > threshold {
+            eprintln!(
+                "Extraction of {filename} has dag cost {dag}, more than the acceptable {threshold}"
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The extractor to use instead of the selected one when the extraction is constrained.
+/// The ILP extractors take the constraints directly, the others are replaced by
+/// greedy-max or the bounded-depth extractor. Only the ILP can limit the distinct ops, keep
+/// to exclusive groups of ops or match a target shape.
+fn constrained_extractor(
+    extractor_name: &str,
+    constraints: &Constraints,
+) -> Option<Box<dyn Extractor>> {
+    if constraints.is_empty() {
+        return None;
+    }
+    let extractor = match extractor_name {
+        #[cfg(feature = "ilp-cbc")]
+        "ilp-cbc-timeout" | "ilp-cbc" => extract::ilp_cbc::ConfiguredCbcExtractor {
+            config: constraints.ilp_config(),
+            timeout_seconds: if extractor_name == "ilp-cbc" {
+                std::u32::MAX
+            } else {
+                10
+            },
+        }
+        .boxed(),
+        _ if constraints.max_distinct_ops.is_some() => {
+            panic!("Only the ILP extractors can limit --max-distinct-ops")
+        }
+        _ if !constraints.exclusive_groups.is_empty() => {
+            panic!("Only the ILP extractors can keep to an --exclusive-group")
+        }
+        _ if !constraints.linked_groups.is_empty() => {
+            panic!("Only the ILP extractors can keep a --linked-group together")
+        }
+        _ if constraints.target_shape.is_some() => {
+            panic!("Only the ILP extractors can match a --target-shape")
+        }
+        _ if constraints.node_budget.is_some() => {
+            if constraints.maximize || constraints.max_depth.is_some() {
+                panic!("Only the ILP extractors can combine --node-budget with --maximize or --max-depth")
+            }
+            log::info!("Extracting with node-budget instead of {extractor_name}");
+            extract::node_budget::NodeBudgetExtractor {
+                budget: constraints.node_budget.unwrap(),
+            }
+            .boxed()
+        }
+        // The other extractors' results are checked for the nodes to cover afterwards.
+        _ if !constraints.maximize && constraints.max_depth.is_none() => return None,
+        _ => match constraints.max_depth {
+            Some(_) if constraints.maximize => {
+                panic!("Only the ILP extractors can --maximize with --max-depth")
+            }
+            Some(max_depth) => {
+                log::info!("Extracting with bounded-depth instead of {extractor_name}");
+                extract::bounded_depth::BoundedDepthExtractor { max_depth }.boxed()
+            }
+            None => {
+                log::info!("Maximizing with greedy-max instead of {extractor_name}");
+                extract::greedy_max::GreedyMaxExtractor.boxed()
+            }
+        },
+    };
+    Some(extractor)
+}
+
+/// Reads named sets of roots from a JSON object of lists of class ids.
+fn read_queries(path: &Path) -> anyhow::Result<IndexMap<String, Vec<ClassId>>> {
+    let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let Some(object) = json.as_object() else {
+        anyhow::bail!("Expected an object of named lists of roots");
+    };
+    object
+        .iter()
+        .map(|(name, roots)| {
+            let Some(roots) = roots.as_array() else {
+                anyhow::bail!("Query {name} should be a list of roots");
+            };
+            let roots = roots
+                .iter()
+                .map(|root| match root.as_str() {
+                    Some(root) => Ok(ClassId::from(root)),
+                    None => anyhow::bail!("Query {name} has {root}, not a class id"),
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Ok((name.clone(), roots))
+        })
+        .collect()
+}
+
+/// Extracts the roots of each query on its own from `extraction_egraph`, and reports its
+/// costs in `egraph` by name.
+fn extract_queries(
+    extractor: &dyn Extractor,
+    egraph: &EGraph,
+    extraction_egraph: &EGraph,
+    queries: &IndexMap<String, Vec<ClassId>>,
+) -> anyhow::Result<serde_json::Value> {
+    let mut per_query = serde_json::Map::new();
+    for (name, roots) in queries {
+        if let Some(root) = roots.iter().find(|r| !egraph.classes().contains_key(*r)) {
+            anyhow::bail!("Query {name} has root {root}, which isn't in the egraph");
+        }
+        let start_time = std::time::Instant::now();
+        let result = extractor.extract(extraction_egraph, roots);
+        let us = start_time.elapsed().as_micros();
+
+        let unextracted: Vec<&ClassId> = roots
+            .iter()
+            .filter(|root| !result.choices.contains_key(*root))
+            .collect();
+        if !unextracted.is_empty() {
+            anyhow::bail!("Query {name} has roots {unextracted:?} that can't be extracted");
+        }
+        // check() wants every root of the egraph, not just the query's.
+        assert!(result.find_cycles(egraph, roots).is_empty());
+
+        let tree = result.tree_cost(egraph, roots);
+        let dag = result.dag_cost(egraph, roots);
+        log::info!("{name:40}\t{tree:5}\t{dag:5}\t{us:5}");
+        per_query.insert(
+            name.clone(),
+            serde_json::json!({
+                "roots": roots.iter().map(|r| r.to_string()).collect::<Vec<_>>(),
+                "tree": tree.into_inner(),
+                "dag": dag.into_inner(),
+                "micros": us as u64,
+            }),
+        );
+    }
+    Ok(per_query.into())
+}
+
+/// Extracts under each cost model and prints a table comparing the extractions, each
+/// costed with the egraph's costs. Latency is always extracted with a bottom-up pass that
+/// minimizes the critical path, as the extractors minimize sums.
+fn compare_cost_models(
+    extractor: &dyn Extractor,
+    egraph: &EGraph,
+    models: &[(String, objective::CostModelSpec)],
+) -> serde_json::Value {
+    use objective::CostModelSpec;
+
+    let roots = &egraph.root_eclasses;
+    let mut per_model = serde_json::Map::new();
+    eprintln!(
+        "{:20}\t{:>10}\t{:>10}\t{:>5}\t{:>5}",
+        "model", "tree", "dag", "depth", "nodes"
+    );
+    for (name, model) in models {
+        let result = match model {
+            CostModelSpec::Sum => extractor.extract(egraph, roots),
+            CostModelSpec::Count => {
+                let counted =
+                    transform::weight_by_objective(egraph, &objective::Objective::Num(1.0))
+                        .unwrap();
+                extractor.extract(&counted, roots)
+            }
+            CostModelSpec::Latency => {
+                extract::bottom_up::BottomUpExtractor.extract_min_latency(egraph)
+            }
+            CostModelSpec::Objective(objective) => {
+                let weighted = transform::weight_by_objective(egraph, objective).unwrap();
+                extractor.extract(&weighted, roots)
+            }
+        };
+        result.check(egraph);
+        let tree = result.tree_cost(egraph, roots);
+        let dag = result.dag_cost(egraph, roots);
+        let depth = result.critical_path_with(egraph, roots, &extract::UnitCost);
+        let nodes = result.dag_cost_with(egraph, roots, &extract::UnitCost);
+        eprintln!("{name:20}\t{tree:10.3}\t{dag:10.3}\t{depth:5}\t{nodes:5}");
+        per_model.insert(
+            name.clone(),
+            serde_json::json!({
+                "tree": tree.into_inner(),
+                "dag": dag.into_inner(),
+                "depth": depth.into_inner() as usize,
+                "nodes": nodes.into_inner() as usize,
+            }),
+        );
+    }
+    per_model.into()
+}
+
+/// The dag costs of faster-greedy-dag's and faster-bottom-up's extractions, for --screen.
+fn screen_heuristics(extractors: &IndexMap<&str, ExtractorDetail>, egraph: &EGraph) -> (f64, f64) {
+    let roots = &egraph.root_eclasses;
+    let dag = |name: &str| {
+        let result = extractors[name].extractor.extract(egraph, roots);
+        result.check(egraph);
+        result.dag_cost(egraph, roots).into_inner()
+    };
+    (dag("faster-greedy-dag"), dag("faster-bottom-up"))
+}
+
+/// The classes that one of two extractions chooses a node for and the other chooses a
+/// different node or none for.
+fn differing_choices(a: &ExtractionResult, b: &ExtractionResult) -> Vec<ClassId> {
+    let mut differing: Vec<ClassId> = a
+        .choices
+        .iter()
+        .filter(|(class_id, node_id)| b.choices.get(*class_id) != Some(*node_id))
+        .map(|(class_id, _)| class_id.clone())
+        .collect();
+    differing.extend(
+        b.choices
+            .keys()
+            .filter(|class_id| !a.choices.contains_key(*class_id))
+            .cloned(),
+    );
+    differing
+}
+
+/// The extractors --choice-trace can follow.
+const TRACED_EXTRACTORS: [&str; 2] = ["bottom-up", "greedy-dag"];
+
+/// Extracts with one of `TRACED_EXTRACTORS`, and gives the choices it made as it ran, a line
+/// of iteration, class, node and cost each.
+fn extract_traced(extractor_name: &str, egraph: &EGraph) -> (ExtractionResult, String) {
+    let mut trace = String::from("# iteration\tclass\tnode\tcost\n");
+    let mut on_choice = |iteration: usize, class_id: &ClassId, node_id: &NodeId, cost| {
+        trace.push_str(&format!("{iteration}\t{class_id}\t{node_id}\t{cost}\n"));
+    };
+    let result = match extractor_name {
+        "bottom-up" => extract::bottom_up::BottomUpExtractor.extract_traced_with(
+            egraph,
+            &extract::NodeCost,
+            |iteration, class_id, node_id, cost| on_choice(iteration, class_id, node_id, *cost),
+        ),
+        "greedy-dag" => extract::greedy_dag::GreedyDagExtractor.extract_traced(egraph, on_choice),
+        _ => panic!("Can't trace the choices of {extractor_name}"),
+    };
+    (result, trace)
+}
+
+/// Extracts the groups of roots that share no classes with each other in parallel, on at
+/// most `available_parallelism` threads, which gives the same result as extracting them
+/// together.
+fn extract_in_parallel(extractor: &dyn Extractor, egraph: &EGraph) -> ExtractionResult {
+    let groups = stats::root_components(egraph);
+    if groups.len() < 2 {
+        log::info!("The roots all share classes, extracting them serially");
+        return extractor.extract(egraph, &egraph.root_eclasses);
+    }
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(groups.len());
+    log::info!(
+        "Extracting {} independent groups of roots on {workers} threads",
+        groups.len()
+    );
+
+    // Each worker takes the next group left until there are none.
+    let parts = transform::split_by_roots(egraph, &groups);
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let mut results: Vec<(usize, ExtractionResult)> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        let Some(part) = parts.get(i) else {
+                            return done;
+                        };
+                        done.push((i, extractor.extract(part, &part.root_eclasses)));
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    let results: Vec<ExtractionResult> = results.into_iter().map(|(_, result)| result).collect();
+
+    // The groups share no classes, so their choices can't conflict.
+    ExtractionResult::merge(&results).unwrap()
+}
+
+/// Runs the ILP with `solve_escalating`. Also returns the last timeout it tried and
+/// whether the extraction was proven optimal.
+#[cfg(feature = "ilp-cbc")]
+fn extract_escalating(
+    extractor_name: &str,
+    egraph: &EGraph,
+    constraints: &Constraints,
+    initial_seconds: u32,
+    max_seconds: u32,
+) -> (ExtractionResult, u32, bool) {
+    use extract::ilp_cbc::{result_or_fallback, solve_escalating, IlpSolution};
+
+    if !matches!(extractor_name, "ilp-cbc-timeout" | "ilp-cbc") {
+        panic!("--escalate-timeout only applies to ilp-cbc and ilp-cbc-timeout");
+    }
+    let config = constraints.ilp_config();
+    let roots = &egraph.root_eclasses;
+    let (solution, timeout) =
+        solve_escalating(egraph, roots, &config, initial_seconds, max_seconds);
+    let optimal = matches!(solution, IlpSolution::Optimal(_));
+    let result = result_or_fallback(solution, egraph, roots, &config, timeout);
+    (result, timeout, optimal)
+}
+
+#[cfg(not(feature = "ilp-cbc"))]
+fn extract_escalating(
+    _extractor_name: &str,
+    _egraph: &EGraph,
+    _constraints: &Constraints,
+    _initial_seconds: u32,
+    _max_seconds: u32,
+) -> (ExtractionResult, u32, bool) {
+    panic!("--escalate-timeout needs the ilp-cbc feature");
+}
+
+/// Solves the ILP with each timeout and prints to stderr a table of the dag cost it reached
+/// and whether it proved that optimal, along with how long it took. A run that found nothing
+/// has no cost, rather than the heuristic's that `extract` would fall back to.
+#[cfg(feature = "ilp-cbc")]
+fn sweep_ilp_timeouts(
+    egraph: &EGraph,
+    constraints: &Constraints,
+    timeouts: &[u32],
+) -> serde_json::Value {
+    use extract::ilp_cbc::{solve, IlpSolution};
+
+    let config = constraints.ilp_config();
+    let roots = &egraph.root_eclasses;
+    eprintln!(
+        "{:>7}\t{:>10}\t{:>10}\t{:>7}",
+        "timeout", "dag", "seconds", "optimal"
+    );
+    let mut per_timeout = vec![];
+    for &timeout in timeouts {
+        let start = std::time::Instant::now();
+        let solution = solve(egraph, roots, &config, timeout);
+        let seconds = start.elapsed().as_secs_f64();
+        let (result, status) = match solution {
+            IlpSolution::Optimal(result) => (Some(result), "optimal"),
+            IlpSolution::Incumbent(result) => (Some(result), "incumbent"),
+            IlpSolution::NoSolution => (None, "no solution"),
+            IlpSolution::Infeasible => (None, "infeasible"),
+        };
+        let dag = result.map(|result| {
+            result.check(egraph);
+            result.dag_cost(egraph, roots).into_inner()
+        });
+        let optimal = status == "optimal";
+        let shown = dag.map_or("-".to_string(), |dag| format!("{dag:.3}"));
+        eprintln!("{timeout:>7}\t{shown:>10}\t{seconds:>10.3}\t{optimal:>7}");
+        per_timeout.push(serde_json::json!({
+            "timeout": timeout,
+            "dag": dag,
+            "seconds": seconds,
+            "optimal": optimal,
+            "status": status,
+        }));
+    }
+    per_timeout.into()
+}
+
+#[cfg(not(feature = "ilp-cbc"))]
+fn sweep_ilp_timeouts(
+    _egraph: &EGraph,
+    _constraints: &Constraints,
+    _timeouts: &[u32],
+) -> serde_json::Value {
+    panic!("--ilp-timeout-sweep needs the ilp-cbc feature");
+}
+
+#[cfg(feature = "scripting")]
+fn cost_by_script(egraph: &EGraph, path: &Path) -> EGraph {
+    let script = script::CostScript::load(path)
+        .with_context(|| format!("Failed to load the cost script {}", path.display()))
+        .unwrap();
+    transform::cost_by_script(egraph, &script).unwrap()
+}
+
+#[cfg(not(feature = "scripting"))]
+fn cost_by_script(_egraph: &EGraph, _path: &Path) -> EGraph {
+    panic!("--cost-script needs the scripting feature");
+}
+
+/// The size of ilp-cbc's ILP as `(variables, constraints)`.
+#[cfg(feature = "ilp-cbc")]
+fn estimate_ilp(egraph: &EGraph, constraints: &Constraints) -> (u32, u32) {
+    extract::ilp_cbc::estimate(egraph, &egraph.root_eclasses, &constraints.ilp_config())
+}
+
+#[cfg(not(feature = "ilp-cbc"))]
+fn estimate_ilp(_egraph: &EGraph, _constraints: &Constraints) -> (u32, u32) {
+    panic!("--ilp-estimate needs the ilp-cbc feature");
+}
+
+/// Compares the DAG cost of a heuristic extraction to the ILP's, as `(heuristic - optimal) / optimal`.
+/// If the solver times out its incumbent is used instead, so the gap is only an upper bound.
+#[cfg(feature = "ilp-cbc")]
+fn report_gap(egraph: &EGraph, heuristic: Cost, report: &mut serde_json::Value) {
+    use extract::ilp_cbc::{solve, IlpSolution};
+
+    let (optimal, upper_bound) = match solve(
+        egraph,
+        &egraph.root_eclasses,
+        &Default::default(),
+        GAP_TIMEOUT_IN_SECONDS,
+    ) {
+        IlpSolution::Optimal(result) => (result, false),
+        IlpSolution::Incumbent(result) => (result, true),
+        IlpSolution::NoSolution | IlpSolution::Infeasible => {
+            log::info!("gap: unknown, the ILP found no extraction in {GAP_TIMEOUT_IN_SECONDS}s");
+            report["gap"] = serde_json::Value::Null;
+            return;
+        }
+    };
+    optimal.check(egraph);
+    let optimal = optimal.dag_cost(egraph, &egraph.root_eclasses);
+
+    let gap = if optimal == 0.0 {
+        if heuristic == 0.0 {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        ((heuristic - optimal) / optimal).into_inner()
+    };
+    if upper_bound {
+        log::info!("gap: <= {gap:.6} (ILP timed out, compared against its incumbent)");
+    } else {
+        log::info!("gap: {gap:.6}");
+    }
+
+    report["optimal_dag"] = optimal.into_inner().into();
+    report["gap"] = gap.into();
+    report["gap_is_upper_bound"] = upper_bound.into();
+}
+
+#[cfg(not(feature = "ilp-cbc"))]
+fn report_gap(_egraph: &EGraph, _heuristic: Cost, report: &mut serde_json::Value) {
+    log::info!("gap: unknown, the ILP extractor needs the ilp-cbc feature");
+    report["gap"] = serde_json::Value::Null;
+}
+
+/// Compares the DAG cost of a heuristic extraction to the LP relaxation's lower bound, as
+/// `(heuristic - bound) / bound`, so the true gap is at most this.
+#[cfg(feature = "ilp-cbc")]
+fn report_lp_bound(
+    egraph: &EGraph,
+    heuristic: Cost,
+    constraints: &Constraints,
+    report: &mut serde_json::Value,
+) {
+    if constraints.maximize || constraints.maximize_roots {
+        panic!("--with-lp-bound only bounds the cheapest extraction of every root");
+    }
+    let Some(bound) =
+        extract::ilp_cbc::lp_bound(egraph, &egraph.root_eclasses, &constraints.ilp_config())
+    else {
+        log::info!("lp bound: none, the relaxation is infeasible");
+        report["lp_bound"] = serde_json::Value::Null;
+        return;
+    };
+    // The solver's tolerances can put the bound a hair above an optimal extraction.
+    let bound = bound.min(heuristic.into_inner());
+
+    let gap = if bound <= 0.0 {
+        if heuristic == 0.0 {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        (heuristic.into_inner() - bound) / bound
+    };
+    log::info!("lp bound: {bound:.6}, gap <= {gap:.6}");
+
+    report["lp_bound"] = bound.into();
+    report["lp_gap"] = gap.into();
+}
+
+#[cfg(not(feature = "ilp-cbc"))]
+fn report_lp_bound(
+    _egraph: &EGraph,
+    _heuristic: Cost,
+    _constraints: &Constraints,
+    _report: &mut serde_json::Value,
+) {
+    panic!("--with-lp-bound needs the ilp-cbc feature");
+}
+
+/// Runs the user's equivalence checker on the extracted and reference s-expressions.
+/// The command is split on whitespace, no shell is involved.
+fn verify_equivalence(command: &str, extracted: &Path, reference: &Path) -> bool {
+    let mut words = command.split_whitespace();
+    let program = words
+        .next()
+        .expect("Empty --extract-then-verify-equivalence command");
+    std::process::Command::new(program)
+        .args(words)
+        .arg(extracted)
+        .arg(reference)
+        .status()
+        .with_context(|| format!("Failed to run equivalence checker: {command}"))
+        .unwrap()
+        .success()
 }
 
 #[cfg(test)]
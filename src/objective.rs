@@ -0,0 +1,321 @@
+/* A custom objective, an arithmetic expression over per-node metrics like `cost + 0.1*depth`.
+
+The metrics are:
+ - `cost`, the node's cost in the egraph.
+ - `depth`, how many hops the node's class is from the nearest root (0 if none reach it).
+ - `fanout`, how many nodes have the node's class as a child.
+ - `arity` (or `n`), how many children the node has.
+
+Besides `+ - * /`, expressions can use the functions `log2`, `ceil` and `floor`, e.g.
+`ceil(log2(n))` for a reduction tree over the children.
+
+The objective's value for each node becomes its cost, so every extractor can optimize it.
+The same expressions give per-op costs in a cost table.
+*/
+
+use crate::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    Cost,
+    Depth,
+    FanOut,
+    Arity,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Func {
+    Log2,
+    Ceil,
+    Floor,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Objective {
+    Num(f64),
+    Metric(Metric),
+    Neg(Box<Objective>),
+    Add(Box<Objective>, Box<Objective>),
+    Sub(Box<Objective>, Box<Objective>),
+    Mul(Box<Objective>, Box<Objective>),
+    Div(Box<Objective>, Box<Objective>),
+    Call(Func, Box<Objective>),
+}
+
+/// The metrics of one node.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NodeMetrics {
+    pub cost: f64,
+    pub depth: usize,
+    pub fan_out: usize,
+    pub arity: usize,
+}
+
+impl Objective {
+    pub fn eval(&self, metrics: &NodeMetrics) -> f64 {
+        match self {
+            Objective::Num(n) => *n,
+            Objective::Metric(Metric::Cost) => metrics.cost,
+            Objective::Metric(Metric::Depth) => metrics.depth as f64,
+            Objective::Metric(Metric::FanOut) => metrics.fan_out as f64,
+            Objective::Metric(Metric::Arity) => metrics.arity as f64,
+            Objective::Neg(a) => -a.eval(metrics),
+            Objective::Add(a, b) => a.eval(metrics) + b.eval(metrics),
+            Objective::Sub(a, b) => a.eval(metrics) - b.eval(metrics),
+            Objective::Mul(a, b) => a.eval(metrics) * b.eval(metrics),
+            Objective::Div(a, b) => a.eval(metrics) / b.eval(metrics),
+            Objective::Call(Func::Log2, a) => a.eval(metrics).log2(),
+            Objective::Call(Func::Ceil, a) => a.eval(metrics).ceil(),
+            Objective::Call(Func::Floor, a) => a.eval(metrics).floor(),
+        }
+    }
+
+    /// Whether this is a weighted sum of the metrics, which the ILP can take as its
+    /// objective. Products of metrics and dividing by a metric are not.
+    pub fn is_linear(&self) -> bool {
+        self.degree().is_some_and(|d| d <= 1)
+    }
+
+    /// The degree of the expression as a polynomial in the metrics, or `None` for a
+    /// division by a metric or a function of one.
+    fn degree(&self) -> Option<usize> {
+        match self {
+            Objective::Num(_) => Some(0),
+            Objective::Metric(_) => Some(1),
+            Objective::Neg(a) => a.degree(),
+            Objective::Add(a, b) | Objective::Sub(a, b) => Some(a.degree()?.max(b.degree()?)),
+            Objective::Mul(a, b) => Some(a.degree()? + b.degree()?),
+            Objective::Div(a, b) => match b.degree()? {
+                0 => a.degree(),
+                _ => None,
+            },
+            Objective::Call(_, a) => match a.degree()? {
+                0 => Some(0),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+        };
+        let objective = parser.expr()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(format!(
+                "Unexpected {:?} at position {} of objective {s:?}",
+                parser.chars[parser.pos], parser.pos
+            ));
+        }
+        Ok(objective)
+    }
+}
+
+/// A recursive descent parser for `+ - * /`, parentheses, functions, numbers and metric names.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes `c` if it's next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Objective, String> {
+        let mut lhs = self.term()?;
+        loop {
+            if self.eat('+') {
+                lhs = Objective::Add(Box::new(lhs), Box::new(self.term()?));
+            } else if self.eat('-') {
+                lhs = Objective::Sub(Box::new(lhs), Box::new(self.term()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Objective, String> {
+        let mut lhs = self.factor()?;
+        loop {
+            if self.eat('*') {
+                lhs = Objective::Mul(Box::new(lhs), Box::new(self.factor()?));
+            } else if self.eat('/') {
+                lhs = Objective::Div(Box::new(lhs), Box::new(self.factor()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Result<Objective, String> {
+        if self.eat('-') {
+            return Ok(Objective::Neg(Box::new(self.factor()?)));
+        }
+        if self.eat('(') {
+            let inner = self.expr()?;
+            if !self.eat(')') {
+                return Err(format!("Missing ) at position {}", self.pos));
+            }
+            return Ok(inner);
+        }
+
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_alphanumeric() || *c == '.' || *c == '_')
+        {
+            self.pos += 1;
+        }
+        let word: String = self.chars[start..self.pos].iter().collect();
+        let func = match word.as_str() {
+            "log2" => Some(Func::Log2),
+            "ceil" => Some(Func::Ceil),
+            "floor" => Some(Func::Floor),
+            _ => None,
+        };
+        if let Some(func) = func {
+            if !self.eat('(') {
+                return Err(format!("Missing ( after {word} at position {}", self.pos));
+            }
+            let arg = self.expr()?;
+            if !self.eat(')') {
+                return Err(format!("Missing ) at position {}", self.pos));
+            }
+            return Ok(Objective::Call(func, Box::new(arg)));
+        }
+        match word.as_str() {
+            "" => Err(format!("Expected a number or metric at position {start}")),
+            "cost" => Ok(Objective::Metric(Metric::Cost)),
+            "depth" => Ok(Objective::Metric(Metric::Depth)),
+            "fanout" => Ok(Objective::Metric(Metric::FanOut)),
+            "arity" | "n" => Ok(Objective::Metric(Metric::Arity)),
+            _ => word.parse().map(Objective::Num).map_err(|_| {
+                format!("Unknown metric {word}, expected cost, depth, fanout or arity")
+            }),
+        }
+    }
+}
+
+/// One of the cost models --cost-model compares extractions under.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CostModelSpec {
+    /// The costs in the egraph.
+    Sum,
+    /// One per node, for the fewest nodes.
+    Count,
+    /// The costs in the egraph as delays, for the shortest critical path.
+    Latency,
+    /// The costs an objective gives.
+    Objective(Objective),
+}
+
+impl std::str::FromStr for CostModelSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "sum" => Ok(CostModelSpec::Sum),
+            "count" => Ok(CostModelSpec::Count),
+            "latency" => Ok(CostModelSpec::Latency),
+            expr => expr
+                .parse()
+                .map(CostModelSpec::Objective)
+                .map_err(|e| format!("{e}, expected sum, count, latency or an objective")),
+        }
+    }
+}
+
+/// Reads a cost table, a JSON object from op to either a cost or an expression for it,
+/// like `{"Mul": 3, "Add": "ceil(log2(n))"}`. Keys can also be patterns, like
+/// `{"Mul(i64)": 4, "Mul*": 3, "*": 1}`, as `transform::table_entry` matches them.
+pub fn read_cost_table(filename: &str) -> anyhow::Result<IndexMap<String, Objective>> {
+    let json: serde_json::Value =
+        serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(filename)?))?;
+    let Some(table) = json.as_object() else {
+        anyhow::bail!("{filename} should be an object from op to cost");
+    };
+    table
+        .iter()
+        .map(|(op, cost)| {
+            let objective = match cost {
+                serde_json::Value::Number(n) => Objective::Num(n.as_f64().unwrap()),
+                serde_json::Value::String(expr) => expr
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("Bad cost for {op}: {e}"))?,
+                _ => anyhow::bail!("The cost of {op} should be a number or string, not {cost}"),
+            };
+            Ok((op.clone(), objective))
+        })
+        .collect()
+}
+
+/// The metrics of every node in the egraph.
+pub fn node_metrics(egraph: &EGraph) -> FxHashMap<NodeId, NodeMetrics> {
+    let mut depths: FxHashMap<&ClassId, usize> = FxHashMap::default();
+    let mut frontier: Vec<&ClassId> = egraph.root_eclasses.iter().collect();
+    let mut depth = 0;
+    while !frontier.is_empty() {
+        let mut next = vec![];
+        for class_id in frontier {
+            if depths.contains_key(class_id) {
+                continue;
+            }
+            depths.insert(class_id, depth);
+            for node_id in &egraph[class_id].nodes {
+                next.extend(
+                    egraph[node_id]
+                        .children
+                        .iter()
+                        .map(|c| egraph.nid_to_cid(c)),
+                );
+            }
+        }
+        frontier = next;
+        depth += 1;
+    }
+
+    let mut fan_outs: FxHashMap<&ClassId, usize> = FxHashMap::default();
+    for node in egraph.nodes.values() {
+        let children: FxHashSet<&ClassId> =
+            node.children.iter().map(|c| egraph.nid_to_cid(c)).collect();
+        for child in children {
+            *fan_outs.entry(child).or_default() += 1;
+        }
+    }
+
+    egraph
+        .nodes
+        .iter()
+        .map(|(node_id, node)| {
+            let metrics = NodeMetrics {
+                cost: node.cost.into_inner(),
+                depth: depths.get(&node.eclass).copied().unwrap_or(0),
+                fan_out: fan_outs.get(&node.eclass).copied().unwrap_or(0),
+                arity: node.children.len(),
+            };
+            (node_id.clone(), metrics)
+        })
+        .collect()
+}
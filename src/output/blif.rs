@@ -0,0 +1,137 @@
+/* Writes an extraction over boolean ops as a BLIF netlist, e.g. for ABC.
+
+Leaves are `Var("name")` inputs or `Num(0)`/`Num(1)` constants, and the gates are `And`,
+`Or` (with any number of inputs), `Not` and `Mux(select, then, else)`. Roots that are
+`RootNode("name")` become outputs with that name (other roots are called `out0`, `out1`...).
+The gates' own signals are `_n0`, `_n1`..., so no input or output can start with `_n`.
+*/
+
+use super::arg;
+use crate::*;
+use indexmap::IndexSet;
+use rustc_hash::FxHashMap;
+
+pub const MODEL_NAME: &str = "extracted";
+
+/// The start of the name of every signal a gate outputs.
+pub const SIGNAL_PREFIX: &str = "_n";
+
+pub fn to_blif(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<String> {
+    let mut emitter = Emitter {
+        egraph,
+        signals: FxHashMap::default(),
+        inputs: IndexSet::default(),
+        gates: String::new(),
+        next_signal: 0,
+    };
+
+    result.visit(egraph, roots, &mut emitter)?;
+
+    let mut outputs = vec![];
+    let mut buffers = String::new();
+    for (i, root) in roots.iter().enumerate() {
+        let node = &egraph[&result.choices[root]];
+        let (name, signal) = match arg(&node.op, "RootNode") {
+            Some(name) if node.children.len() == 1 => {
+                reserved(name)?;
+                let child = egraph.nid_to_cid(&node.children[0]);
+                (name.to_string(), emitter.signal(child)?)
+            }
+            _ => (format!("out{i}"), emitter.signal(root)?),
+        };
+        buffers.push_str(&format!(".names {signal} {name}\n1 1\n"));
+        outputs.push(name);
+    }
+
+    let inputs: Vec<String> = emitter.inputs.into_iter().collect();
+    Ok(format!(
+        ".model {MODEL_NAME}\n.inputs {}\n.outputs {}\n{}{buffers}.end\n",
+        inputs.join(" "),
+        outputs.join(" "),
+        emitter.gates
+    ))
+}
+
+struct Emitter<'a> {
+    egraph: &'a EGraph,
+    signals: FxHashMap<ClassId, String>,
+    inputs: IndexSet<String>,
+    gates: String,
+    next_signal: usize,
+}
+
+impl Emitter<'_> {
+    /// The name of the signal holding the value of a class that has been left already.
+    fn signal(&self, class_id: &ClassId) -> anyhow::Result<String> {
+        match self.signals.get(class_id) {
+            Some(name) => Ok(name.clone()),
+            None => anyhow::bail!("Class {class_id} is used in its own computation"),
+        }
+    }
+}
+
+impl DagVisitor for Emitter<'_> {
+    /// Declares the gate of a class, whose children have their signals already.
+    fn leave_node(&mut self, class_id: &ClassId, node_id: &NodeId) -> anyhow::Result<()> {
+        let node = &self.egraph[node_id];
+        let op = node.op.as_str();
+
+        let name = if let Some(var) = arg(op, "Var") {
+            reserved(var)?;
+            self.inputs.insert(var.to_string());
+            var.to_string()
+        } else if arg(op, "RootNode").is_some() && node.children.len() == 1 {
+            // the output is buffered from its child's signal
+            return Ok(());
+        } else {
+            let args = node
+                .children
+                .iter()
+                .map(|c| self.signal(self.egraph.nid_to_cid(c)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let table = truth_table(op, args.len())?;
+            let name = format!("{SIGNAL_PREFIX}{}", self.next_signal);
+            self.next_signal += 1;
+            let mut signals = args;
+            signals.push(name.clone());
+            self.gates
+                .push_str(&format!(".names {}\n{table}", signals.join(" ")));
+            name
+        };
+        self.signals.insert(class_id.clone(), name);
+        Ok(())
+    }
+}
+
+/// Fails for a port name a gate's signal could have.
+fn reserved(name: &str) -> anyhow::Result<()> {
+    if name.starts_with(SIGNAL_PREFIX) {
+        anyhow::bail!("Can't name a port {name} in BLIF, {SIGNAL_PREFIX} is kept for the gates");
+    }
+    Ok(())
+}
+
+/// The rows of the gate's truth table for which the output is 1.
+fn truth_table(op: &str, inputs: usize) -> anyhow::Result<String> {
+    let table = match (op, inputs) {
+        _ if arg(op, "Num") == Some("0") && inputs == 0 => String::new(),
+        _ if arg(op, "Num") == Some("1") && inputs == 0 => "1\n".to_string(),
+        ("Not", 1) => "0 1\n".to_string(),
+        ("And", 1..) => format!("{} 1\n", "1".repeat(inputs)),
+        ("Or", 1..) => (0..inputs)
+            .map(|i| {
+                let row: String = (0..inputs)
+                    .map(|j| if i == j { '1' } else { '-' })
+                    .collect();
+                format!("{row} 1\n")
+            })
+            .collect(),
+        ("Mux", 3) => "11- 1\n0-1 1\n".to_string(),
+        _ => anyhow::bail!("Can't write {op} with {inputs} children as a BLIF gate"),
+    };
+    Ok(table)
+}
@@ -0,0 +1,95 @@
+use crate::*;
+use indexmap::IndexSet;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+pub const HEADER: &str = "class_id,min_tree_cost,min_dag_contribution,chosen_node_op";
+
+/// A CSV row for every class reachable from the roots through any node, to plot where the
+/// cost is in the egraph.
+///
+/// `min_tree_cost` is the cost of the cheapest tree for the class, whether or not the
+/// extraction uses it. `min_dag_contribution` is what the class adds to the DAG cost of the
+/// extraction, the cost of its chosen node once, or 0 if the extraction doesn't use the class.
+pub fn to_heatmap_csv(egraph: &EGraph, result: &ExtractionResult, roots: &[ClassId]) -> String {
+    let cheapest = extract::bottom_up::BottomUpExtractor.extract(egraph, roots);
+    let mut tree_costs = FxHashMap::default();
+
+    let mut used = FxHashSet::<&ClassId>::default();
+    let mut todo: Vec<&ClassId> = roots.iter().collect();
+    while let Some(class_id) = todo.pop() {
+        if !used.insert(class_id) {
+            continue;
+        }
+        let node = &egraph[&result.choices[class_id]];
+        todo.extend(node.children.iter().map(|c| egraph.nid_to_cid(c)));
+    }
+
+    let mut reachable = IndexSet::<&ClassId>::default();
+    let mut todo: Vec<&ClassId> = roots.iter().collect();
+    while let Some(class_id) = todo.pop() {
+        if !reachable.insert(class_id) {
+            continue;
+        }
+        for node_id in &egraph[class_id].nodes {
+            todo.extend(
+                egraph[node_id]
+                    .children
+                    .iter()
+                    .map(|c| egraph.nid_to_cid(c)),
+            );
+        }
+    }
+
+    let mut csv = format!("{HEADER}\n");
+    for class_id in reachable {
+        let tree_cost = tree_cost(egraph, &cheapest, class_id, &mut tree_costs)
+            .map_or(String::new(), |c| c.to_string());
+        let chosen = result.choices.get(class_id).map(|n| &egraph[n]);
+        let dag_contribution = match chosen {
+            Some(node) if used.contains(class_id) => node.cost,
+            _ => Cost::default(),
+        };
+        let op = chosen.map_or("", |node| node.op.as_str());
+        csv.push_str(&format!(
+            "{},{tree_cost},{dag_contribution},{}\n",
+            field(&class_id.to_string()),
+            field(op)
+        ));
+    }
+    csv
+}
+
+/// The cost of the cheapest tree of a class, or `None` if it has no tree without a cycle.
+fn tree_cost(
+    egraph: &EGraph,
+    cheapest: &ExtractionResult,
+    class_id: &ClassId,
+    memo: &mut FxHashMap<ClassId, Option<Cost>>,
+) -> Option<Cost> {
+    if let Some(cost) = memo.get(class_id) {
+        return *cost;
+    }
+    let cost = match cheapest.choices.get(class_id) {
+        Some(node_id) => {
+            let node = &egraph[node_id];
+            let mut cost = Some(node.cost);
+            for child in &node.children {
+                let child = tree_cost(egraph, cheapest, egraph.nid_to_cid(child), memo);
+                cost = cost.zip(child).map(|(a, b)| a + b);
+            }
+            cost
+        }
+        None => None,
+    };
+    memo.insert(class_id.clone(), cost);
+    cost
+}
+
+/// Quotes a CSV field if it needs it.
+fn field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
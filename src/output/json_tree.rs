@@ -0,0 +1,50 @@
+use crate::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde_json::{json, Value};
+
+/// The extraction of each root as a nested `{op, cost, children}` object.
+///
+/// Shared subterms are repeated under every parent that uses them, so unlike the
+/// choices map (one entry per class) the output can be exponentially bigger than the DAG.
+pub fn to_json_tree(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<Value> {
+    let mut memo = FxHashMap::default();
+    let mut visiting = FxHashSet::default();
+    let trees = roots
+        .iter()
+        .map(|root| to_json(egraph, result, root, &mut memo, &mut visiting))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(Value::Array(trees))
+}
+
+fn to_json(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    class_id: &ClassId,
+    memo: &mut FxHashMap<ClassId, Value>,
+    visiting: &mut FxHashSet<ClassId>,
+) -> anyhow::Result<Value> {
+    if let Some(v) = memo.get(class_id) {
+        return Ok(v.clone());
+    }
+    if !visiting.insert(class_id.clone()) {
+        anyhow::bail!("The extraction has a cycle through class {class_id}");
+    }
+    let node = &egraph[&result.choices[class_id]];
+    let children = node
+        .children
+        .iter()
+        .map(|c| to_json(egraph, result, egraph.nid_to_cid(c), memo, visiting))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let v = json!({
+        "op": node.op,
+        "cost": node.cost.into_inner(),
+        "children": children,
+    });
+    visiting.remove(class_id);
+    memo.insert(class_id.clone(), v.clone());
+    Ok(v)
+}
@@ -0,0 +1,123 @@
+/* Formats for writing out an extraction, selected with --format. */
+
+use crate::*;
+
+pub mod blif;
+pub mod heatmap;
+pub mod json_tree;
+pub mod opgraph;
+pub mod prometheus;
+pub mod python;
+pub mod report_json;
+pub mod sexpr;
+pub mod tac;
+pub mod tree;
+pub mod verilog;
+pub mod yosys_json;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One s-expression per root.
+    Sexpr,
+    /// One nested JSON object per root.
+    JsonTree,
+    /// A flat list of nodes that refer to their inputs by id.
+    OpGraph,
+    /// A Verilog module, with the roots as outputs.
+    Verilog,
+    /// A BLIF netlist of boolean gates, with the roots as outputs.
+    Blif,
+    /// The choices along with the report and more cost accounting.
+    ReportJson,
+    /// One indented tree per root, drawing shared subtrees once.
+    Tree,
+    /// A Python function of the variables that returns the roots.
+    Python,
+    /// Three-address code, with a temporary for each value computed.
+    Tac,
+    /// One SSA basic block in LLVM-ish syntax, taking the vars and returning the roots.
+    Ssa,
+    /// The report on a single line, so the reports of runs over many files stream as
+    /// newline-delimited JSON.
+    Jsonl,
+    /// A Yosys JSON netlist, with a cell per op and the roots as outputs.
+    YosysJson,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sexpr" => Ok(OutputFormat::Sexpr),
+            "json-tree" => Ok(OutputFormat::JsonTree),
+            "opgraph" => Ok(OutputFormat::OpGraph),
+            "verilog" => Ok(OutputFormat::Verilog),
+            "blif" => Ok(OutputFormat::Blif),
+            "report-json" => Ok(OutputFormat::ReportJson),
+            "tree" => Ok(OutputFormat::Tree),
+            "python" => Ok(OutputFormat::Python),
+            "tac" => Ok(OutputFormat::Tac),
+            "ssa" => Ok(OutputFormat::Ssa),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "yosys-json" => Ok(OutputFormat::YosysJson),
+            _ => Err(format!("Unknown output format: {s}")),
+        }
+    }
+}
+
+/// The argument of an op like `Var("x")` or `Num(3)`, without quotes.
+pub fn arg<'a>(op: &'a str, name: &str) -> Option<&'a str> {
+    let inner = op
+        .strip_prefix(name)?
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    Some(inner.trim().trim_matches('"'))
+}
+
+/// An op without its width, and the width, for ops that give one as a `:N` suffix like
+/// `Add:16` or `Var("x"):16`.
+pub fn split_width(op: &str) -> (&str, Option<u32>) {
+    match op.rsplit_once(':') {
+        Some((head, width)) => match width.parse() {
+            Ok(width) if width > 0 => (head, Some(width)),
+            _ => (op, None),
+        },
+        None => (op, None),
+    }
+}
+
+pub fn render(
+    format: OutputFormat,
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+    report: &serde_json::Value,
+) -> anyhow::Result<String> {
+    match format {
+        OutputFormat::Sexpr => Ok(sexpr::to_sexprs(egraph, result, roots)),
+        OutputFormat::JsonTree => {
+            let trees = json_tree::to_json_tree(egraph, result, roots)?;
+            Ok(serde_json::to_string_pretty(&trees)? + "\n")
+        }
+        OutputFormat::OpGraph => {
+            let graph = opgraph::to_opgraph(egraph, result, roots)?;
+            Ok(serde_json::to_string_pretty(&graph)? + "\n")
+        }
+        OutputFormat::Verilog => Ok(verilog::to_verilog(egraph, result, roots)?.module),
+        OutputFormat::Blif => blif::to_blif(egraph, result, roots),
+        OutputFormat::ReportJson => {
+            let rich = report_json::to_report_json(egraph, result, roots, report)?;
+            Ok(serde_json::to_string_pretty(&rich)? + "\n")
+        }
+        OutputFormat::Tree => tree::to_tree(egraph, result, roots),
+        OutputFormat::Python => python::to_python(egraph, result, roots),
+        OutputFormat::Tac => tac::to_tac(egraph, result, roots),
+        OutputFormat::Ssa => tac::to_ssa(egraph, result, roots),
+        OutputFormat::Jsonl => Ok(serde_json::to_string(report)? + "\n"),
+        OutputFormat::YosysJson => {
+            let netlist = yosys_json::to_yosys_json(egraph, result, roots)?;
+            Ok(serde_json::to_string_pretty(&netlist)? + "\n")
+        }
+    }
+}
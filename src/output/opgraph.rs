@@ -0,0 +1,63 @@
+use crate::*;
+use rustc_hash::FxHashSet;
+use serde_json::{json, Value};
+
+/// The chosen DAG as a flat op graph, like ML graph formats use: a list of
+/// `{id, op, inputs, attrs}` nodes where `inputs` are the ids of the chosen children, and
+/// the ids of the roots' nodes as `outputs`.
+///
+/// Each node comes after its inputs, and shared nodes are listed once.
+pub fn to_opgraph(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<Value> {
+    let mut graph = OpGraph {
+        egraph,
+        result,
+        nodes: vec![],
+        left: FxHashSet::default(),
+    };
+    result.visit(egraph, roots, &mut graph)?;
+    let outputs: Vec<String> = roots
+        .iter()
+        .map(|root| result.choices[root].to_string())
+        .collect();
+    Ok(json!({
+        "nodes": graph.nodes,
+        "outputs": outputs,
+    }))
+}
+
+struct OpGraph<'a> {
+    egraph: &'a EGraph,
+    result: &'a ExtractionResult,
+    nodes: Vec<Value>,
+    left: FxHashSet<ClassId>,
+}
+
+impl DagVisitor for OpGraph<'_> {
+    /// Adds the chosen node of a class, after its inputs.
+    fn leave_node(&mut self, class_id: &ClassId, node_id: &NodeId) -> anyhow::Result<()> {
+        let node = &self.egraph[node_id];
+        let mut inputs = vec![];
+        for child in &node.children {
+            let child_class = self.egraph.nid_to_cid(child);
+            if !self.left.contains(child_class) {
+                anyhow::bail!("The extraction has a cycle through class {child_class}");
+            }
+            inputs.push(self.result.choices[child_class].to_string());
+        }
+        self.nodes.push(json!({
+            "id": node_id.to_string(),
+            "op": node.op,
+            "inputs": inputs,
+            "attrs": {
+                "eclass": class_id.to_string(),
+                "cost": node.cost.into_inner(),
+            },
+        }));
+        self.left.insert(class_id.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+/* Metrics of a run in the Prometheus text exposition format, for node_exporter's textfile
+collector. The metric names are stable, and each is labeled with the extractor.
+*/
+
+use crate::*;
+use rustc_hash::FxHashSet;
+
+pub fn to_prometheus(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+    extractor_name: &str,
+    micros: u128,
+) -> String {
+    let metrics = [
+        (
+            "extraction_gym_duration_seconds",
+            "How long the extraction took.",
+            micros as f64 / 1e6,
+        ),
+        (
+            "extraction_gym_dag_cost",
+            "The dag cost of the extraction.",
+            result.dag_cost(egraph, roots).into_inner(),
+        ),
+        (
+            "extraction_gym_tree_cost",
+            "The tree cost of the extraction.",
+            result.tree_cost(egraph, roots).into_inner(),
+        ),
+        (
+            "extraction_gym_nodes",
+            "How many nodes the extraction uses, counting shared nodes once.",
+            used_nodes(egraph, result, roots) as f64,
+        ),
+    ];
+
+    let label = escape_label(extractor_name);
+    let mut out = String::new();
+    for (name, help, value) in metrics {
+        out.push_str(&format!(
+            "# HELP {name} {help}\n# TYPE {name} gauge\n{name}{{extractor=\"{label}\"}} {value}\n"
+        ));
+    }
+    out
+}
+
+/// Writes the metrics next to `path` first and renames them over it, so the collector never
+/// reads a half written file.
+pub fn write_metrics_file(path: &std::path::Path, metrics: &str) -> std::io::Result<()> {
+    let tmp = path.with_extension("prom.tmp");
+    std::fs::write(&tmp, metrics)?;
+    std::fs::rename(&tmp, path)
+}
+
+fn used_nodes(egraph: &EGraph, result: &ExtractionResult, roots: &[ClassId]) -> usize {
+    let mut used: FxHashSet<&ClassId> = FxHashSet::default();
+    let mut todo: Vec<&ClassId> = roots.iter().collect();
+    while let Some(class_id) = todo.pop() {
+        if used.insert(class_id) {
+            let node = &egraph[&result.choices[class_id]];
+            todo.extend(node.children.iter().map(|c| egraph.nid_to_cid(c)));
+        }
+    }
+    used.len()
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,91 @@
+/* Writes an extraction over bit-vector ops as a Python function, to run it on test values.
+
+Leaves are `Var("name")` parameters or `Num(n)` constants, and the function returns the
+roots in order. Roots that are `RootNode("name")` are assigned to that name first, and
+other values to the temporaries `_t0`, `_t1`, ... The values are plain Python ints, so `~`
+is the signed complement rather than a fixed width's.
+*/
+
+use super::verilog::expression;
+use super::{arg, split_width};
+use crate::*;
+use indexmap::IndexSet;
+use rustc_hash::FxHashMap;
+
+pub const FUNCTION_NAME: &str = "f";
+
+pub fn to_python(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<String> {
+    let mut emitter = Emitter {
+        egraph,
+        result,
+        names: FxHashMap::default(),
+        params: IndexSet::default(),
+        body: String::new(),
+        next_name: 0,
+    };
+
+    let mut returned = vec![];
+    for root in roots {
+        let node = &egraph[&result.choices[root]];
+        match arg(split_width(&node.op).0, "RootNode") {
+            Some(name) if node.children.len() == 1 => {
+                let value = emitter.name(egraph.nid_to_cid(&node.children[0]))?;
+                emitter.body.push_str(&format!("    {name} = {value}\n"));
+                returned.push(name.to_string());
+            }
+            _ => returned.push(emitter.name(root)?),
+        }
+    }
+
+    let params = emitter.params.into_iter().collect::<Vec<_>>().join(", ");
+    Ok(format!(
+        "def {FUNCTION_NAME}({params}):\n{}    return {}\n",
+        emitter.body,
+        returned.join(", ")
+    ))
+}
+
+struct Emitter<'a> {
+    egraph: &'a EGraph,
+    result: &'a ExtractionResult,
+    names: FxHashMap<ClassId, String>,
+    params: IndexSet<String>,
+    body: String,
+    next_name: usize,
+}
+
+impl Emitter<'_> {
+    /// The name (or constant) holding the value of a class, assigning it and everything it
+    /// depends on first.
+    fn name(&mut self, class_id: &ClassId) -> anyhow::Result<String> {
+        if let Some(name) = self.names.get(class_id) {
+            return Ok(name.clone());
+        }
+        let node = &self.egraph[&self.result.choices[class_id]];
+        let op = split_width(&node.op).0;
+
+        let name = if let Some(var) = arg(op, "Var") {
+            self.params.insert(var.to_string());
+            var.to_string()
+        } else if let Some(num) = arg(op, "Num") {
+            num.to_string()
+        } else {
+            let args = node
+                .children
+                .iter()
+                .map(|c| self.name(self.egraph.nid_to_cid(c)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let expr = expression(op, &args, "Python")?;
+            let name = format!("_t{}", self.next_name);
+            self.next_name += 1;
+            self.body.push_str(&format!("    {name} = {expr}\n"));
+            name
+        };
+        self.names.insert(class_id.clone(), name.clone());
+        Ok(name)
+    }
+}
@@ -0,0 +1,149 @@
+use crate::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde_json::{json, Map, Value};
+
+/// Everything about an extraction in one object, so it doesn't take several runs to get.
+///
+/// `report` is what gets written to --out. Its costs and timing are always included, the
+/// sections that come from flags (dims, critical path, attribution, gap, exclusion,
+/// sources, equivalence, throughput) only when the flag was given.
+pub fn to_report_json(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+    report: &Value,
+) -> anyhow::Result<Value> {
+    let choices: Map<String, Value> = result
+        .choices
+        .iter()
+        .map(|(class_id, node_id)| (class_id.to_string(), node_id.to_string().into()))
+        .collect();
+
+    let mut used = Used::default();
+    for root in roots {
+        used.visit(egraph, result, root)?;
+    }
+    let depth = roots.iter().map(|r| used.depths[r]).max().unwrap_or(0);
+
+    let mut ops = IndexMap::<&str, (usize, Cost)>::default();
+    for node_id in used.parents.keys() {
+        let node = &egraph[*node_id];
+        let (count, cost) = ops.entry(&node.op).or_insert((0, Cost::default()));
+        *count += 1;
+        *cost += node.cost;
+    }
+    let ops: Map<String, Value> = ops
+        .into_iter()
+        .map(|(op, (count, cost))| {
+            (
+                op.to_string(),
+                json!({ "count": count, "cost": cost.into_inner() }),
+            )
+        })
+        .collect();
+
+    let shared = used.parents.values().filter(|&&p| p > 1).count();
+    let tree = result.tree_cost(egraph, roots);
+    let dag = result.dag_cost(egraph, roots);
+
+    let mut rich = json!({
+        "name": report["name"],
+        "extractor": report["extractor"],
+        "choices": choices,
+        "cost": {
+            "tree": tree.into_inner(),
+            "dag": dag.into_inner(),
+            "depth": depth,
+        },
+        "ops": ops,
+        "sharing": {
+            "nodes": used.parents.len(),
+            "shared_nodes": shared,
+            "saved": (tree - dag).into_inner(),
+        },
+        "timing": { "micros": report["micros"] },
+    });
+
+    let section = |keys: &[&str]| -> Option<Value> {
+        let fields: Map<String, Value> = keys
+            .iter()
+            .filter_map(|&key| Some((key.to_string(), report.get(key)?.clone())))
+            .collect();
+        (!fields.is_empty()).then_some(fields.into())
+    };
+    if let Some(dims) = report.get("dims") {
+        rich["cost"]["dims"] = dims.clone();
+    }
+    if let Some(critical_path) = report.get("critical_path") {
+        rich["cost"]["critical_path"] = critical_path.clone();
+    }
+    if let Some(attribution) = report.get("attribution") {
+        rich["attribution"] = attribution.clone();
+    }
+    if let Some(gap) = section(&["optimal_dag", "gap", "gap_is_upper_bound"]) {
+        rich["gap"] = gap;
+    }
+    if let Some(exclusion) = section(&["removed_nodes", "unextractable_classes"]) {
+        rich["exclusion"] = exclusion;
+    }
+    if let Some(sources) = report.get("sources") {
+        rich["sources"] = sources.clone();
+    }
+    if let Some(equivalent) = report.get("equivalent") {
+        rich["equivalent"] = equivalent.clone();
+    }
+    if let Some(throughput) = report.get("throughput") {
+        rich["throughput"] = throughput.clone();
+    }
+    Ok(rich)
+}
+
+/// The choices of an extraction from its report-json.
+pub fn read_choices(rich: &Value) -> anyhow::Result<ExtractionResult> {
+    let Some(choices) = rich["choices"].as_object() else {
+        anyhow::bail!("Expected the choices of a report-json");
+    };
+    let mut result = ExtractionResult::default();
+    for (class_id, node_id) in choices {
+        let Some(node_id) = node_id.as_str() else {
+            anyhow::bail!("The choice for class {class_id} should be a node id, not {node_id}");
+        };
+        result.choose(class_id.as_str().into(), node_id.into());
+    }
+    Ok(result)
+}
+
+/// The chosen nodes reachable from the roots.
+#[derive(Default)]
+struct Used<'a> {
+    /// How many chosen nodes (or roots) use each node.
+    parents: IndexMap<&'a NodeId, usize>,
+    /// The most nodes on a path from each class to a leaf.
+    depths: FxHashMap<&'a ClassId, usize>,
+    visiting: FxHashSet<&'a ClassId>,
+}
+
+impl<'a> Used<'a> {
+    fn visit(
+        &mut self,
+        egraph: &'a EGraph,
+        result: &'a ExtractionResult,
+        class_id: &'a ClassId,
+    ) -> anyhow::Result<usize> {
+        let node_id = &result.choices[class_id];
+        *self.parents.entry(node_id).or_default() += 1;
+        if let Some(&depth) = self.depths.get(class_id) {
+            return Ok(depth);
+        }
+        if !self.visiting.insert(class_id) {
+            anyhow::bail!("The extraction has a cycle through class {class_id}");
+        }
+        let mut depth = 0;
+        for child in &egraph[node_id].children {
+            depth = depth.max(self.visit(egraph, result, egraph.nid_to_cid(child))?);
+        }
+        self.visiting.remove(class_id);
+        self.depths.insert(class_id, depth + 1);
+        Ok(depth + 1)
+    }
+}
@@ -0,0 +1,41 @@
+use crate::*;
+use rustc_hash::FxHashMap;
+
+/// Prints the extraction of each root as an s-expression, one root per line.
+///
+/// Shared subterms are repeated, so the output is the tree, not the DAG,
+/// and can be exponentially bigger than the extraction itself.
+pub fn to_sexprs(egraph: &EGraph, result: &ExtractionResult, roots: &[ClassId]) -> String {
+    let mut memo = FxHashMap::default();
+    let mut out = String::new();
+    for root in roots {
+        out.push_str(&to_sexpr(egraph, result, root, &mut memo));
+        out.push('\n');
+    }
+    out
+}
+
+// this will loop if there are cycles
+fn to_sexpr(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    class_id: &ClassId,
+    memo: &mut FxHashMap<ClassId, String>,
+) -> String {
+    if let Some(s) = memo.get(class_id) {
+        return s.clone();
+    }
+    let node = &egraph[&result.choices[class_id]];
+    let s = if node.is_leaf() {
+        node.op.clone()
+    } else {
+        let children = node
+            .children
+            .iter()
+            .map(|c| to_sexpr(egraph, result, egraph.nid_to_cid(c), memo))
+            .collect::<Vec<_>>();
+        format!("({} {})", node.op, children.join(" "))
+    };
+    memo.insert(class_id.clone(), s.clone());
+    s
+}
@@ -0,0 +1,192 @@
+/* Writes an extraction as three-address code, one instruction per line:
+
+    t0 = MUL x, 3
+    t1 = ADD t0, y
+    out = t1
+    OUTPUT out
+
+The opcode is the op in capitals, without its arguments or width, and any arguments of the
+op itself come after the children's operands. Temporaries are numbered in the order they're
+computed, children before their parents and roots in order, so the same extraction always
+gets the same numbers. `Var("x")` leaves are the operand `x`, and `Num(n)` and any other
+leaves are inlined as constants. A root that is `RootNode("name")` is assigned to that name
+before being output.
+
+The SSA variant is the same instructions as a single LLVM-style basic block, with the vars as
+its arguments and the roots as the operands of its `ret`:
+
+    block(%x, %y):
+      %0 = mul %x, 3
+      %1 = add %0, %y
+      ret %1
+
+Ops are spelled in lower case, with the signed or logical instruction where LLVM has more
+than one, and `Neg` as `sub 0, x` (`FNeg` is already `fneg`). `RootNode`s are skipped,
+since values in SSA have no names.
+*/
+
+use super::{arg, split_width};
+use crate::*;
+use rustc_hash::FxHashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    Tac,
+    Ssa,
+}
+
+pub fn to_tac(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<String> {
+    let mut emitter = Emitter::new(egraph, result, Syntax::Tac);
+    result.visit(egraph, roots, &mut emitter)?;
+    Ok(emitter.code)
+}
+
+pub fn to_ssa(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<String> {
+    let mut emitter = Emitter::new(egraph, result, Syntax::Ssa);
+    result.visit(egraph, roots, &mut emitter)?;
+    Ok(format!(
+        "block({}):\n{}  ret {}\n",
+        emitter.arguments.join(", "),
+        emitter.code,
+        emitter.outputs.join(", ")
+    ))
+}
+
+/// The LLVM instruction for an op and its operands, for ops whose name isn't already one.
+/// `fneg` is only for floats, so an integer `neg` is subtracted from zero.
+fn llvm_instruction(opcode: &str, mut operands: Vec<String>) -> (String, Vec<String>) {
+    let opcode = opcode.to_lowercase();
+    let opcode = match opcode.as_str() {
+        "div" => "sdiv".to_string(),
+        "rem" | "mod" => "srem".to_string(),
+        "shr" => "lshr".to_string(),
+        "neg" => {
+            operands.insert(0, "0".to_string());
+            "sub".to_string()
+        }
+        _ => opcode,
+    };
+    (opcode, operands)
+}
+
+struct Emitter<'a> {
+    egraph: &'a EGraph,
+    result: &'a ExtractionResult,
+    syntax: Syntax,
+    operands: FxHashMap<ClassId, String>,
+    /// The vars, in the order they're first used.
+    arguments: Vec<String>,
+    /// The operands the SSA block returns.
+    outputs: Vec<String>,
+    code: String,
+    next_temporary: usize,
+}
+
+impl<'a> Emitter<'a> {
+    fn new(egraph: &'a EGraph, result: &'a ExtractionResult, syntax: Syntax) -> Self {
+        Emitter {
+            egraph,
+            result,
+            syntax,
+            operands: FxHashMap::default(),
+            arguments: vec![],
+            outputs: vec![],
+            code: String::new(),
+            next_temporary: 0,
+        }
+    }
+
+    /// The operand holding the value of a class that has been left already.
+    fn operand(&self, class_id: &ClassId) -> anyhow::Result<String> {
+        match self.operands.get(class_id) {
+            Some(operand) => Ok(operand.clone()),
+            None => anyhow::bail!("Class {class_id} is used in its own computation"),
+        }
+    }
+}
+
+impl DagVisitor for Emitter<'_> {
+    /// Computes the value of a class, whose children have their operands already.
+    fn leave_node(&mut self, class_id: &ClassId, node_id: &NodeId) -> anyhow::Result<()> {
+        let node = &self.egraph[node_id];
+        let op = split_width(&node.op).0;
+
+        let operand = if let Some(var) = arg(op, "Var") {
+            match self.syntax {
+                Syntax::Tac => var.to_string(),
+                Syntax::Ssa => {
+                    let argument = format!("%{var}");
+                    if !self.arguments.contains(&argument) {
+                        self.arguments.push(argument.clone());
+                    }
+                    argument
+                }
+            }
+        } else if let Some(num) = arg(op, "Num") {
+            num.to_string()
+        } else if node.children.is_empty() {
+            op.to_string()
+        } else if arg(op, "RootNode").is_some() && node.children.len() == 1 {
+            // leave_root names it, the value is its child's
+            self.operand(self.egraph.nid_to_cid(&node.children[0]))?
+        } else {
+            let mut operands = node
+                .children
+                .iter()
+                .map(|c| self.operand(self.egraph.nid_to_cid(c)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let (opcode, op_args) = match op.split_once('(') {
+                Some((head, rest)) => (head, rest.strip_suffix(')').unwrap_or(rest)),
+                None => (op, ""),
+            };
+            operands.extend(
+                op_args
+                    .split(',')
+                    .map(|a| a.trim().trim_matches('"'))
+                    .filter(|a| !a.is_empty())
+                    .map(str::to_string),
+            );
+            let (temporary, line) = match self.syntax {
+                Syntax::Tac => {
+                    let temporary = format!("t{}", self.next_temporary);
+                    let opcode = opcode.to_uppercase();
+                    let line = format!("{temporary} = {opcode} {}\n", operands.join(", "));
+                    (temporary, line)
+                }
+                Syntax::Ssa => {
+                    let temporary = format!("%{}", self.next_temporary);
+                    let (opcode, operands) = llvm_instruction(opcode, operands);
+                    let line = format!("  {temporary} = {opcode} {}\n", operands.join(", "));
+                    (temporary, line)
+                }
+            };
+            self.next_temporary += 1;
+            self.code.push_str(&line);
+            temporary
+        };
+        self.operands.insert(class_id.clone(), operand);
+        Ok(())
+    }
+
+    fn leave_root(&mut self, root: &ClassId) -> anyhow::Result<()> {
+        let value = self.operand(root)?;
+        let node = &self.egraph[&self.result.choices[root]];
+        match (self.syntax, arg(split_width(&node.op).0, "RootNode")) {
+            (Syntax::Tac, Some(name)) if node.children.len() == 1 => {
+                self.code
+                    .push_str(&format!("{name} = {value}\nOUTPUT {name}\n"));
+            }
+            (Syntax::Tac, _) => self.code.push_str(&format!("OUTPUT {value}\n")),
+            (Syntax::Ssa, _) => self.outputs.push(value),
+        }
+        Ok(())
+    }
+}
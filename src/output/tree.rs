@@ -0,0 +1,63 @@
+use crate::*;
+use rustc_hash::FxHashSet;
+
+/// Draws the extraction of each root as an indented tree, one op per line.
+///
+/// A subtree that was already drawn, whether under an earlier root or elsewhere in the
+/// same one, is only drawn again as its op followed by `* (shared)`.
+pub fn to_tree(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<String> {
+    let mut tree = Tree {
+        egraph,
+        result,
+        out: String::new(),
+        drawn: FxHashSet::default(),
+        visiting: FxHashSet::default(),
+    };
+    for root in roots {
+        tree.draw(root, "", "")?;
+    }
+    Ok(tree.out)
+}
+
+struct Tree<'a> {
+    egraph: &'a EGraph,
+    result: &'a ExtractionResult,
+    out: String,
+    drawn: FxHashSet<&'a ClassId>,
+    visiting: FxHashSet<&'a ClassId>,
+}
+
+impl<'a> Tree<'a> {
+    /// Draws a class's line after `branch`, and its children with `indent` before theirs.
+    fn draw(&mut self, class_id: &'a ClassId, branch: &str, indent: &str) -> anyhow::Result<()> {
+        let node = &self.egraph[&self.result.choices[class_id]];
+        if self.drawn.contains(class_id) {
+            self.out
+                .push_str(&format!("{indent}{branch}{} * (shared)\n", node.op));
+            return Ok(());
+        }
+        if !self.visiting.insert(class_id) {
+            anyhow::bail!("The extraction has a cycle through class {class_id}");
+        }
+        self.out.push_str(&format!("{indent}{branch}{}\n", node.op));
+
+        let child_indent = match branch {
+            "├── " => format!("{indent}│   "),
+            "└── " => format!("{indent}    "),
+            _ => indent.to_string(),
+        };
+        for (i, child) in node.children.iter().enumerate() {
+            let last = i + 1 == node.children.len();
+            let child_branch = if last { "└── " } else { "├── " };
+            self.draw(self.egraph.nid_to_cid(child), child_branch, &child_indent)?;
+        }
+
+        self.visiting.remove(class_id);
+        self.drawn.insert(class_id);
+        Ok(())
+    }
+}
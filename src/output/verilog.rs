@@ -0,0 +1,276 @@
+/* Writes an extraction over bit-vector ops as a Verilog module.
+
+Leaves are `Var("name")` inputs or `Num(n)` constants, and roots that are
+`RootNode("name")` become outputs with that name (other roots are called `out0`, `out1`...).
+Every wire is `WIDTH` bits wide, unless the ops give their widths as a `:N` suffix, like
+`Add:16`. Then each wire, input and output is as wide as its node, and nodes without a
+width are 1 bit wide.
+*/
+
+use super::{arg, split_width};
+use crate::*;
+use indexmap::IndexSet;
+use rustc_hash::FxHashMap;
+
+pub const MODULE_NAME: &str = "extracted";
+pub const DEFAULT_WIDTH: u32 = 8;
+
+/// The module's ports, in declaration order.
+#[derive(Debug, Default)]
+pub struct Ports {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// The widths of the ports whose ops give one, the others are `WIDTH` bits wide.
+    pub widths: FxHashMap<String, u32>,
+}
+
+pub struct Verilog {
+    pub module: String,
+    pub ports: Ports,
+}
+
+pub fn to_verilog(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<Verilog> {
+    let mut emitter = Emitter {
+        egraph,
+        wires: FxHashMap::default(),
+        widths: FxHashMap::default(),
+        inputs: IndexSet::default(),
+        body: String::new(),
+        next_wire: 0,
+        sized: result
+            .choices
+            .values()
+            .any(|node_id| split_width(&egraph[node_id].op).1.is_some()),
+    };
+
+    result.visit(egraph, roots, &mut emitter)?;
+
+    let mut outputs = vec![];
+    let mut output_widths = vec![];
+    let mut assigns = String::new();
+    for (i, root) in roots.iter().enumerate() {
+        let node = &egraph[&result.choices[root]];
+        let (name, class_id) = match arg(split_width(&node.op).0, "RootNode") {
+            Some(name) if node.children.len() == 1 => {
+                (name.to_string(), egraph.nid_to_cid(&node.children[0]))
+            }
+            _ => (format!("out{i}"), root),
+        };
+        let value = emitter.wire(class_id)?;
+        assigns.push_str(&format!("    assign {name} = {value};\n"));
+        if let Some(width) = emitter.widths[class_id] {
+            output_widths.push((name.clone(), width));
+        }
+        outputs.push(name);
+    }
+
+    let mut widths = emitter.input_widths();
+    widths.extend(output_widths);
+    let ports = Ports {
+        inputs: emitter.inputs.into_iter().collect(),
+        outputs,
+        widths,
+    };
+    let declarations = ports
+        .inputs
+        .iter()
+        .map(|name| format!("    input {} {name}", ports.range(name)))
+        .chain(
+            ports
+                .outputs
+                .iter()
+                .map(|name| format!("    output {} {name}", ports.range(name))),
+        )
+        .collect::<Vec<_>>()
+        .join(",\n");
+
+    let module = format!(
+        "module {MODULE_NAME} #(parameter WIDTH = {DEFAULT_WIDTH}) (\n{declarations}\n);\n{}{assigns}endmodule\n",
+        emitter.body
+    );
+    Ok(Verilog { module, ports })
+}
+
+struct Emitter<'a> {
+    egraph: &'a EGraph,
+    wires: FxHashMap<ClassId, String>,
+    /// The width of each class's wire, if the ops give widths.
+    widths: FxHashMap<ClassId, Option<u32>>,
+    inputs: IndexSet<String>,
+    body: String,
+    next_wire: usize,
+    /// Whether some op gives its width.
+    sized: bool,
+}
+
+impl Emitter<'_> {
+    /// The name of the wire (or input, or constant) holding the value of a class that has
+    /// been left already.
+    fn wire(&self, class_id: &ClassId) -> anyhow::Result<String> {
+        match self.wires.get(class_id) {
+            Some(name) => Ok(name.clone()),
+            None => anyhow::bail!("Class {class_id} is used in its own computation"),
+        }
+    }
+
+    /// The widths of the inputs whose `Var` gives one.
+    fn input_widths(&self) -> FxHashMap<String, u32> {
+        self.wires
+            .iter()
+            .filter(|(_, name)| self.inputs.contains(*name))
+            .filter_map(|(class_id, name)| Some((name.clone(), self.widths[class_id]?)))
+            .collect()
+    }
+}
+
+impl DagVisitor for Emitter<'_> {
+    /// Declares the wire of a class, whose children have theirs already.
+    fn leave_node(&mut self, class_id: &ClassId, node_id: &NodeId) -> anyhow::Result<()> {
+        let node = &self.egraph[node_id];
+        let (op, width) = split_width(&node.op);
+        if arg(op, "RootNode").is_some() && node.children.len() == 1 {
+            // the output is assigned its child's wire
+            return Ok(());
+        }
+        let width = match width {
+            Some(width) if self.sized => Some(width),
+            None if self.sized => {
+                log::warn!("Node {node_id} ({op}) has no width, making it 1 bit wide");
+                Some(1)
+            }
+            _ => None,
+        };
+
+        let name = if let Some(var) = arg(op, "Var") {
+            self.inputs.insert(var.to_string());
+            var.to_string()
+        } else if let Some(num) = arg(op, "Num") {
+            num.to_string()
+        } else {
+            let args = node
+                .children
+                .iter()
+                .map(|c| self.wire(self.egraph.nid_to_cid(c)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let expr = expression(op, &args, "Verilog")?;
+            let name = format!("n{}", self.next_wire);
+            self.next_wire += 1;
+            self.body
+                .push_str(&format!("    wire {} {name} = {expr};\n", range(width)));
+            name
+        };
+        self.wires.insert(class_id.clone(), name);
+        self.widths.insert(class_id.clone(), width);
+        Ok(())
+    }
+}
+
+impl Ports {
+    /// The range to declare a port with.
+    fn range(&self, port: &str) -> String {
+        range(self.widths.get(port).copied())
+    }
+}
+
+/// The range to declare a wire of some width with, or `WIDTH` bits without one.
+fn range(width: Option<u32>) -> String {
+    match width {
+        Some(width) => format!("[{}:0]", width - 1),
+        None => "[WIDTH-1:0]".to_string(),
+    }
+}
+
+/// The expression for an op applied to its arguments, following `print_assignments`. Python
+/// has the same operators, so it's used for `--format python` too.
+pub(super) fn expression(op: &str, args: &[String], language: &str) -> anyhow::Result<String> {
+    let infix = |symbol: &str| args.join(&format!(" {symbol} "));
+    // The constant operand of some unary ops is part of the op, e.g. `Shl(_, 2)`.
+    let inline_operand = |after: &str, default: &str| {
+        op.split(after)
+            .nth(1)
+            .and_then(|rest| rest.split(')').next())
+            .map_or(default.to_string(), |s| s.trim().to_string())
+    };
+
+    let expr = if args.is_empty() {
+        anyhow::bail!("Can't write leaf {op} as {language}");
+    } else if op.starts_with("Not") && args.len() == 1 {
+        format!("~{}", args[0])
+    } else if op.starts_with("Mul") && args.len() == 1 {
+        format!("{} * {}", args[0], inline_operand("Num(", "1"))
+    } else if op.starts_with("Shl") && args.len() == 1 {
+        format!("{} << {}", args[0], inline_operand(",", "1"))
+    } else if op.starts_with("Shr") && args.len() == 1 {
+        format!("{} >> {}", args[0], inline_operand(",", "1"))
+    } else if op.starts_with("Add") {
+        infix("+")
+    } else if op.starts_with("Mul") {
+        infix("*")
+    } else if op.starts_with("And") {
+        infix("&")
+    } else if op.starts_with("Or") {
+        infix("|")
+    } else if op.starts_with("Shl") && args.len() == 2 {
+        infix("<<")
+    } else if op.starts_with("Shr") && args.len() == 2 {
+        infix(">>")
+    } else {
+        anyhow::bail!(
+            "Can't write {op} with {} children as {language}",
+            args.len()
+        );
+    };
+    Ok(expr)
+}
+
+/// A testbench that instantiates the module, drives every input, and displays every port.
+pub fn testbench(ports: &Ports) -> String {
+    let mut tb = format!(
+        "`timescale 1ns/1ps\n\nmodule {MODULE_NAME}_tb;\n    localparam WIDTH = {DEFAULT_WIDTH};\n\n"
+    );
+    for input in &ports.inputs {
+        tb.push_str(&format!("    reg {} {input};\n", ports.range(input)));
+    }
+    for output in &ports.outputs {
+        tb.push_str(&format!("    wire {} {output};\n", ports.range(output)));
+    }
+
+    let connections = ports
+        .inputs
+        .iter()
+        .chain(&ports.outputs)
+        .map(|port| format!(".{port}({port})"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    tb.push_str(&format!(
+        "\n    {MODULE_NAME} #(.WIDTH(WIDTH)) dut ({connections});\n\n    initial begin\n"
+    ));
+
+    let format = ports
+        .inputs
+        .iter()
+        .chain(&ports.outputs)
+        .map(|port| format!("{port}=%0d"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let values = ports
+        .inputs
+        .iter()
+        .chain(&ports.outputs)
+        .map(|port| format!(", {port}"))
+        .collect::<String>();
+    for input in &ports.inputs {
+        tb.push_str(&format!("        {input} = 0;\n"));
+    }
+    tb.push_str(&format!("        #1 $display(\"{format}\"{values});\n"));
+    for input in &ports.inputs {
+        tb.push_str(&format!("        {input} = $random;\n"));
+    }
+    tb.push_str(&format!("        #1 $display(\"{format}\"{values});\n"));
+    tb.push_str("        $finish;\n    end\nendmodule\n");
+    tb
+}
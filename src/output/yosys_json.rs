@@ -0,0 +1,263 @@
+/* Writes an extraction over bit-vector ops as a Yosys JSON netlist, to read with
+`read_json`.
+
+The ports, leaves and widths are as for `--format verilog`: `Var("name")` inputs, `Num(n)`
+constants, `RootNode("name")` outputs, and `WIDTH` bits per signal unless the ops give
+their widths as a `:N` suffix. Each other node becomes one internal cell, `$add`, `$and`,
+`$mux` and so on, or a chain of them for `Add`, `And`... with more than two children.
+Signals are lists of bit numbers from 2 up, as Yosys numbers them, and constants are lists
+of "0" and "1" bits. The cells are named `$extract$0`, `$extract$1`... so they can't clash
+with a port, as Yosys keeps names starting with `$` for ones it made up itself.
+*/
+
+use super::{arg, split_width, verilog::DEFAULT_WIDTH, verilog::MODULE_NAME};
+use crate::*;
+use indexmap::IndexSet;
+use rustc_hash::FxHashMap;
+use serde_json::{json, Value};
+
+pub fn to_yosys_json(
+    egraph: &EGraph,
+    result: &ExtractionResult,
+    roots: &[ClassId],
+) -> anyhow::Result<Value> {
+    let mut emitter = Emitter {
+        egraph,
+        signals: FxHashMap::default(),
+        inputs: IndexSet::default(),
+        cells: serde_json::Map::new(),
+        netnames: serde_json::Map::new(),
+        next_bit: 2,
+        sized: result
+            .choices
+            .values()
+            .any(|node_id| split_width(&egraph[node_id].op).1.is_some()),
+    };
+
+    result.visit(egraph, roots, &mut emitter)?;
+
+    let mut outputs = vec![];
+    for (i, root) in roots.iter().enumerate() {
+        let node = &egraph[&result.choices[root]];
+        let (name, bits) = match arg(split_width(&node.op).0, "RootNode") {
+            Some(name) if node.children.len() == 1 => {
+                let child = egraph.nid_to_cid(&node.children[0]);
+                (name.to_string(), emitter.signal(child)?)
+            }
+            _ => (format!("out{i}"), emitter.signal(root)?),
+        };
+        emitter.net(&name, &bits);
+        outputs.push((name, bits));
+    }
+
+    let mut ports: serde_json::Map<String, Value> = serde_json::Map::new();
+    for name in &emitter.inputs {
+        let bits = &emitter.netnames[name]["bits"];
+        ports.insert(name.clone(), json!({"direction": "input", "bits": bits}));
+    }
+    for (name, bits) in outputs {
+        ports.insert(name, json!({"direction": "output", "bits": bits}));
+    }
+
+    Ok(json!({
+        "creator": "extraction-gym",
+        "modules": {
+            MODULE_NAME: {
+                "attributes": {},
+                "ports": ports,
+                "cells": emitter.cells,
+                "netnames": emitter.netnames,
+            }
+        }
+    }))
+}
+
+struct Emitter<'a> {
+    egraph: &'a EGraph,
+    /// The bits of each class's signal.
+    signals: FxHashMap<ClassId, Vec<Value>>,
+    inputs: IndexSet<String>,
+    cells: serde_json::Map<String, Value>,
+    netnames: serde_json::Map<String, Value>,
+    next_bit: u64,
+    /// Whether some op gives its width.
+    sized: bool,
+}
+
+impl Emitter<'_> {
+    /// The bits holding the value of a class that has been left already.
+    fn signal(&self, class_id: &ClassId) -> anyhow::Result<Vec<Value>> {
+        match self.signals.get(class_id) {
+            Some(bits) => Ok(bits.clone()),
+            None => anyhow::bail!("Class {class_id} is used in its own computation"),
+        }
+    }
+
+    /// Adds the cells computing an op of its arguments, returning their output bits.
+    fn cells_for(
+        &mut self,
+        op: &str,
+        args: Vec<Vec<Value>>,
+        width: u32,
+    ) -> anyhow::Result<Vec<Value>> {
+        // The constant operand of some unary ops is part of the op, e.g. `Shl(_, 2)`.
+        let inline_operand = |after: &str, default: i64| {
+            op.split(after)
+                .nth(1)
+                .and_then(|rest| rest.split(')').next())
+                .and_then(|s| s.trim().parse().ok())
+                .unwrap_or(default)
+        };
+        let head = op.split(['(', ':']).next().unwrap_or(op);
+
+        let bits = match (head, args.as_slice()) {
+            (_, []) => anyhow::bail!("Can't write leaf {op} as Yosys JSON"),
+            ("Not", [a]) => self.cell("$not", &[("A", a)], width),
+            ("Neg", [a]) => self.cell("$neg", &[("A", a)], width),
+            ("Mul", [a]) => {
+                let b = constant(inline_operand("Num(", 1), width);
+                self.cell("$mul", &[("A", a), ("B", &b)], width)
+            }
+            ("Shl", [a]) => {
+                let b = constant(inline_operand(",", 1), 32);
+                self.cell("$shl", &[("A", a), ("B", &b)], width)
+            }
+            ("Shr", [a]) => {
+                let b = constant(inline_operand(",", 1), 32);
+                self.cell("$shr", &[("A", a), ("B", &b)], width)
+            }
+            ("Sub", [a, b]) => self.cell("$sub", &[("A", a), ("B", b)], width),
+            ("Shl", [a, b]) => self.cell("$shl", &[("A", a), ("B", b)], width),
+            ("Shr", [a, b]) => self.cell("$shr", &[("A", a), ("B", b)], width),
+            ("Mux", [s, t, e]) => {
+                let s = vec![s[0].clone()];
+                self.cell("$mux", &[("A", e), ("B", t), ("S", &s)], width)
+            }
+            ("Add" | "Mul" | "And" | "Or" | "Xor", [first, rest @ ..]) => {
+                let kind = format!("${}", head.to_lowercase());
+                let mut acc = first.clone();
+                for b in rest {
+                    acc = self.cell(&kind, &[("A", &acc), ("B", b)], width);
+                }
+                acc
+            }
+            _ => anyhow::bail!(
+                "Can't write {op} with {} children as a Yosys cell",
+                args.len()
+            ),
+        };
+        Ok(bits)
+    }
+
+    /// Adds a cell of some type with its inputs connected, returning the bits of its `Y`.
+    fn cell(&mut self, kind: &str, inputs: &[(&str, &Vec<Value>)], width: u32) -> Vec<Value> {
+        let y = self.wire(width);
+        let mut parameters: serde_json::Map<String, Value> = serde_json::Map::new();
+        let mut directions: serde_json::Map<String, Value> = serde_json::Map::new();
+        let mut connections: serde_json::Map<String, Value> = serde_json::Map::new();
+        for (port, bits) in inputs {
+            // $mux has a WIDTH for A, B and Y, and S is one bit.
+            if kind != "$mux" {
+                parameters.insert(format!("{port}_SIGNED"), 0.into());
+                parameters.insert(format!("{port}_WIDTH"), bits.len().into());
+            }
+            directions.insert(port.to_string(), "input".into());
+            connections.insert(port.to_string(), json!(bits));
+        }
+        if kind == "$mux" {
+            parameters.insert("WIDTH".to_string(), width.into());
+        } else {
+            parameters.insert("Y_WIDTH".to_string(), width.into());
+        }
+        directions.insert("Y".to_string(), "output".into());
+        connections.insert("Y".to_string(), json!(y));
+
+        let name = format!("$extract${}", self.cells.len());
+        self.cells.insert(
+            name.clone(),
+            json!({
+                "hide_name": 1,
+                "type": kind,
+                "parameters": parameters,
+                "attributes": {},
+                "port_directions": directions,
+                "connections": connections,
+            }),
+        );
+        self.net(&name, &y);
+        y
+    }
+
+    /// Fresh bits for a signal of some width.
+    fn wire(&mut self, width: u32) -> Vec<Value> {
+        let bits = (self.next_bit..self.next_bit + width as u64)
+            .map(Value::from)
+            .collect();
+        self.next_bit += width as u64;
+        bits
+    }
+
+    /// Names some bits, so they show up by name in Yosys.
+    fn net(&mut self, name: &str, bits: &[Value]) {
+        let hide_name = u8::from(name.starts_with('$'));
+        self.netnames.insert(
+            name.to_string(),
+            json!({"hide_name": hide_name, "bits": bits, "attributes": {}}),
+        );
+    }
+}
+
+impl DagVisitor for Emitter<'_> {
+    /// Adds the cell of a class, whose children have their bits already.
+    fn leave_node(&mut self, class_id: &ClassId, node_id: &NodeId) -> anyhow::Result<()> {
+        let node = &self.egraph[node_id];
+        let (op, width) = split_width(&node.op);
+        if arg(op, "RootNode").is_some() && node.children.len() == 1 {
+            // the output port takes its child's bits
+            return Ok(());
+        }
+        let width = match width {
+            Some(width) if self.sized => width,
+            None if self.sized => {
+                log::warn!("Node {node_id} ({op}) has no width, making it 1 bit wide");
+                1
+            }
+            _ => DEFAULT_WIDTH,
+        };
+
+        let bits = if let Some(var) = arg(op, "Var") {
+            let bits = self.wire(width);
+            self.inputs.insert(var.to_string());
+            self.net(var, &bits);
+            bits
+        } else if let Some(num) = arg(op, "Num") {
+            let num: i64 = num
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Can't write constant {op} as Yosys JSON"))?;
+            constant(num, width)
+        } else {
+            let args = node
+                .children
+                .iter()
+                .map(|c| self.signal(self.egraph.nid_to_cid(c)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            self.cells_for(op, args, width)?
+        };
+        self.signals.insert(class_id.clone(), bits);
+        Ok(())
+    }
+}
+
+/// The bits of a constant, least significant first, in two's complement.
+fn constant(num: i64, width: u32) -> Vec<Value> {
+    (0..width)
+        .map(|i| {
+            let bit = if i < 64 {
+                (num >> i) & 1
+            } else {
+                num >> 63 & 1
+            };
+            Value::from(bit.to_string())
+        })
+        .collect()
+}
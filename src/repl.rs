@@ -0,0 +1,212 @@
+/* An interactive loop for exploring one egraph, run with --repl egraph.json.
+
+Commands, one per line:
+ - `extract [extractor]` extracts the roots, with bottom-up or the last extractor if none
+   is given.
+ - `cost` prints the tree and dag cost of the extraction.
+ - `show <class>` lists the nodes of a class, marking the chosen one.
+ - `why <class>` compares the chosen node of a class with the others, by how much the dag
+   cost of the extraction would change if the class chose them instead.
+ - `forbid <node>` removes a node, as if the rewrite that made it hadn't happened. The
+   extraction is dropped, to be redone with `extract`.
+ - `reset` puts back every forbidden node.
+ - `help` and `quit`.
+
+Lines are read from stdin as they are, without editing or history.
+*/
+
+use crate::*;
+use rustc_hash::FxHashSet;
+use std::io::{BufRead, Write};
+
+struct Session<'a> {
+    original: EGraph,
+    egraph: EGraph,
+    forbidden: Vec<NodeId>,
+    extractors: &'a IndexMap<&'static str, ExtractorDetail>,
+    extractor_name: String,
+    result: Option<ExtractionResult>,
+}
+
+/// Reads commands from `input` until it ends or says `quit`, writing what they print to
+/// `output`. A command that fails prints why, and the loop goes on.
+pub fn run(
+    egraph: EGraph,
+    extractors: &IndexMap<&'static str, ExtractorDetail>,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> anyhow::Result<()> {
+    let mut session = Session {
+        original: egraph.clone(),
+        egraph,
+        forbidden: vec![],
+        extractors,
+        extractor_name: "bottom-up".to_string(),
+        result: None,
+    };
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            write!(output, "> ")?;
+            output.flush()?;
+            continue;
+        };
+        if command == "quit" || command == "exit" {
+            break;
+        }
+        let arg = words.next();
+        match session.command(command, arg) {
+            Ok(text) => write!(output, "{text}")?,
+            Err(e) => writeln!(output, "error: {e}")?,
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)?;
+    Ok(())
+}
+
+impl Session<'_> {
+    fn command(&mut self, command: &str, arg: Option<&str>) -> anyhow::Result<String> {
+        match (command, arg) {
+            ("extract", name) => self.extract(name),
+            ("cost", None) => {
+                let result = self.result()?;
+                let roots = &self.egraph.root_eclasses;
+                Ok(format!(
+                    "tree {}\ndag {}\n",
+                    result.tree_cost(&self.egraph, roots),
+                    result.dag_cost(&self.egraph, roots)
+                ))
+            }
+            ("show", Some(class)) => self.show(&class.into()),
+            ("why", Some(class)) => self.why(&class.into()),
+            ("forbid", Some(node)) => self.forbid(node.into()),
+            ("reset", None) => {
+                self.egraph = self.original.clone();
+                self.forbidden.clear();
+                self.result = None;
+                Ok("All nodes are allowed again\n".to_string())
+            }
+            ("help", None) => Ok("extract [extractor], cost, show <class>, why <class>, \
+                                  forbid <node>, reset, quit\n"
+                .to_string()),
+            _ => anyhow::bail!("Unknown command {command}, try help"),
+        }
+    }
+
+    fn result(&self) -> anyhow::Result<&ExtractionResult> {
+        self.result
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Nothing extracted yet, use extract"))
+    }
+
+    fn class(&self, class_id: &ClassId) -> anyhow::Result<&egraph_serialize::Class> {
+        self.egraph
+            .classes()
+            .get(class_id)
+            .ok_or_else(|| anyhow::anyhow!("No class {class_id}"))
+    }
+
+    fn extract(&mut self, name: Option<&str>) -> anyhow::Result<String> {
+        let name = name.unwrap_or(&self.extractor_name).to_string();
+        let Some(ed) = self.extractors.get(name.as_str()) else {
+            let names: Vec<&str> = self.extractors.keys().copied().collect();
+            anyhow::bail!("No extractor {name}, try one of {}", names.join(", "));
+        };
+        let roots = &self.egraph.root_eclasses;
+        let result = ed.extractor.extract(&self.egraph, roots);
+        let missing: Vec<&ClassId> = roots
+            .iter()
+            .filter(|root| !result.choices.contains_key(*root))
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!("{name} can't extract roots {missing:?}");
+        }
+        let text = format!(
+            "{name}: tree {}, dag {}\n",
+            result.tree_cost(&self.egraph, roots),
+            result.dag_cost(&self.egraph, roots)
+        );
+        self.extractor_name = name;
+        self.result = Some(result);
+        Ok(text)
+    }
+
+    fn show(&self, class_id: &ClassId) -> anyhow::Result<String> {
+        let chosen = self.result.as_ref().and_then(|r| r.choices.get(class_id));
+        let mut text = String::new();
+        for node_id in &self.class(class_id)?.nodes {
+            let node = &self.egraph[node_id];
+            let children: Vec<String> = node
+                .children
+                .iter()
+                .map(|c| self.egraph.nid_to_cid(c).to_string())
+                .collect();
+            let mark = if chosen == Some(node_id) { "*" } else { " " };
+            text.push_str(&format!(
+                "{mark} {node_id}\t{}\tcost {}\tchildren [{}]\n",
+                node.op,
+                node.cost,
+                children.join(", ")
+            ));
+        }
+        Ok(text)
+    }
+
+    fn why(&self, class_id: &ClassId) -> anyhow::Result<String> {
+        let result = self.result()?;
+        let class = self.class(class_id)?;
+        let Some(chosen) = result.choices.get(class_id) else {
+            anyhow::bail!("Class {class_id} has no choice");
+        };
+        if !uses(result, &self.egraph, class_id) {
+            return Ok(format!(
+                "{chosen} is chosen, but the roots don't use class {class_id}\n"
+            ));
+        }
+        let uses = DagUses::new(&self.egraph, result, &self.egraph.root_eclasses);
+        let mut text = format!("{chosen} is chosen, the dag cost would change by\n");
+        for node_id in class.nodes.iter().filter(|n| *n != chosen) {
+            let delta = uses.delta(&self.egraph, result, class_id, node_id);
+            let delta = if delta.is_infinite() {
+                "inf (it makes a cycle or needs a class without a choice)".to_string()
+            } else {
+                format!("{delta:+}")
+            };
+            text.push_str(&format!("  {node_id}\t{delta}\n"));
+        }
+        Ok(text)
+    }
+
+    fn forbid(&mut self, node_id: NodeId) -> anyhow::Result<String> {
+        if !self.egraph.nodes.contains_key(&node_id) {
+            anyhow::bail!("No node {node_id}");
+        }
+        self.egraph = transform::retain_nodes(&self.egraph, |id, _| *id != node_id);
+        self.forbidden.push(node_id.clone());
+        self.result = None;
+        Ok(format!(
+            "Forbade {node_id}, {} forbidden in all, extract again\n",
+            self.forbidden.len()
+        ))
+    }
+}
+
+/// Whether the roots of the extraction use a class.
+fn uses(result: &ExtractionResult, egraph: &EGraph, class_id: &ClassId) -> bool {
+    let mut seen: FxHashSet<&ClassId> = FxHashSet::default();
+    let mut todo: Vec<&ClassId> = egraph.root_eclasses.iter().collect();
+    while let Some(cid) = todo.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        for child in &egraph[&result.choices[cid]].children {
+            todo.push(egraph.nid_to_cid(child));
+        }
+    }
+    seen.contains(class_id)
+}
@@ -0,0 +1,184 @@
+/* The shape of an input egraph, checked by --strict-input before egraph_serialize parses it,
+so a hand-edited file gets an error naming the JSON path that is wrong:
+
+    {
+      "nodes": {                      required
+        "<node id>": {
+          "op": "<string>",           required
+          "children": ["<node id>"],  required, each a node in "nodes"
+          "eclass": "<class id>",     required
+          "cost": <number>,           required
+          "subsumed": <bool>          optional
+        }
+      },
+      "root_eclasses": ["<class id>"],
+      "class_data": {"<class id>": {"type": "<string>"}}
+    }
+
+At least one of root_eclasses and class_data has to be there. Nodes can have other fields,
+like the ones --report-dims, --iteration-bias and --with-source read.
+
+A node id defined twice is an error even without --strict-input, as parsing would silently
+keep one of the definitions.
+*/
+
+use indexmap::IndexSet;
+use serde_json::Value;
+
+pub fn validate(json: &Value) -> anyhow::Result<()> {
+    let Some(top) = json.as_object() else {
+        anyhow::bail!("$: expected an object, found {}", kind(json));
+    };
+
+    let Some(nodes) = top.get("nodes") else {
+        anyhow::bail!("$.nodes: missing");
+    };
+    let Some(nodes) = nodes.as_object() else {
+        anyhow::bail!("$.nodes: expected an object, found {}", kind(nodes));
+    };
+    for (id, node) in nodes {
+        let path = format!("$.nodes[{id:?}]");
+        let Some(node) = node.as_object() else {
+            anyhow::bail!("{path}: expected an object, found {}", kind(node));
+        };
+        for (field, expected) in [("op", "a string"), ("eclass", "a string")] {
+            match node.get(field) {
+                None => anyhow::bail!("{path}.{field}: missing"),
+                Some(Value::String(_)) => {}
+                Some(v) => anyhow::bail!("{path}.{field}: expected {expected}, found {}", kind(v)),
+            }
+        }
+        match node.get("cost") {
+            None => anyhow::bail!("{path}.cost: missing"),
+            Some(Value::Number(_)) => {}
+            Some(v) => anyhow::bail!("{path}.cost: expected a number, found {}", kind(v)),
+        }
+        match node.get("subsumed") {
+            None | Some(Value::Bool(_)) => {}
+            Some(v) => anyhow::bail!("{path}.subsumed: expected a bool, found {}", kind(v)),
+        }
+        let children = match node.get("children") {
+            None => anyhow::bail!("{path}.children: missing"),
+            Some(Value::Array(children)) => children,
+            Some(v) => anyhow::bail!("{path}.children: expected a list, found {}", kind(v)),
+        };
+        for (i, child) in children.iter().enumerate() {
+            match child.as_str() {
+                None => anyhow::bail!(
+                    "{path}.children[{i}]: expected a node id, found {}",
+                    kind(child)
+                ),
+                Some(child) if !nodes.contains_key(child) => {
+                    anyhow::bail!("{path}.children[{i}]: no node {child:?} in $.nodes")
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    if !top.contains_key("root_eclasses") && !top.contains_key("class_data") {
+        anyhow::bail!("$.root_eclasses: missing, and there's no $.class_data either");
+    }
+    if let Some(roots) = top.get("root_eclasses") {
+        let Some(roots) = roots.as_array() else {
+            anyhow::bail!("$.root_eclasses: expected a list, found {}", kind(roots));
+        };
+        for (i, root) in roots.iter().enumerate() {
+            if !root.is_string() {
+                anyhow::bail!(
+                    "$.root_eclasses[{i}]: expected a class id, found {}",
+                    kind(root)
+                );
+            }
+        }
+    }
+    if let Some(class_data) = top.get("class_data") {
+        let Some(class_data) = class_data.as_object() else {
+            anyhow::bail!(
+                "$.class_data: expected an object, found {}",
+                kind(class_data)
+            );
+        };
+        for (id, data) in class_data {
+            let path = format!("$.class_data[{id:?}]");
+            let Some(data) = data.as_object() else {
+                anyhow::bail!("{path}: expected an object, found {}", kind(data));
+            };
+            match data.get("type") {
+                None | Some(Value::Null) | Some(Value::String(_)) => {}
+                Some(v) => anyhow::bail!("{path}.type: expected a string, found {}", kind(v)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The node ids that $.nodes has more than once, in the order of their second definition.
+/// Parsing keeps only one definition of each, so this scans the text. It expects valid
+/// JSON.
+pub fn duplicate_node_ids(json: &str) -> Vec<&str> {
+    let mut seen = IndexSet::new();
+    let mut duplicates = IndexSet::new();
+    // whether each open container is an object
+    let mut open: Vec<bool> = vec![];
+    let mut expect_key = false;
+    let mut top_key = "";
+    let mut in_nodes = false;
+    let mut chars = json.char_indices();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '"' => {
+                let mut end = json.len();
+                let mut escaped = false;
+                for (i, c) in chars.by_ref() {
+                    match c {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => {
+                            end = i;
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                let string = &json[start + 1..end];
+                if expect_key {
+                    expect_key = false;
+                    if open.len() == 1 {
+                        top_key = string;
+                    } else if in_nodes && open.len() == 2 && !seen.insert(string) {
+                        duplicates.insert(string);
+                    }
+                }
+            }
+            '{' => {
+                open.push(true);
+                expect_key = true;
+                if open.len() == 2 {
+                    in_nodes = top_key == "nodes";
+                }
+            }
+            '[' => open.push(false),
+            '}' | ']' => {
+                open.pop();
+                if open.len() < 2 {
+                    in_nodes = false;
+                }
+            }
+            ',' => expect_key = open.last() == Some(&true),
+            _ => {}
+        }
+    }
+    duplicates.into_iter().collect()
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a bool",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "an object",
+    }
+}
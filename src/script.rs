@@ -0,0 +1,52 @@
+/* A cost model written as a Rhai script, loaded with --cost-script, so it can be changed
+without recompiling. The script defines
+
+    fn cost(op, num_children, children_ops) { ... }
+
+which gets a node's op, its number of children and their ops, and returns its cost as an
+int or a float.
+*/
+
+use crate::*;
+use std::path::Path;
+
+pub struct CostScript {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+impl CostScript {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let engine = rhai::Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| anyhow::anyhow!("{e}"))?;
+        Ok(CostScript { engine, ast })
+    }
+
+    /// The cost the script gives a node. Fails if the script does, or returns something that
+    /// isn't a non-negative number.
+    pub fn cost(&self, egraph: &EGraph, node_id: &NodeId) -> anyhow::Result<Cost> {
+        let node = &egraph[node_id];
+        let children_ops: rhai::Array = node
+            .children
+            .iter()
+            .map(|child| rhai::Dynamic::from(egraph[child].op.clone()))
+            .collect();
+        let args = (node.op.clone(), node.children.len() as i64, children_ops);
+        let cost: rhai::Dynamic = self
+            .engine
+            .call_fn(&mut rhai::Scope::new(), &self.ast, "cost", args)
+            .map_err(|e| anyhow::anyhow!("cost({}) failed: {e}", node.op))?;
+        let cost = cost
+            .as_float()
+            .or_else(|_| cost.as_int().map(|cost| cost as f64))
+            .map_err(|type_name| {
+                anyhow::anyhow!("cost({}) returned a {type_name}, not a number", node.op)
+            })?;
+        if cost.is_nan() || cost < 0.0 {
+            anyhow::bail!("cost({}) is {cost}, it can't be negative", node.op);
+        }
+        Ok(Cost::new(cost)?)
+    }
+}
@@ -0,0 +1,129 @@
+/* A smoke test of the binary itself, run with --selftest: every compiled-in extractor,
+including the feature-gated solvers, extracts a few small egraphs built in memory.
+
+An extraction passes if it covers the roots without cycles, its dag cost is at most its
+tree cost, and it costs no more than any other extractor by the measure it is optimal for.
+*/
+
+use crate::*;
+use std::panic::AssertUnwindSafe;
+
+/// Runs every extractor on every egraph, printing a line per extraction and a summary.
+/// Returns whether they all passed.
+pub fn run() -> bool {
+    let extractors = extractors();
+    let mut passed = 0;
+    let mut failed = 0;
+    for (name, egraph) in egraphs() {
+        let mut costs: IndexMap<&str, (Cost, Cost)> = IndexMap::new();
+        let mut failures: IndexMap<&str, String> = IndexMap::new();
+        for (extractor_name, ed) in &extractors {
+            // `check` asserts, so a failed check is a panic, as is a solver that doesn't work.
+            let extracted = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                let result = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+                result.check(&egraph);
+                (
+                    result.tree_cost(&egraph, &egraph.root_eclasses),
+                    result.dag_cost(&egraph, &egraph.root_eclasses),
+                )
+            }));
+            match extracted {
+                Ok((tree, dag)) if dag > tree + EPSILON_ALLOWANCE => {
+                    failures.insert(extractor_name, format!("dag {dag} > tree {tree}"));
+                }
+                Ok(cost) => {
+                    costs.insert(extractor_name, cost);
+                }
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    failures.insert(extractor_name, format!("panicked: {message}"));
+                }
+            }
+        }
+
+        for (extractor_name, &(tree, dag)) in &costs {
+            let (cost, best, measure) = match extractors[extractor_name].optimal {
+                Optimal::Tree => (tree, costs.values().map(|c| c.0).min().unwrap(), "tree"),
+                Optimal::DAG => (dag, costs.values().map(|c| c.1).min().unwrap(), "dag"),
+                Optimal::Neither => continue,
+            };
+            if cost > best + EPSILON_ALLOWANCE {
+                failures.insert(
+                    extractor_name,
+                    format!("{measure} {cost}, but another extractor found {best}"),
+                );
+            }
+        }
+
+        for extractor_name in extractors.keys() {
+            match failures.get(extractor_name) {
+                Some(failure) => {
+                    failed += 1;
+                    println!("{name:12}\t{extractor_name:24}\tFAIL: {failure}");
+                }
+                None => {
+                    passed += 1;
+                    println!("{name:12}\t{extractor_name:24}\tpass");
+                }
+            }
+        }
+    }
+    println!("{passed} passed, {failed} failed");
+    failed == 0
+}
+
+/// Small egraphs with a shared class, a choice where sharing beats the cheapest tree, and a
+/// cycle to avoid.
+fn egraphs() -> Vec<(&'static str, EGraph)> {
+    let shared = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("n", "-", "n", &["x"], 1.0),
+            ("a", "+", "a", &["n", "n"], 1.0),
+        ],
+        &["a"],
+    );
+    let sharing = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("s", "S", "s", &["x"], 3.0),
+            ("f", "F", "r1", &["s"], 1.0),
+            ("g", "G", "r1", &["x"], 2.0),
+            ("h", "H", "r2", &["s"], 1.0),
+        ],
+        &["r1", "r2"],
+    );
+    let cycle = build(
+        &[
+            ("loop", "F", "a", &["neg"], 1.0),
+            ("leaf", "y", "a", &[], 5.0),
+            ("neg", "-", "b", &["loop"], 1.0),
+            ("r", "G", "r", &["neg"], 1.0),
+        ],
+        &["r"],
+    );
+    vec![("shared", shared), ("sharing", sharing), ("cycle", cycle)]
+}
+
+pub(crate) type NodeSpec<'a> = (&'a str, &'a str, &'a str, &'a [&'a str], f64);
+
+pub(crate) fn build(nodes: &[NodeSpec], roots: &[&str]) -> EGraph {
+    let mut egraph = EGraph::default();
+    for &(id, op, class, children, cost) in nodes {
+        egraph.add_node(
+            id.into(),
+            Node {
+                op: op.to_string(),
+                children: children.iter().map(|&c| c.into()).collect(),
+                eclass: class.into(),
+                cost: Cost::new(cost).unwrap(),
+            },
+        );
+    }
+    egraph.root_eclasses = roots.iter().map(|&r| r.into()).collect();
+    egraph
+}
@@ -0,0 +1,179 @@
+/* Metrics about an egraph, independent of any extraction, to characterize the inputs. */
+
+use crate::*;
+use rustc_hash::FxHashMap;
+
+#[derive(Debug)]
+pub struct EGraphStats {
+    pub classes: usize,
+    pub nodes: usize,
+    pub roots: usize,
+    pub mean_class_size: f64,
+    pub max_class_size: usize,
+    /// Whether some class can reach itself through the children of its nodes.
+    pub has_cycles: bool,
+    /// How many nodes have each op, most frequent first.
+    pub op_counts: IndexMap<String, usize>,
+}
+
+impl EGraphStats {
+    pub fn compute(egraph: &EGraph) -> Self {
+        let classes = egraph.classes();
+        let max_class_size = classes.values().map(|c| c.nodes.len()).max().unwrap_or(0);
+        let mean_class_size = if classes.is_empty() {
+            0.0
+        } else {
+            egraph.nodes.len() as f64 / classes.len() as f64
+        };
+
+        let mut op_counts = IndexMap::<String, usize>::default();
+        for node in egraph.nodes.values() {
+            *op_counts.entry(node.op.clone()).or_default() += 1;
+        }
+        op_counts.sort_by(|op1, count1, op2, count2| count2.cmp(count1).then(op1.cmp(op2)));
+
+        EGraphStats {
+            classes: classes.len(),
+            nodes: egraph.nodes.len(),
+            roots: egraph.root_eclasses.len(),
+            mean_class_size,
+            max_class_size,
+            has_cycles: has_cycles(egraph),
+            op_counts,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let op_counts: serde_json::Map<String, serde_json::Value> = self
+            .op_counts
+            .iter()
+            .map(|(op, count)| (op.clone(), (*count).into()))
+            .collect();
+        serde_json::json!({
+            "classes": self.classes,
+            "nodes": self.nodes,
+            "roots": self.roots,
+            "mean_class_size": self.mean_class_size,
+            "max_class_size": self.max_class_size,
+            "has_cycles": self.has_cycles,
+            "op_counts": op_counts,
+        })
+    }
+}
+
+/// Looks for a cycle between classes with a depth-first search.
+fn has_cycles(egraph: &EGraph) -> bool {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        InProgress,
+        Done,
+    }
+
+    let mut visits = FxHashMap::<&ClassId, Visit>::default();
+    for start in egraph.classes().keys() {
+        if visits.contains_key(start) {
+            continue;
+        }
+        // Each entry is a class and whether its children have been pushed already.
+        let mut stack = vec![(start, false)];
+        while let Some((class_id, expanded)) = stack.pop() {
+            if expanded {
+                visits.insert(class_id, Visit::Done);
+                continue;
+            }
+            match visits.get(class_id) {
+                Some(Visit::Done) => continue,
+                Some(Visit::InProgress) => return true,
+                None => {}
+            }
+            visits.insert(class_id, Visit::InProgress);
+            stack.push((class_id, true));
+            for node_id in &egraph[class_id].nodes {
+                for child in &egraph[node_id].children {
+                    let child = egraph.nid_to_cid(child);
+                    match visits.get(child) {
+                        Some(Visit::InProgress) => return true,
+                        Some(Visit::Done) => {}
+                        None => stack.push((child, false)),
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/// The representative of `i`'s set in a union-find forest, halving the path on the way.
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// Groups the roots that share a class they can reach, through any nodes. Roots in
+/// different groups are independent, e.g. separate circuits bundled in one file.
+pub fn root_components(egraph: &EGraph) -> Vec<Vec<ClassId>> {
+    let mut component: FxHashMap<&ClassId, usize> = FxHashMap::default();
+    let mut parent: Vec<usize> = vec![];
+
+    for (i, root) in egraph.root_eclasses.iter().enumerate() {
+        parent.push(i);
+        let mut todo = vec![root];
+        while let Some(class_id) = todo.pop() {
+            if let Some(&other) = component.get(class_id) {
+                let (a, b) = (find(&mut parent, other), find(&mut parent, i));
+                parent[a] = b;
+                continue;
+            }
+            component.insert(class_id, i);
+            for node_id in &egraph[class_id].nodes {
+                todo.extend(
+                    egraph[node_id]
+                        .children
+                        .iter()
+                        .map(|c| egraph.nid_to_cid(c)),
+                );
+            }
+        }
+    }
+
+    let mut components: IndexMap<usize, Vec<ClassId>> = IndexMap::default();
+    for (i, root) in egraph.root_eclasses.iter().enumerate() {
+        let c = find(&mut parent, i);
+        let roots = components.entry(c).or_default();
+        if !roots.contains(root) {
+            roots.push(root.clone());
+        }
+    }
+    components.into_values().collect()
+}
+
+/// The weakly connected components of the egraph, ignoring which way the edges go: a node
+/// connects its class with the classes of its children. Each is given by how many classes
+/// and nodes it has, biggest first.
+pub fn weak_components(egraph: &EGraph) -> Vec<(usize, usize)> {
+    let classes = egraph.classes();
+    let mut parent: Vec<usize> = (0..classes.len()).collect();
+
+    for (i, class) in classes.values().enumerate() {
+        for node_id in &class.nodes {
+            for child in &egraph[node_id].children {
+                let j = classes.get_index_of(egraph.nid_to_cid(child)).unwrap();
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut sizes: FxHashMap<usize, (usize, usize)> = FxHashMap::default();
+    for (i, class) in classes.values().enumerate() {
+        let size = sizes.entry(find(&mut parent, i)).or_default();
+        size.0 += 1;
+        size.1 += class.nodes.len();
+    }
+    let mut sizes: Vec<(usize, usize)> = sizes.into_values().collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes
+}
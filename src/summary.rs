@@ -0,0 +1,109 @@
+/* Statistics over the reports of a whole corpus, written by --corpus-summary.
+
+Each extractor's dag cost on a file is compared with the cheapest any extractor found for
+that file, so a ratio of 1 means it was the best. The ratios and solve times are summarized
+by percentiles, and the ILP runs that didn't prove their extraction optimal are counted as
+timeouts.
+*/
+
+use anyhow::Context;
+use indexmap::IndexMap;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// The percentiles reported, by name.
+const PERCENTILES: [(&str, f64); 3] = [("median", 50.0), ("p90", 90.0), ("p99", 99.0)];
+
+/// Reads every report under `dir`. Other JSON files, like egraphs or rich reports, are
+/// skipped.
+pub fn read_reports(dir: &Path) -> anyhow::Result<Vec<Value>> {
+    let mut reports = vec![];
+    for entry in walkdir::WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file()
+            || entry.path().extension().and_then(|e| e.to_str()) != Some("json")
+        {
+            continue;
+        }
+        let json = std::fs::read_to_string(entry.path())?;
+        let report: Value = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse {}", entry.path().display()))?;
+        let is_report = ["name", "extractor", "dag", "micros"]
+            .iter()
+            .all(|key| report.get(key).is_some());
+        if is_report {
+            reports.push(report);
+        }
+    }
+    Ok(reports)
+}
+
+pub fn summarize(reports: &[Value]) -> anyhow::Result<Value> {
+    let mut best: IndexMap<&str, f64> = IndexMap::new();
+    for report in reports {
+        let name = report["name"]
+            .as_str()
+            .context("A report's name isn't a string")?;
+        let dag = report["dag"]
+            .as_f64()
+            .context("A report's dag isn't a number")?;
+        let best = best.entry(name).or_insert(dag);
+        *best = best.min(dag);
+    }
+
+    let mut by_extractor: IndexMap<&str, Vec<&Value>> = IndexMap::new();
+    for report in reports {
+        let extractor = report["extractor"]
+            .as_str()
+            .context("A report's extractor isn't a string")?;
+        by_extractor.entry(extractor).or_default().push(report);
+    }
+
+    let mut extractors = serde_json::Map::new();
+    for (extractor, reports) in by_extractor {
+        let mut ratios = vec![];
+        let mut micros = vec![];
+        let mut timeouts = 0;
+        for report in &reports {
+            let dag = report["dag"].as_f64().unwrap();
+            let best = best[report["name"].as_str().unwrap()];
+            // Even when both are free.
+            ratios.push(if dag == best { 1.0 } else { dag / best });
+            micros.push(
+                report["micros"]
+                    .as_f64()
+                    .context("A report's micros isn't a number")?,
+            );
+            if report["ilp_optimal"] == json!(false) {
+                timeouts += 1;
+            }
+        }
+        extractors.insert(
+            extractor.to_string(),
+            json!({
+                "files": reports.len(),
+                "dag_ratio": percentiles(&mut ratios),
+                "micros": percentiles(&mut micros),
+                "timeouts": timeouts,
+            }),
+        );
+    }
+
+    Ok(json!({
+        "files": best.len(),
+        "extractors": extractors,
+    }))
+}
+
+/// The nearest-rank percentiles of `values`, which can't be empty.
+fn percentiles(values: &mut [f64]) -> Value {
+    values.sort_by(f64::total_cmp);
+    let summary: serde_json::Map<String, Value> = PERCENTILES
+        .iter()
+        .map(|&(name, p)| {
+            let rank = (p / 100.0 * values.len() as f64).ceil() as usize;
+            (name.to_string(), values[rank.max(1) - 1].into())
+        })
+        .collect();
+    summary.into()
+}
@@ -1,4 +1,5 @@
-use crate::{extractors, Extractor, Optimal, EPSILON_ALLOWANCE};
+use crate::selftest::build;
+use crate::{extractors, CostAttribution, Extractor, Optimal, EPSILON_ALLOWANCE};
 pub type Cost = NotNan<f64>;
 use egraph_serialize::{EGraph, Node, NodeId};
 use ordered_float::NotNan;
@@ -18,15 +19,29 @@ pub fn test_save_path(name: &str) -> String {
 }
 
 // generates a float between 0 and 1
-fn generate_random_not_nan() -> NotNan<f64> {
-    let mut rng: rand::prelude::ThreadRng = rand::thread_rng();
+fn generate_random_not_nan(rng: &mut impl Rng) -> NotNan<f64> {
     let random_float: f64 = rng.gen();
     NotNan::new(random_float).unwrap()
 }
 
+// Unless we do it explicitly, the costs are almost never equal to others' costs or zero:
+fn get_semi_random_cost(rng: &mut impl Rng, nodes: &[Node]) -> Cost {
+    if nodes.len() > 0 && rng.gen_bool(0.1) {
+        return nodes[rng.gen_range(0..nodes.len())].cost;
+    } else if rng.gen_bool(0.05) {
+        return Cost::default();
+    } else {
+        return generate_random_not_nan(rng) * 100.0;
+    }
+}
+
 //make a random egraph that has a loop-free extraction.
 pub fn generate_random_egraph() -> EGraph {
-    let mut rng = rand::thread_rng();
+    generate_random_egraph_with(&mut rand::thread_rng())
+}
+
+// The same as generate_random_egraph, but reproducible with a seeded rng.
+pub fn generate_random_egraph_with(rng: &mut impl Rng) -> EGraph {
     let core_node_count = rng.gen_range(1..100) as usize;
     let extra_node_count = rng.gen_range(1..100);
     let mut nodes: Vec<Node> = Vec::with_capacity(core_node_count + extra_node_count);
@@ -34,19 +49,6 @@ pub fn generate_random_egraph() -> EGraph {
 
     let id2nid = |id: usize| -> NodeId { format!("node_{}", id).into() };
 
-    // Unless we do it explicitly, the costs are almost never equal to others' costs or zero:
-    let get_semi_random_cost = |nodes: &Vec<Node>| -> Cost {
-        let mut rng = rand::thread_rng();
-
-        if nodes.len() > 0 && rng.gen_bool(0.1) {
-            return nodes[rng.gen_range(0..nodes.len())].cost;
-        } else if rng.gen_bool(0.05) {
-            return Cost::default();
-        } else {
-            return generate_random_not_nan() * 100.0;
-        }
-    };
-
     for i in 0..core_node_count {
         let children: Vec<NodeId> = (0..i).filter(|_| rng.gen_bool(0.1)).map(id2nid).collect();
 
@@ -58,7 +60,7 @@ pub fn generate_random_egraph() -> EGraph {
             op: "operation".to_string(),
             children: children,
             eclass: eclass.to_string().clone().into(),
-            cost: get_semi_random_cost(&nodes),
+            cost: get_semi_random_cost(rng, &nodes),
         });
     }
 
@@ -69,7 +71,7 @@ pub fn generate_random_egraph() -> EGraph {
             op: "operation".to_string(),
             children: vec![],
             eclass: rng.gen_range(0..eclass * 2 + 1).to_string().clone().into(),
-            cost: get_semi_random_cost(&nodes),
+            cost: get_semi_random_cost(rng, &nodes),
         });
     }
 
@@ -106,7 +108,7 @@ pub fn generate_random_egraph() -> EGraph {
  * Checks that the extractions are valid.
  */
 
-fn check_optimal_results<I: Iterator<Item = EGraph>>(egraphs: I) {
+fn check_optimal_results<I: Iterator<Item = (String, EGraph)>>(egraphs: I) {
     let mut optimal_dag: Vec<Box<dyn Extractor>> = Default::default();
     let mut optimal_tree: Vec<Box<dyn Extractor>> = Default::default();
     let mut others: Vec<Box<dyn Extractor>> = Default::default();
@@ -119,7 +121,7 @@ fn check_optimal_results<I: Iterator<Item = EGraph>>(egraphs: I) {
         }
     }
 
-    for egraph in egraphs {
+    for (name, egraph) in egraphs {
         let mut optimal_dag_cost: Option<Cost> = None;
 
         for e in &optimal_dag {
@@ -134,11 +136,13 @@ fn check_optimal_results<I: Iterator<Item = EGraph>>(egraphs: I) {
 
             assert!(
                 (dag_cost.into_inner() - optimal_dag_cost.unwrap().into_inner()).abs()
-                    < EPSILON_ALLOWANCE
+                    < EPSILON_ALLOWANCE,
+                "{name}"
             );
 
             assert!(
-                tree_cost.into_inner() + EPSILON_ALLOWANCE > optimal_dag_cost.unwrap().into_inner()
+                tree_cost.into_inner() + EPSILON_ALLOWANCE > optimal_dag_cost.unwrap().into_inner(),
+                "{name}"
             );
         }
 
@@ -148,6 +152,8 @@ fn check_optimal_results<I: Iterator<Item = EGraph>>(egraphs: I) {
             let extract = e.extract(&egraph, &egraph.root_eclasses);
             extract.check(&egraph);
             let tree_cost = extract.tree_cost(&egraph, &egraph.root_eclasses);
+            let dag_cost = extract.dag_cost(&egraph, &egraph.root_eclasses);
+            assert!(tree_cost + EPSILON_ALLOWANCE >= dag_cost, "{name}");
             if optimal_tree_cost.is_none() {
                 optimal_tree_cost = Some(tree_cost);
                 continue;
@@ -155,12 +161,16 @@ fn check_optimal_results<I: Iterator<Item = EGraph>>(egraphs: I) {
 
             assert!(
                 (tree_cost.into_inner() - optimal_tree_cost.unwrap().into_inner()).abs()
-                    < EPSILON_ALLOWANCE
+                    < EPSILON_ALLOWANCE,
+                "{name}"
             );
         }
 
         if optimal_dag_cost.is_some() && optimal_tree_cost.is_some() {
-            assert!(optimal_dag_cost.unwrap() < optimal_tree_cost.unwrap() + EPSILON_ALLOWANCE);
+            assert!(
+                optimal_dag_cost.unwrap() < optimal_tree_cost.unwrap() + EPSILON_ALLOWANCE,
+                "{name}"
+            );
         }
 
         for e in &others {
@@ -168,15 +178,22 @@ fn check_optimal_results<I: Iterator<Item = EGraph>>(egraphs: I) {
             extract.check(&egraph);
             let tree_cost = extract.tree_cost(&egraph, &egraph.root_eclasses);
             let dag_cost = extract.dag_cost(&egraph, &egraph.root_eclasses);
+            assert!(tree_cost + EPSILON_ALLOWANCE >= dag_cost, "{name}");
 
             // The optimal tree cost should be <= any extractor's tree cost.
             if optimal_tree_cost.is_some() {
-                assert!(optimal_tree_cost.unwrap() <= tree_cost + EPSILON_ALLOWANCE);
+                assert!(
+                    optimal_tree_cost.unwrap() <= tree_cost + EPSILON_ALLOWANCE,
+                    "{name}"
+                );
             }
 
             if optimal_dag_cost.is_some() {
                 // The optimal dag should be less <= any extractor's dag cost
-                assert!(optimal_dag_cost.unwrap() <= dag_cost + EPSILON_ALLOWANCE);
+                assert!(
+                    optimal_dag_cost.unwrap() <= dag_cost + EPSILON_ALLOWANCE,
+                    "{name}"
+                );
             }
         }
     }
@@ -195,10 +212,2905 @@ fn run_on_test_egraphs() {
                 && e.path().extension().and_then(std::ffi::OsStr::to_str) == Some("json")
         })
         .map(|e| e.path().to_string_lossy().into_owned())
-        .map(|e| EGraph::from_json_file(e).unwrap());
+        .map(|e| {
+            let egraph = EGraph::from_json_file(&e).unwrap();
+            (e, egraph)
+        });
     check_optimal_results(egraphs);
 }
 
+// Random egraphs that are the same every run, so a failure can be reproduced from the
+// seed named in its message.
+#[test]
+fn check_seeded_random_egraphs() {
+    use rand::SeedableRng;
+
+    for seed in 0..1000 {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let egraph = generate_random_egraph_with(&mut rng);
+        check_optimal_results(std::iter::once((format!("seed {seed}"), egraph)));
+    }
+}
+
+// --count-only should print what a full bottom-up extraction costs.
+#[test]
+fn min_tree_costs_match_bottom_up() {
+    let extractor = crate::extract::bottom_up::BottomUpExtractor;
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let costs = crate::extract::bottom_up::min_tree_costs(&egraph);
+        let result = extractor.extract(&egraph, &egraph.root_eclasses);
+        for root in &egraph.root_eclasses {
+            let tree = result.tree_cost(&egraph, std::slice::from_ref(root));
+            assert!((costs[root] - tree).abs() < EPSILON_ALLOWANCE);
+        }
+    }
+}
+
+// Dead classes shouldn't change what lazy-greedy chooses for the rest.
+#[test]
+fn lazy_greedy_matches_greedy_dag_on_used_classes() {
+    use crate::extract::{greedy_dag::GreedyDagExtractor, lazy_greedy::LazyGreedyExtractor};
+
+    for _ in 0..100 {
+        let mut egraph = generate_random_egraph();
+        // the classes only the other roots use become dead
+        egraph
+            .root_eclasses
+            .truncate(egraph.root_eclasses.len().div_ceil(2));
+
+        let greedy = GreedyDagExtractor.extract(&egraph, &egraph.root_eclasses);
+        let lazy = LazyGreedyExtractor.extract(&egraph, &egraph.root_eclasses);
+        lazy.check(&egraph);
+        for (class_id, node_id) in &lazy.choices {
+            assert_eq!(&greedy.choices[class_id], node_id);
+        }
+        assert_eq!(
+            lazy.dag_cost(&egraph, &egraph.root_eclasses),
+            greedy.dag_cost(&egraph, &egraph.root_eclasses)
+        );
+    }
+}
+
+// Costing only the classes one root reaches gives the same tree cost as the full fixpoint.
+#[test]
+fn lazy_bottom_up_only_costs_reachable_classes() {
+    use crate::extract::{bottom_up::BottomUpExtractor, lazy_bottom_up::LazyBottomUpExtractor};
+
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let full = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+        for root in &egraph.root_eclasses {
+            let mut one_root = egraph.clone();
+            one_root.root_eclasses = vec![root.clone()];
+            let roots = &one_root.root_eclasses;
+            let lazy = LazyBottomUpExtractor.extract(&one_root, roots);
+            lazy.check(&one_root);
+            assert!(lazy.choices.len() <= full.choices.len());
+            assert!(
+                (lazy.tree_cost(&egraph, roots) - full.tree_cost(&egraph, roots)).abs()
+                    < EPSILON_ALLOWANCE
+            );
+        }
+    }
+}
+
+// Sharing s saves its whole cost without decay, and only half of it with a decay of 0.5.
+#[test]
+fn reuse_decay_charges_each_further_use() {
+    use crate::extract::greedy_dag::{GreedyDagExtractor, ReuseDecayExtractor};
+
+    let egraph = build(
+        &[
+            ("s", "s", "s", &[], 10.0),
+            ("t", "t", "t", &[], 14.0),
+            ("twice", "twice", "r", &["s", "s"], 1.0),
+            ("once", "once", "r", &["t"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let roots = &egraph.root_eclasses;
+
+    let shared = ReuseDecayExtractor { decay: 0.0 }.extract(&egraph, roots);
+    assert_eq!(shared, GreedyDagExtractor.extract(&egraph, roots));
+    assert_eq!(shared.choices[&"r".into()], "twice".into());
+    assert_eq!(shared.reuse_cost(&egraph, roots, 0.0), 11.0);
+    assert_eq!(shared.reuse_cost(&egraph, roots, 0.5), 16.0);
+    assert_eq!(shared.reuse_cost(&egraph, roots, 1.0), 21.0);
+
+    let decayed = ReuseDecayExtractor { decay: 0.5 }.extract(&egraph, roots);
+    decayed.check(&egraph);
+    assert_eq!(decayed.choices[&"r".into()], "once".into());
+    assert_eq!(decayed.reuse_cost(&egraph, roots, 0.5), 15.0);
+
+    for _ in 0..20 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        let result = ReuseDecayExtractor { decay: 0.0 }.extract(&egraph, roots);
+        let reuse_cost = result.reuse_cost(&egraph, roots, 0.0);
+        assert!((reuse_cost - result.dag_cost(&egraph, roots)).abs() < EPSILON_ALLOWANCE);
+    }
+}
+
+#[test]
+fn repl_forbids_nodes_and_extracts_again() {
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("cheap", "cheap", "r", &["x"], 1.0),
+            ("dear", "dear", "r", &["x"], 3.0),
+        ],
+        &["r"],
+    );
+
+    let script = "cost\nextract\nwhy r\nforbid cheap\ncost\nextract\nshow r\nquit\nextract\n";
+    let mut output = vec![];
+    crate::repl::run(egraph, &extractors(), script.as_bytes(), &mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let replies: Vec<&str> = output.split("> ").skip(1).collect();
+    assert_eq!(
+        replies,
+        [
+            "error: Nothing extracted yet, use extract\n",
+            "bottom-up: tree 2, dag 2\n",
+            "cheap is chosen, the dag cost would change by\n  dear\t+2\n",
+            "Forbade cheap, 1 forbidden in all, extract again\n",
+            "error: Nothing extracted yet, use extract\n",
+            "bottom-up: tree 4, dag 4\n",
+            "* dear\tdear\tcost 3\tchildren [x]\n",
+            "\n",
+        ]
+    );
+}
+
+#[test]
+fn replaying_a_recording_makes_the_same_choices() {
+    use crate::extract::recording::{to_log, ReplayExtractor};
+
+    for (name, ed) in extractors() {
+        let egraph = generate_random_egraph();
+        let result = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        let log = to_log(&egraph, &result);
+        assert_eq!(log.lines().count(), result.choices.len() + 1, "{name}");
+
+        let replayed = ReplayExtractor::from_log(&log)
+            .unwrap()
+            .extract(&egraph, &egraph.root_eclasses);
+        assert_eq!(replayed, result, "{name}");
+    }
+    assert!(ReplayExtractor::from_log("just_a_class\n").is_err());
+}
+
+#[test]
+fn certificates_verify_and_catch_tampering() {
+    use crate::certificate::{to_certificate, verify_certificate};
+
+    for (name, ed) in extractors() {
+        let egraph = generate_random_egraph();
+        let result = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        let cert = to_certificate(&egraph, &result, &egraph.root_eclasses).unwrap();
+        verify_certificate(&egraph, &cert).unwrap_or_else(|e| panic!("{name}: {e}"));
+
+        let mut reversed = cert.clone();
+        reversed["order"].as_array_mut().unwrap().reverse();
+        // the last class comes before its children once the order is reversed
+        let last = cert["order"].as_array().unwrap().last().unwrap();
+        if !last["children"].as_array().unwrap().is_empty() {
+            assert!(verify_certificate(&egraph, &reversed).is_err(), "{name}");
+        }
+        let mut uncovered = cert.clone();
+        uncovered["order"].as_array_mut().unwrap().pop();
+        assert!(verify_certificate(&egraph, &uncovered).is_err(), "{name}");
+        let mut cheaper = cert.clone();
+        cheaper["dag_cost"] = (cert["dag_cost"].as_f64().unwrap() - 1.0).into();
+        assert!(verify_certificate(&egraph, &cheaper).is_err(), "{name}");
+    }
+}
+
+// Every possible swap should change the dag cost by the predicted delta.
+#[test]
+fn cost_delta_matches_recomputed_dag_cost() {
+    let extractor = crate::extract::faster_greedy_dag::FasterGreedyDagExtractor;
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        let result = extractor.extract(&egraph, roots);
+        let before = result.dag_cost(&egraph, roots);
+        for class in egraph.classes().values() {
+            let Some(chosen) = result.choices.get(&class.id) else {
+                continue;
+            };
+            let used = result
+                .uncovered(&egraph, roots, std::slice::from_ref(chosen))
+                .is_empty();
+            for node_id in &class.nodes {
+                let delta = result.cost_delta_if_changed(&egraph, roots, &class.id, node_id);
+                let mut changed = result.clone();
+                changed.choose(class.id.clone(), node_id.clone());
+                // a class the roots don't use can still be swapped into a cycle
+                let reached = if used {
+                    roots.as_slice()
+                } else {
+                    std::slice::from_ref(&class.id)
+                };
+                let valid = egraph[node_id]
+                    .children
+                    .iter()
+                    .all(|c| changed.choices.contains_key(egraph.nid_to_cid(c)))
+                    && changed.find_cycles(&egraph, reached).is_empty();
+                if valid && !used {
+                    assert_eq!(delta, 0.0);
+                } else if valid {
+                    let after = changed.dag_cost(&egraph, roots);
+                    assert!((after - before - delta).abs() < EPSILON_ALLOWANCE);
+                } else {
+                    assert_eq!(delta, crate::INFINITY);
+                }
+            }
+        }
+    }
+}
+
+// A chain of swaps, each costed with the uses the earlier ones left, should track the dag
+// cost of the roots given, here just the first one.
+#[test]
+fn dag_uses_track_changes_for_the_given_roots() {
+    use crate::extract::DagUses;
+
+    let extractor = crate::extract::faster_greedy_dag::FasterGreedyDagExtractor;
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses[..1];
+        let mut result = extractor.extract(&egraph, &egraph.root_eclasses);
+        let mut uses = DagUses::new(&egraph, &result, roots);
+        let mut cost = result.dag_cost(&egraph, roots);
+        for class in egraph.classes().values() {
+            if !result.choices.contains_key(&class.id) {
+                continue;
+            }
+            for node_id in &class.nodes {
+                let delta = uses.change(&egraph, &mut result, &class.id, node_id);
+                if delta != crate::INFINITY {
+                    cost += delta;
+                    let recomputed = result.dag_cost(&egraph, roots);
+                    assert!((cost - recomputed).abs() < EPSILON_ALLOWANCE);
+                }
+            }
+        }
+    }
+}
+
+// The per-root figures should account for exactly the dag cost.
+#[test]
+fn attributed_costs_sum_to_dag_cost() {
+    let extractor = crate::extract::faster_greedy_dag::FasterGreedyDagExtractor;
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let result = extractor.extract(&egraph, &egraph.root_eclasses);
+        let dag_cost = result.dag_cost(&egraph, &egraph.root_eclasses);
+        for attribution in [CostAttribution::Equal, CostAttribution::Proportional] {
+            let total: Cost = result
+                .attribute_cost(&egraph, &egraph.root_eclasses, attribution)
+                .values()
+                .sum();
+            assert!((total.into_inner() - dag_cost.into_inner()).abs() < EPSILON_ALLOWANCE);
+        }
+    }
+}
+
+// 1/10 + 2/10 is exactly 3/10, so it's cheaper than 3/10 + 1/10^17. In f64 though,
+// 0.1 + 0.2 rounds to more than 0.3 + 1e-17 does, so the other node looks cheaper.
+#[cfg(feature = "rational")]
+#[test]
+fn rational_costs_are_exact() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::extract::faster_bottom_up::FasterBottomUpExtractor;
+    use egraph_serialize::ClassId;
+    use indexmap::IndexMap;
+    use num_rational::Ratio;
+
+    let exact = [
+        ("x", Ratio::new(1, 10)),
+        ("y", Ratio::new(2, 10)),
+        ("sum", Ratio::from_integer(0)),
+        ("big", Ratio::new(3 * 10_i64.pow(16) + 1, 10_i64.pow(17))),
+    ];
+    let rounded = |id: &str| {
+        let (_, cost) = exact.iter().find(|(i, _)| *i == id).unwrap();
+        *cost.numer() as f64 / *cost.denom() as f64
+    };
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], rounded("x")),
+            ("y", "y", "y", &[], rounded("y")),
+            ("sum", "sum", "r", &["x", "y"], rounded("sum")),
+            ("big", "big", "r", &[], rounded("big")),
+        ],
+        &["r"],
+    );
+    let exact_costs: IndexMap<NodeId, Ratio<i64>> = exact
+        .into_iter()
+        .map(|(id, cost)| (id.into(), cost))
+        .collect();
+
+    let root = ClassId::from("r");
+    let rounded = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    assert_eq!(rounded.choices[&root], "big".into());
+
+    for exact in [
+        BottomUpExtractor.extract_with(&egraph, &exact_costs),
+        FasterBottomUpExtractor.extract_with(&egraph, &exact_costs),
+    ] {
+        exact.check(&egraph);
+        assert_eq!(exact.choices[&root], "sum".into());
+        assert_eq!(
+            exact.dag_cost_with(&egraph, &egraph.root_eclasses, &exact_costs),
+            Ratio::new(3, 10)
+        );
+    }
+}
+
+// tree_cost counts a shared class once per use, dag_cost counts it once.
+#[test]
+fn diamond_is_shared_in_dag_cost_only() {
+    let egraph = build(
+        &[
+            ("shared", "shared", "shared", &[], 5.0),
+            ("left", "left", "left", &["shared"], 1.0),
+            ("right", "right", "right", &["shared"], 1.0),
+            ("root", "root", "root", &["left", "right"], 1.0),
+        ],
+        &["root"],
+    );
+
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(
+            result.tree_cost(&egraph, &egraph.root_eclasses),
+            5.0 * 2.0 + 3.0,
+            "{name}"
+        );
+        assert_eq!(
+            result.dag_cost(&egraph, &egraph.root_eclasses),
+            5.0 + 3.0,
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn tree_formats_repeat_shared_subterms() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::json_tree::to_json_tree;
+    use crate::output::sexpr::to_sexprs;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("neg", "-", "neg", &["x"], 1.0),
+            ("mul", "*", "mul", &["neg", "neg"], 1.0),
+            ("out", "out", "out", &["x"], 1.0),
+        ],
+        &["mul", "out"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    assert_eq!(
+        to_sexprs(&egraph, &result, &egraph.root_eclasses),
+        "(* (- x) (- x))\n(out x)\n"
+    );
+
+    let trees = to_json_tree(&egraph, &result, &egraph.root_eclasses).unwrap();
+    let x = serde_json::json!({"op": "x", "cost": 1.0, "children": []});
+    let neg = serde_json::json!({"op": "-", "cost": 1.0, "children": [x]});
+    assert_eq!(
+        trees[0],
+        serde_json::json!({"op": "*", "cost": 1.0, "children": [neg, neg]})
+    );
+}
+
+#[test]
+fn greedy_max_is_valid_and_no_cheaper_than_optimal_tree() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::extract::greedy_max::GreedyMaxExtractor;
+
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        let max = GreedyMaxExtractor.extract(&egraph, roots);
+        max.check(&egraph);
+        let min = BottomUpExtractor.extract(&egraph, roots);
+        assert!(min.tree_cost(&egraph, roots) <= max.tree_cost(&egraph, roots) + EPSILON_ALLOWANCE);
+    }
+}
+
+#[test]
+fn dag_visitor_enters_shared_nodes_once() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::DagVisitor;
+    use egraph_serialize::ClassId;
+
+    #[derive(Default)]
+    struct Log(Vec<String>);
+    impl DagVisitor for Log {
+        fn enter_node(&mut self, _: &ClassId, node_id: &NodeId) -> anyhow::Result<()> {
+            self.0.push(format!("enter {node_id}"));
+            Ok(())
+        }
+        fn on_edge(&mut self, parent: &NodeId, child: &NodeId) -> anyhow::Result<()> {
+            self.0.push(format!("{parent}->{child}"));
+            Ok(())
+        }
+        fn leave_node(&mut self, _: &ClassId, node_id: &NodeId) -> anyhow::Result<()> {
+            self.0.push(format!("leave {node_id}"));
+            Ok(())
+        }
+        fn leave_root(&mut self, root: &ClassId) -> anyhow::Result<()> {
+            self.0.push(format!("root {root}"));
+            Ok(())
+        }
+    }
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("neg", "neg", "neg", &["x"], 1.0),
+            ("add", "add", "add", &["neg", "neg"], 1.0),
+        ],
+        &["add", "neg"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let mut log = Log::default();
+    result
+        .visit(&egraph, &egraph.root_eclasses, &mut log)
+        .unwrap();
+    assert_eq!(
+        log.0,
+        vec![
+            "enter add",
+            "add->neg",
+            "enter neg",
+            "neg->x",
+            "enter x",
+            "leave x",
+            "leave neg",
+            "add->neg",
+            "leave add",
+            "root add",
+            "root neg",
+        ]
+    );
+}
+
+#[test]
+fn opgraph_lists_shared_nodes_once_after_their_inputs() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::opgraph::to_opgraph;
+    use serde_json::json;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("neg", "-", "neg", &["x"], 1.0),
+            ("add", "+", "add", &["neg", "neg"], 1.0),
+        ],
+        &["add"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let graph = to_opgraph(&egraph, &result, &egraph.root_eclasses).unwrap();
+    let node = |id: &str, op: &str, inputs: &[&str]| json!({"id": id, "op": op, "inputs": inputs, "attrs": {"eclass": id, "cost": 1.0}});
+    assert_eq!(
+        graph,
+        json!({
+            "nodes": [node("x", "x", &[]), node("neg", "-", &["x"]), node("add", "+", &["neg", "neg"])],
+            "outputs": ["add"],
+        })
+    );
+}
+
+#[test]
+fn tree_marks_subtrees_it_already_drew_as_shared() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::tree::to_tree;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("neg", "-", "neg", &["x"], 1.0),
+            ("mul", "*", "mul", &["neg", "y"], 1.0),
+            ("add", "+", "add", &["mul", "neg"], 1.0),
+        ],
+        &["add", "y"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let tree = to_tree(&egraph, &result, &egraph.root_eclasses).unwrap();
+    assert_eq!(
+        tree,
+        "+\n├── *\n│   ├── -\n│   │   └── x\n│   └── y\n└── - * (shared)\ny * (shared)\n"
+    );
+}
+
+#[test]
+fn prometheus_metrics_are_labeled_with_the_extractor() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::prometheus::to_prometheus;
+
+    let egraph = build(
+        &[("x", "x", "x", &[], 2.0), ("f", "f", "f", &["x", "x"], 1.0)],
+        &["f"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let metrics = to_prometheus(&egraph, &result, &egraph.root_eclasses, "bottom-up", 1500);
+    for line in [
+        "# TYPE extraction_gym_dag_cost gauge",
+        "extraction_gym_duration_seconds{extractor=\"bottom-up\"} 0.0015",
+        "extraction_gym_dag_cost{extractor=\"bottom-up\"} 3",
+        "extraction_gym_tree_cost{extractor=\"bottom-up\"} 5",
+        "extraction_gym_nodes{extractor=\"bottom-up\"} 2",
+    ] {
+        assert!(
+            metrics.lines().any(|l| l == line),
+            "{line} missing from\n{metrics}"
+        );
+    }
+}
+
+#[test]
+fn python_takes_the_vars_and_returns_the_roots() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::python::to_python;
+
+    let egraph = build(
+        &[
+            ("a", "Var(\"a\")", "a", &[], 1.0),
+            ("b", "Var(\"b\")", "b", &[], 1.0),
+            ("two", "Num(2)", "two", &[], 1.0),
+            ("or", "Or", "or", &["a", "b"], 1.0),
+            ("shl", "Shl", "shl", &["or", "two"], 1.0),
+            ("root", "RootNode(\"y\")", "root", &["shl"], 1.0),
+            ("not", "Not", "not", &["a"], 1.0),
+        ],
+        &["root", "not"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let python = to_python(&egraph, &result, &egraph.root_eclasses).unwrap();
+    assert_eq!(
+        python,
+        "def f(a, b):\n    _t0 = a | b\n    _t1 = _t0 << 2\n    y = _t1\n    _t2 = ~a\n    return y, _t2\n"
+    );
+}
+
+#[test]
+fn tac_numbers_temporaries_children_first() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::tac::to_tac;
+
+    let egraph = build(
+        &[
+            ("a", "Var(\"a\"):8", "a", &[], 1.0),
+            ("b", "Var(\"b\")", "b", &[], 1.0),
+            ("two", "Num(2)", "two", &[], 1.0),
+            ("or", "Or:8", "or", &["a", "b"], 1.0),
+            ("shl", "Shl", "shl", &["or", "two"], 1.0),
+            ("rot", "Rotl(3)", "rot", &["shl"], 1.0),
+            ("root", "RootNode(\"y\")", "root", &["rot"], 1.0),
+            ("not", "Not", "not", &["or"], 1.0),
+        ],
+        &["root", "not"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let tac = to_tac(&egraph, &result, &egraph.root_eclasses).unwrap();
+    assert_eq!(
+        tac,
+        "t0 = OR a, b\nt1 = SHL t0, 2\nt2 = ROTL t1, 3\ny = t2\nOUTPUT y\nt3 = NOT t0\nOUTPUT t3\n"
+    );
+}
+
+#[test]
+fn ssa_takes_vars_as_arguments_and_returns_the_roots() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::tac::to_ssa;
+
+    let egraph = build(
+        &[
+            ("b", "Var(\"b\")", "b", &[], 1.0),
+            ("a", "Var(\"a\")", "a", &[], 1.0),
+            ("two", "Num(2)", "two", &[], 1.0),
+            ("div", "Div", "div", &["a", "two"], 1.0),
+            ("sub", "Sub:8", "sub", &["div", "b"], 1.0),
+            ("root", "RootNode(\"y\")", "root", &["sub"], 1.0),
+            ("shr", "Shr", "shr", &["div", "two"], 1.0),
+            ("neg", "Neg", "neg", &["b"], 1.0),
+            ("fneg", "FNeg", "fneg", &["b"], 1.0),
+        ],
+        &["root", "shr", "neg", "fneg"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let ssa = to_ssa(&egraph, &result, &egraph.root_eclasses).unwrap();
+    assert_eq!(
+        ssa,
+        "block(%a, %b):\n  %0 = sdiv %a, 2\n  %1 = sub %0, %b\n  %2 = lshr %0, 2\n  \
+         %3 = sub 0, %b\n  %4 = fneg %b\n  ret %1, %2, %3, %4\n"
+    );
+}
+
+#[test]
+fn verilog_ports_come_from_vars_and_root_nodes() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::verilog::{testbench, to_verilog};
+
+    let egraph = build(
+        &[
+            ("a", "Var(\"a\")", "a", &[], 1.0),
+            ("b", "Var(\"b\")", "b", &[], 1.0),
+            ("and", "And", "and", &["a", "b"], 1.0),
+            ("not", "Not", "not", &["and"], 1.0),
+            ("root", "RootNode(\"y\")", "root", &["not"], 1.0),
+        ],
+        &["root"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let verilog = to_verilog(&egraph, &result, &egraph.root_eclasses).unwrap();
+    assert_eq!(verilog.ports.inputs, vec!["a", "b"]);
+    assert_eq!(verilog.ports.outputs, vec!["y"]);
+    assert!(verilog.module.contains("wire [WIDTH-1:0] n0 = a & b;"));
+    assert!(verilog.module.contains("wire [WIDTH-1:0] n1 = ~n0;"));
+    assert!(verilog.module.contains("assign y = n1;"));
+
+    let tb = testbench(&verilog.ports);
+    assert!(tb.contains("dut (.a(a), .b(b), .y(y));"));
+    assert!(tb.contains("$display(\"a=%0d b=%0d y=%0d\", a, b, y);"));
+}
+
+#[test]
+fn verilog_wires_take_the_widths_of_their_ops() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::verilog::{testbench, to_verilog};
+
+    let egraph = build(
+        &[
+            ("a", "Var(\"a\"):16", "a", &[], 1.0),
+            ("b", "Var(\"b\"):16", "b", &[], 1.0),
+            ("sum", "Add:16", "sum", &["a", "b"], 1.0),
+            ("bit", "And", "bit", &["sum", "b"], 1.0),
+            ("y", "RootNode(\"y\")", "y", &["sum"], 1.0),
+            ("z", "RootNode(\"z\")", "z", &["bit"], 1.0),
+        ],
+        &["y", "z"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let verilog = to_verilog(&egraph, &result, &egraph.root_eclasses).unwrap();
+    assert!(verilog.module.contains("input [15:0] a"));
+    assert!(verilog.module.contains("output [15:0] y"));
+    assert!(verilog.module.contains("wire [15:0] n0 = a + b;"));
+    // And has no width, so it's 1 bit wide.
+    assert!(verilog.module.contains("wire [0:0] n1 = n0 & b;"));
+    assert!(verilog.module.contains("output [0:0] z"));
+    assert!(testbench(&verilog.ports).contains("reg [15:0] b;"));
+}
+
+#[test]
+fn blif_has_a_truth_table_per_gate() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::blif::to_blif;
+
+    let mut egraph = build(
+        &[
+            ("a", "Var(\"a\")", "a", &[], 1.0),
+            ("b", "Var(\"b\")", "b", &[], 1.0),
+            ("or", "Or", "or", &["a", "b"], 1.0),
+            ("not", "Not", "not", &["a"], 1.0),
+            ("mux", "Mux", "mux", &["b", "or", "not"], 1.0),
+            ("root", "RootNode(\"y\")", "root", &["mux"], 1.0),
+            ("add", "Add", "add", &["a", "b"], 1.0),
+        ],
+        &["root"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    assert_eq!(
+        to_blif(&egraph, &result, &egraph.root_eclasses).unwrap(),
+        ".model extracted\n.inputs b a\n.outputs y\n\
+         .names a b _n0\n1- 1\n-1 1\n\
+         .names a _n1\n0 1\n\
+         .names b _n0 _n1 _n2\n11- 1\n0-1 1\n\
+         .names _n2 y\n1 1\n.end\n"
+    );
+
+    egraph.root_eclasses = vec!["add".into()];
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    assert!(to_blif(&egraph, &result, &egraph.root_eclasses).is_err());
+
+    // An input could be mistaken for a gate's signal.
+    egraph.root_eclasses = vec!["not".into()];
+    egraph.nodes[&NodeId::from("a")].op = "Var(\"_n1\")".to_string();
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    assert!(to_blif(&egraph, &result, &egraph.root_eclasses).is_err());
+}
+
+#[test]
+fn yosys_json_has_a_cell_per_op() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::output::yosys_json::to_yosys_json;
+
+    let egraph = build(
+        &[
+            ("a", "Var(\"n0\"):4", "a", &[], 1.0),
+            ("b", "Var(\"b\"):4", "b", &[], 1.0),
+            ("three", "Num(3):4", "three", &[], 1.0),
+            ("sum", "Add:4", "sum", &["a", "b", "three"], 1.0),
+            ("not", "Not:4", "not", &["sum"], 1.0),
+            ("y", "RootNode(\"y\")", "y", &["not"], 1.0),
+        ],
+        &["y"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let netlist = to_yosys_json(&egraph, &result, &egraph.root_eclasses).unwrap();
+    let module = &netlist["modules"]["extracted"];
+    assert_eq!(
+        module["ports"]["n0"]["direction"],
+        serde_json::json!("input")
+    );
+    assert_eq!(
+        module["ports"]["n0"]["bits"],
+        serde_json::json!([2, 3, 4, 5])
+    );
+    assert_eq!(
+        module["ports"]["b"]["bits"],
+        serde_json::json!([6, 7, 8, 9])
+    );
+
+    // The three-way Add is a chain of two $add cells.
+    let cells = module["cells"].as_object().unwrap();
+    let types: Vec<&str> = cells
+        .values()
+        .map(|c| c["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(types, vec!["$add", "$add", "$not"]);
+    // An input named like a cell keeps its own net.
+    assert_eq!(
+        module["netnames"]["n0"]["bits"],
+        serde_json::json!([2, 3, 4, 5])
+    );
+    assert_eq!(
+        cells["$extract$1"]["connections"]["B"],
+        serde_json::json!(["1", "1", "0", "0"])
+    );
+    assert_eq!(
+        cells["$extract$1"]["parameters"]["Y_WIDTH"],
+        serde_json::json!(4)
+    );
+    assert_eq!(
+        module["ports"]["y"]["direction"],
+        serde_json::json!("output")
+    );
+    assert_eq!(
+        module["ports"]["y"]["bits"],
+        cells["$extract$2"]["connections"]["Y"]
+    );
+}
+
+#[test]
+fn bounded_depth_respects_max_depth() {
+    use crate::extract::bounded_depth::BoundedDepthExtractor;
+    use crate::ExtractionResult;
+    use egraph_serialize::ClassId;
+
+    fn depth(egraph: &EGraph, result: &ExtractionResult, class: &ClassId) -> usize {
+        let node = &egraph[&result.choices[class]];
+        1 + node
+            .children
+            .iter()
+            .map(|c| depth(egraph, result, egraph.nid_to_cid(c)))
+            .max()
+            .unwrap_or(0)
+    }
+
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        for max_depth in 1..6 {
+            let result = BoundedDepthExtractor { max_depth }.extract(&egraph, roots);
+            if roots.iter().all(|r| result.choices.contains_key(r)) {
+                result.check(&egraph);
+                assert!(roots
+                    .iter()
+                    .all(|r| depth(&egraph, &result, r) <= max_depth));
+            }
+        }
+        let max_depth = egraph.classes().len();
+        let result = BoundedDepthExtractor { max_depth }.extract(&egraph, roots);
+        result.check(&egraph);
+    }
+}
+
+#[test]
+fn costs_are_per_node_not_per_op() {
+    use crate::extract::global_greedy_dag::GlobalGreedyDagExtractor;
+
+    let egraph = build(
+        &[
+            ("x_dear", "x", "x", &[], 5.0),
+            ("x_cheap", "x", "x", &[], 1.0),
+            ("f_dear", "f", "r", &["x_dear"], 7.0),
+            ("f_cheap", "f", "r", &["x_dear"], 2.0),
+        ],
+        &["r"],
+    );
+
+    let mut all: Vec<(&str, Box<dyn Extractor>)> = extractors()
+        .into_iter()
+        .map(|(name, ed)| (name, ed.extractor))
+        .collect();
+    all.push(("global-greedy-dag", GlobalGreedyDagExtractor.boxed()));
+    for (name, extractor) in all {
+        let result = extractor.extract(&egraph, &egraph.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"x".into()], "x_cheap".into(), "{name}");
+        assert_eq!(result.choices[&"r".into()], "f_cheap".into(), "{name}");
+        assert_eq!(
+            result.dag_cost(&egraph, &egraph.root_eclasses),
+            3.0,
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn must_use_nodes_are_chosen() {
+    use crate::transform::must_use;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("cheap", "cheap", "y", &[], 1.0),
+            ("dear", "dear", "y", &["x"], 5.0),
+            ("loop", "loop", "y", &["root"], 0.0),
+            ("root", "root", "r", &["cheap"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let forced = must_use(&egraph, &["dear".into()]).unwrap();
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&forced, &forced.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"y".into()], "dear".into(), "{name}");
+    }
+
+    assert!(must_use(&egraph, &["loop".into()]).is_err());
+    assert!(must_use(&egraph, &["cheap".into(), "dear".into()]).is_err());
+    assert!(must_use(&egraph, &["missing".into()]).is_err());
+}
+
+#[test]
+fn excluding_top_cost_nodes_counts_lost_classes() {
+    use crate::transform::{exclude_top_cost, extractable_classes};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 9.0),
+            ("y", "y", "y", &[], 8.0),
+            ("cheap", "cheap", "z", &["y"], 1.0),
+            ("dear", "dear", "z", &["x"], 2.0),
+            ("root", "root", "r", &["dear"], 1.0),
+        ],
+        &["r"],
+    );
+
+    // 20% of the 5 nodes is just `x`, which takes `dear` with it but not its class.
+    let excluded = exclude_top_cost(&egraph, 20.0);
+    assert!(!excluded.nodes.contains_key(&NodeId::from("x")));
+    assert!(!excluded.nodes.contains_key(&NodeId::from("dear")));
+    assert_eq!(
+        extractable_classes(&egraph) - extractable_classes(&excluded),
+        1
+    );
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&excluded, &excluded.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"z".into()], "cheap".into(), "{name}");
+    }
+
+    assert_eq!(exclude_top_cost(&egraph, 0.0).nodes.len(), 5);
+    assert!(exclude_top_cost(&egraph, 40.0).nodes.is_empty());
+}
+
+#[test]
+fn graph_stats_count_classes_ops_and_cycles() {
+    use crate::stats::EGraphStats;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("add", "+", "r", &["x", "y"], 1.0),
+            ("mul", "*", "r", &["x", "y"], 1.0),
+            ("add2", "+", "r", &["add", "x"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let stats = EGraphStats::compute(&egraph);
+    assert_eq!((stats.classes, stats.nodes, stats.roots), (3, 5, 1));
+    assert_eq!(stats.max_class_size, 3);
+    assert!(stats.has_cycles);
+    assert_eq!(stats.op_counts.first(), Some((&"+".to_string(), &2)));
+
+    let acyclic = crate::transform::retain_nodes(&egraph, |id, _| *id != "add2".into());
+    assert!(!EGraphStats::compute(&acyclic).has_cycles);
+}
+
+// Two classes with a common child are connected, though neither reaches the other.
+#[test]
+fn weak_components_ignore_edge_direction() {
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("f", "f", "f", &["x"], 1.0),
+            ("g", "g", "g", &["x"], 1.0),
+            ("g2", "g2", "g", &["x", "x"], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("h", "h", "h", &["y"], 1.0),
+            ("z", "z", "z", &[], 1.0),
+        ],
+        &["f", "g", "h"],
+    );
+
+    assert_eq!(
+        crate::stats::weak_components(&egraph),
+        [(3, 4), (2, 2), (1, 1)]
+    );
+}
+
+#[test]
+fn report_json_accounts_for_sharing_and_depth() {
+    use crate::output::report_json::to_report_json;
+    use serde_json::json;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 2.0),
+            ("neg", "-", "n", &["x"], 1.0),
+            ("add", "+", "r", &["neg", "neg"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let result =
+        crate::extract::bottom_up::BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let report = json!({"name": "test", "extractor": "bottom-up", "micros": 7});
+    let rich = to_report_json(&egraph, &result, &egraph.root_eclasses, &report).unwrap();
+
+    assert_eq!(rich["choices"]["n"], json!("neg"));
+    assert_eq!(rich["cost"], json!({"tree": 7.0, "dag": 4.0, "depth": 3}));
+    assert_eq!(rich["ops"]["-"], json!({"count": 1, "cost": 1.0}));
+    assert_eq!(
+        rich["sharing"],
+        json!({"nodes": 3, "shared_nodes": 1, "saved": 3.0})
+    );
+    assert_eq!(rich["timing"], json!({"micros": 7}));
+    assert!(rich.get("gap").is_none());
+}
+
+#[test]
+fn jsonl_is_the_report_on_one_line() {
+    use crate::output::{render, OutputFormat};
+    use serde_json::json;
+
+    let egraph = generate_random_egraph();
+    let result =
+        crate::extract::bottom_up::BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let report = json!({"name": "test", "extractor": "bottom-up", "dag": 4.0});
+    let line = render(
+        OutputFormat::Jsonl,
+        &egraph,
+        &result,
+        &egraph.root_eclasses,
+        &report,
+    )
+    .unwrap();
+    assert_eq!(line.lines().count(), 1);
+    assert!(line.ends_with('\n'));
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+        report
+    );
+}
+
+#[test]
+fn iteration_bias_steers_between_old_and_new_nodes() {
+    use crate::transform::{bias_by_iteration, read_node_field, ITERATION_FIELD};
+
+    let filename = std::env::temp_dir().join("extraction_gym_iteration_bias.json");
+    std::fs::write(
+        &filename,
+        r#"{"nodes": {
+            "old": {"op": "old", "children": [], "eclass": "r", "cost": 2.0, "iteration": 0},
+            "new": {"op": "new", "children": [], "eclass": "r", "cost": 3.0, "iteration": 4}
+        }, "root_eclasses": ["r"]}"#,
+    )
+    .unwrap();
+    let filename = filename.to_str().unwrap();
+    let egraph = EGraph::from_json_file(filename).unwrap();
+    let iterations = read_node_field(filename, ITERATION_FIELD).unwrap();
+    assert_eq!(iterations[&NodeId::from("new")], 4.0);
+
+    for (weight, chosen) in [(0.0, "old"), (-0.5, "new")] {
+        let biased = bias_by_iteration(&egraph, &iterations, weight);
+        for (name, ed) in extractors() {
+            let result = ed.extractor.extract(&biased, &biased.root_eclasses);
+            result.check(&egraph);
+            assert_eq!(result.choices[&"r".into()], chosen.into(), "{name}");
+        }
+    }
+}
+
+#[test]
+fn node_values_keep_any_json() {
+    use crate::transform::{read_node_field, read_node_values};
+
+    let filename = std::env::temp_dir().join("extraction_gym_node_values.json");
+    std::fs::write(
+        &filename,
+        r#"{"nodes": {
+            "x": {"op": "x", "children": [], "eclass": "x", "cost": 1.0, "source": "foo.rs:42"},
+            "y": {"op": "y", "children": [], "eclass": "y", "cost": 1.0,
+                  "source": {"file": "foo.rs", "line": 7}},
+            "z": {"op": "z", "children": [], "eclass": "z", "cost": 1.0}
+        }, "root_eclasses": ["x"]}"#,
+    )
+    .unwrap();
+    let filename = filename.to_str().unwrap();
+    let sources = read_node_values(filename, "source").unwrap();
+    assert_eq!(sources.len(), 2);
+    assert_eq!(sources[&NodeId::from("x")], serde_json::json!("foo.rs:42"));
+    assert_eq!(sources[&NodeId::from("y")]["line"], serde_json::json!(7));
+    // Only numbers are read as numeric fields.
+    assert!(read_node_field(filename, "source").unwrap().is_empty());
+}
+
+// Each dim comes back in the order asked for, as the dag cost of just that field.
+#[test]
+fn report_dims_are_read_together() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::transform::read_node_fields;
+
+    let filename = std::env::temp_dir().join("extraction_gym_report_dims.json");
+    std::fs::write(
+        &filename,
+        r#"{"nodes": {
+            "x": {"op": "x", "children": [], "eclass": "x", "cost": 1.0, "area": 2.0, "power": 0.5},
+            "f": {"op": "f", "children": ["x", "x"], "eclass": "r", "cost": 1.0, "area": 3.0},
+            "g": {"op": "g", "children": [], "eclass": "r", "cost": 5.0, "area": 1.0, "power": 9.0}
+        }, "root_eclasses": ["r"]}"#,
+    )
+    .unwrap();
+    let filename = filename.to_str().unwrap();
+    let egraph = EGraph::from_json_file(filename).unwrap();
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+
+    let dims = read_node_fields(filename, &["power", "area", "delay"]).unwrap();
+    assert_eq!(dims.len(), 3);
+    assert!(dims[2].is_empty());
+    let totals: Vec<f64> = dims
+        .iter()
+        .map(|values| {
+            let model: indexmap::IndexMap<NodeId, crate::Cost> = egraph
+                .nodes
+                .keys()
+                .map(|id| {
+                    (
+                        id.clone(),
+                        NotNan::new(values.get(id).copied().unwrap_or(0.0)).unwrap(),
+                    )
+                })
+                .collect();
+            result
+                .dag_cost_with(&egraph, &egraph.root_eclasses, &model)
+                .into_inner()
+        })
+        .collect();
+    // x is shared, so it counts once.
+    assert_eq!(totals, vec![0.5, 5.0, 0.0]);
+}
+
+#[test]
+fn constants_are_free_and_written_as_their_literal() {
+    use crate::transform::mark_constants;
+    use rustc_hash::FxHashMap;
+
+    let egraph = build(
+        &[
+            ("c", "Const:8", "c", &[], 5.0),
+            ("k", "Lit", "k", &[], 5.0),
+            ("x", "Var(\"x\")", "x", &[], 1.0),
+            ("add", "Add", "add", &["x", "c", "k"], 1.0),
+        ],
+        &["add"],
+    );
+
+    let values: FxHashMap<NodeId, serde_json::Value> = [
+        (NodeId::from("c"), serde_json::json!(3)),
+        (NodeId::from("k"), serde_json::json!("0x1f")),
+    ]
+    .into_iter()
+    .collect();
+    let marked = mark_constants(&egraph, &values).unwrap();
+    let node = |id: &str| &marked.nodes[&NodeId::from(id)];
+    assert_eq!(
+        (node("c").op.as_str(), node("k").op.as_str()),
+        ("Num(3):8", "Num(0x1f)")
+    );
+    assert_eq!(
+        (node("c").cost.into_inner(), node("x").cost.into_inner()),
+        (0.0, 1.0)
+    );
+
+    let result =
+        crate::extract::bottom_up::BottomUpExtractor.extract(&marked, &marked.root_eclasses);
+    let tac = crate::output::tac::to_tac(&marked, &result, &marked.root_eclasses).unwrap();
+    assert!(tac.contains("ADD x, 3, 0x1f"), "{tac}");
+
+    // A constant can't have children.
+    let with_children = [(NodeId::from("add"), serde_json::json!(1))];
+    assert!(mark_constants(&egraph, &with_children.into_iter().collect()).is_err());
+}
+
+#[test]
+fn heatmap_has_a_row_per_reachable_class() {
+    use crate::output::heatmap::{to_heatmap_csv, HEADER};
+
+    let egraph = build(
+        &[
+            ("x", "Var(\"x\")", "x", &[], 1.0),
+            ("y", "y", "y", &[], 5.0),
+            ("neg", "-", "n", &["x"], 1.0),
+            ("sub", "-", "n", &["y"], 0.0),
+            ("add", "+", "r", &["neg", "neg"], 1.0),
+            ("unused", "z", "z", &[], 1.0),
+        ],
+        &["r"],
+    );
+
+    let result =
+        crate::extract::bottom_up::BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let csv = to_heatmap_csv(&egraph, &result, &egraph.root_eclasses);
+    let mut rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows.remove(0), HEADER);
+    rows.sort();
+    assert_eq!(
+        rows,
+        ["n,2,1,-", "r,5,1,+", "x,1,1,\"Var(\"\"x\"\")\"", "y,5,0,y"]
+    );
+}
+
+#[test]
+fn max_distance_keeps_classes_near_the_roots() {
+    use crate::transform::within_distance;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("deep", "deep", "y", &["x"], 1.0),
+            ("leaf", "leaf", "y", &[], 1.0),
+            ("root", "root", "r", &["deep"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let near = within_distance(&egraph, 1);
+    assert!(!near.nodes.contains_key(&NodeId::from("x")));
+    assert!(!near.nodes.contains_key(&NodeId::from("deep")));
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&near, &near.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"y".into()], "leaf".into(), "{name}");
+    }
+
+    assert_eq!(within_distance(&egraph, 2).nodes.len(), 4);
+    assert!(within_distance(&egraph, 0).nodes.is_empty());
+}
+
+#[test]
+fn objective_weights_nodes_by_their_metrics() {
+    use crate::objective::Objective;
+    use crate::transform::weight_by_objective;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("wide", "wide", "r", &["x", "y"], 1.0),
+            ("narrow", "narrow", "r", &["x"], 2.0),
+        ],
+        &["r"],
+    );
+
+    let objective: Objective = "cost + 2 * (arity - depth/4)".parse().unwrap();
+    assert!(objective.is_linear());
+    let weighted = weight_by_objective(&egraph, &objective).unwrap();
+    assert_eq!(weighted.nodes[&NodeId::from("wide")].cost, 5.0);
+    assert_eq!(weighted.nodes[&NodeId::from("x")].cost, 0.5);
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&weighted, &weighted.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"r".into()], "narrow".into(), "{name}");
+    }
+
+    assert!(!"cost * depth".parse::<Objective>().unwrap().is_linear());
+    assert!(!"cost / (1 + arity)"
+        .parse::<Objective>()
+        .unwrap()
+        .is_linear());
+    assert!("cost +".parse::<Objective>().is_err());
+    assert!("cost + size".parse::<Objective>().is_err());
+    assert!(weight_by_objective(&egraph, &"cost - 2".parse().unwrap()).is_err());
+}
+
+#[test]
+fn cost_table_expressions_depend_on_the_number_of_children() {
+    use crate::objective::Objective;
+    use crate::transform::cost_by_table;
+
+    let egraph = build(
+        &[
+            ("a", "a", "a", &[], 1.0),
+            ("b", "b", "b", &[], 1.0),
+            ("c", "c", "c", &[], 1.0),
+            ("d", "d", "d", &[], 1.0),
+            ("e", "e", "e", &[], 1.0),
+            ("add2", "Add", "add2", &["a", "b"], 1.0),
+            ("add5", "Add", "add5", &["a", "b", "c", "d", "e"], 1.0),
+            ("mul", "Mul", "mul", &["a", "b"], 1.0),
+        ],
+        &[],
+    );
+
+    let table: indexmap::IndexMap<String, Objective> = [
+        ("Add".to_string(), "ceil(log2(n))".parse().unwrap()),
+        ("Mul".to_string(), Objective::Num(3.0)),
+    ]
+    .into_iter()
+    .collect();
+    let costed = cost_by_table(&egraph, &table).unwrap();
+    let cost = |id: &str| costed.nodes[&NodeId::from(id)].cost.into_inner();
+    assert_eq!(
+        (cost("add2"), cost("add5"), cost("mul"), cost("a")),
+        (1.0, 3.0, 3.0, 1.0)
+    );
+
+    assert_eq!(
+        "floor(n / 2) + 1"
+            .parse::<Objective>()
+            .unwrap()
+            .eval(&Default::default()),
+        1.0
+    );
+    assert!(!"log2(n)".parse::<Objective>().unwrap().is_linear());
+    assert!("log2 n".parse::<Objective>().is_err());
+    let negative = [("Add".to_string(), "n - 3".parse().unwrap())];
+    assert!(cost_by_table(&egraph, &negative.into_iter().collect()).is_err());
+}
+
+#[test]
+fn cost_table_patterns_prefer_the_most_specific_key() {
+    use crate::objective::Objective;
+    use crate::transform::cost_by_table;
+
+    let egraph = build(
+        &[
+            ("i32", "Mul(i32)", "i32", &[], 1.0),
+            ("i64", "Mul(i64)", "i64", &[], 1.0),
+            ("u8", "Mul(u8)", "u8", &[], 1.0),
+            ("mulu", "Mulu", "mulu", &[], 1.0),
+            ("add", "Add", "add", &[], 1.0),
+        ],
+        &[],
+    );
+
+    let table: indexmap::IndexMap<String, Objective> = [
+        ("*".to_string(), Objective::Num(7.0)),
+        ("Mul*".to_string(), Objective::Num(3.0)),
+        ("Mul(u*".to_string(), Objective::Num(2.0)),
+        ("Mul(i64)".to_string(), Objective::Num(4.0)),
+    ]
+    .into_iter()
+    .collect();
+    let costed = cost_by_table(&egraph, &table).unwrap();
+    let cost = |id: &str| costed.nodes[&NodeId::from(id)].cost.into_inner();
+    assert_eq!(
+        (
+            cost("i32"),
+            cost("i64"),
+            cost("u8"),
+            cost("mulu"),
+            cost("add")
+        ),
+        (3.0, 4.0, 2.0, 3.0, 7.0)
+    );
+}
+
+#[test]
+fn excluded_classes_are_not_used_even_indirectly() {
+    use crate::transform::exclude_classes;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("via_x", "via_x", "y", &["x"], 1.0),
+            ("leaf", "leaf", "y", &[], 5.0),
+            ("root", "root", "r", &["via_x"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let excluded = exclude_classes(&egraph, &["x".into()]).unwrap();
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&excluded, &excluded.root_eclasses);
+        result.check(&egraph);
+        assert!(!result.choices.contains_key(&"x".into()), "{name}");
+        assert_eq!(result.choices[&"y".into()], "leaf".into(), "{name}");
+    }
+
+    assert!(exclude_classes(&egraph, &["r".into()]).is_err());
+}
+
+#[test]
+fn available_classes_are_free() {
+    use crate::transform::make_available;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 10.0),
+            ("big", "big", "b", &["x"], 10.0),
+            ("also_big", "also_big", "b", &["x"], 20.0),
+            ("via_big", "via_big", "r", &["big"], 1.0),
+            ("cheap", "cheap", "r", &[], 5.0),
+        ],
+        &["r"],
+    );
+
+    let free = make_available(&egraph, &["b".into()]).unwrap();
+    assert_eq!(free.classes()[&"b".into()].nodes.len(), 1);
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&free, &free.root_eclasses);
+        result.check(&free);
+        assert_eq!(result.choices[&"r".into()], "via_big".into(), "{name}");
+        assert_eq!(result.dag_cost(&free, &free.root_eclasses), 1.0, "{name}");
+    }
+
+    assert!(make_available(&egraph, &["missing".into()]).is_err());
+}
+
+#[test]
+fn ties_go_to_the_lowest_node_id() {
+    use crate::transform::sort_by_node_id;
+
+    let egraph = build(
+        &[
+            ("x2", "x2", "x", &[], 1.0),
+            ("x1", "x1", "x", &[], 1.0),
+            ("r2", "r2", "r", &["x2"], 1.0),
+            ("r1", "r1", "r", &["x1"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let sorted = sort_by_node_id(&egraph);
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&sorted, &sorted.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"x".into()], "x1".into(), "{name}");
+        assert_eq!(result.choices[&"r".into()], "r1".into(), "{name}");
+    }
+}
+
+#[test]
+fn tie_break_keys_prefer_higher_priority_ops() {
+    use crate::transform::{sort_by_tie_break_keys, TieBreakKey};
+
+    let egraph = build(
+        &[
+            ("x", "Var(\"x\")", "x", &[], 1.0),
+            ("mul", "Mul(Num(2))", "r", &["x"], 1.0),
+            ("shl", "Shl:8", "r", &["x"], 1.0),
+            ("add", "Add", "r", &["x"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let keys: Vec<TieBreakKey> = ["Shl=2", "Mul*=1", "Add=-1"]
+        .iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    let sorted = sort_by_tie_break_keys(&egraph, &keys);
+    let order: Vec<NodeId> = sorted.nodes.keys().cloned().collect();
+    let expected: Vec<NodeId> = ["x", "shl", "mul", "add"].map(NodeId::from).into();
+    assert_eq!(order, expected);
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&sorted, &sorted.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"r".into()], "shl".into(), "{name}");
+    }
+
+    assert!("Shl".parse::<TieBreakKey>().is_err());
+    assert!("Shl=high".parse::<TieBreakKey>().is_err());
+}
+
+// A key's priority outranks the size of the dag when refitting ties.
+#[test]
+fn tie_break_keys_outrank_dag_size() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::transform::{tie_break_priority, TieBreakKey};
+    use crate::TieFit;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 0.0),
+            ("y", "y", "y", &["x"], 0.0),
+            ("mul", "Mul", "r", &["x"], 0.0),
+            ("shl", "Shl", "r", &["y"], 0.0),
+        ],
+        &["r"],
+    );
+
+    let keys: Vec<TieBreakKey> = vec!["Shl=1".parse().unwrap()];
+    let mut result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    result.refit_ties(&egraph, TieFit::Last);
+    assert_eq!(result.choices[&"r".into()], "mul".into());
+    result.refit_ties_with(&egraph, TieFit::First, |node| {
+        tie_break_priority(&keys, node)
+    });
+    result.check(&egraph);
+    assert_eq!(result.choices[&"r".into()], "shl".into());
+}
+
+#[test]
+fn drifted_roots_compares_tracked_and_recomputed_costs() {
+    use crate::extract::faster_greedy_dag::{drifted_roots, FasterGreedyDagExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 5.0),
+            ("left", "left", "left", &["x"], 1.0),
+            ("right", "right", "right", &["x"], 1.0),
+            ("root", "root", "root", &["left", "right"], 1.0),
+        ],
+        &["root"],
+    );
+
+    let (result, mut totals) = FasterGreedyDagExtractor.extract_with_totals(&egraph);
+    assert_eq!(totals[&"root".into()], 8.0);
+    assert!(drifted_roots(&egraph, &egraph.root_eclasses, &result, &totals).is_empty());
+
+    totals.insert("root".into(), NotNan::new(9.0).unwrap());
+    let drifted = drifted_roots(&egraph, &egraph.root_eclasses, &result, &totals);
+    assert_eq!(
+        drifted,
+        vec![(
+            "root".into(),
+            8.0.try_into().unwrap(),
+            9.0.try_into().unwrap()
+        )]
+    );
+
+    let unextracted = ["nowhere".into()];
+    assert_eq!(
+        drifted_roots(&egraph, &unextracted, &result, &totals),
+        vec![("nowhere".into(), crate::INFINITY, crate::INFINITY)]
+    );
+}
+
+#[test]
+fn tie_fit_chooses_between_equally_cheap_nodes() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::extract::faster_bottom_up::FasterBottomUpExtractor;
+    use crate::TieFit;
+
+    let egraph = build(
+        &[
+            ("b", "b", "x", &[], 1.0),
+            ("a", "a", "x", &[], 1.0),
+            ("c", "c", "x", &[], 1.0),
+            ("free", "free", "z", &[], 0.0),
+            ("loop", "loop", "z", &["y"], 0.0),
+            ("y", "y", "y", &["free"], 0.0),
+            ("root", "root", "r", &["b", "y"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let extractors: [&dyn Extractor; 2] = [&BottomUpExtractor, &FasterBottomUpExtractor];
+    for extractor in extractors {
+        for (tie_fit, chosen) in [
+            (TieFit::First, "b"),
+            (TieFit::Last, "c"),
+            (TieFit::SmallestId, "a"),
+        ] {
+            let mut result = extractor.extract(&egraph, &egraph.root_eclasses);
+            result.refit_ties(&egraph, tie_fit);
+            result.check(&egraph);
+            assert_eq!(result.choices[&"x".into()], chosen.into());
+            // The last node in z ties, but it would make a cycle through y.
+            assert_eq!(result.choices[&"z".into()], "free".into());
+        }
+    }
+
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let mut result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+        let tree_cost = result.tree_cost(&egraph, &egraph.root_eclasses);
+        result.refit_ties(&egraph, TieFit::Last);
+        result.check(&egraph);
+        assert!(result
+            .find_cycles(&egraph, &egraph.root_eclasses)
+            .is_empty());
+        assert_eq!(result.tree_cost(&egraph, &egraph.root_eclasses), tree_cost);
+    }
+}
+
+#[test]
+fn roots_sharing_a_class_are_one_component() {
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("z", "z", "z", &[], 1.0),
+            ("f", "f", "a", &["x"], 1.0),
+            ("g", "g", "b", &["z"], 1.0),
+            // only an unchosen node of c reaches x
+            ("h", "h", "c", &["y"], 1.0),
+            ("k", "k", "c", &["x"], 1.0),
+        ],
+        &["a", "b", "c", "a"],
+    );
+
+    let components = crate::stats::root_components(&egraph);
+    assert_eq!(
+        components,
+        vec![vec!["a".into(), "c".into()], vec!["b".into()]]
+    );
+
+    for (_, ed) in extractors().into_iter() {
+        let result = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        result.check(&egraph);
+        let summed: Cost = components
+            .iter()
+            .map(|roots| result.dag_cost(&egraph, roots))
+            .sum();
+        assert_eq!(summed, result.dag_cost(&egraph, &egraph.root_eclasses));
+    }
+}
+
+// a and b are each other's negation, which costs nothing, so following the cycle looks
+// free to an extractor that doesn't guard against it.
+#[test]
+fn dp_scc_breaks_a_zero_cost_cycle() {
+    use crate::extract::dp_scc::DpSccExtractor;
+
+    let egraph = build(
+        &[
+            ("x", "x", "a", &[], 5.0),
+            ("neg_b", "-", "a", &["neg_a"], 0.0),
+            ("y", "y", "b", &[], 1.0),
+            ("neg_a", "-", "b", &["neg_b"], 0.0),
+            ("f", "f", "r", &["neg_b", "x"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let result = DpSccExtractor.extract(&egraph, &egraph.root_eclasses);
+    result.check(&egraph);
+    assert_eq!(result.choices[&"a".into()], "neg_b".into());
+    assert_eq!(result.choices[&"b".into()], "y".into());
+    assert_eq!(result.tree_cost(&egraph, &egraph.root_eclasses), 3.0);
+}
+
+#[test]
+fn parallel_extraction_of_disjoint_roots_matches_serial() {
+    let mut egraph = EGraph::default();
+    for prefix in ["a", "b"] {
+        let part = generate_random_egraph();
+        let rename = |id: &str| format!("{prefix}{id}");
+        for (node_id, node) in &part.nodes {
+            egraph.add_node(
+                rename(&node_id.to_string()).into(),
+                Node {
+                    children: node
+                        .children
+                        .iter()
+                        .map(|c| rename(&c.to_string()).into())
+                        .collect(),
+                    eclass: rename(&node.eclass.to_string()).into(),
+                    ..node.clone()
+                },
+            );
+        }
+        egraph.root_eclasses.extend(
+            part.root_eclasses
+                .iter()
+                .map(|r| rename(&r.to_string()).into()),
+        );
+    }
+    assert!(crate::stats::root_components(&egraph).len() >= 2);
+
+    for (name, ed) in extractors() {
+        let serial = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        let parallel = crate::extract_in_parallel(ed.extractor.as_ref(), &egraph);
+        parallel.check(&egraph);
+        let (serial, parallel) = (
+            serial.dag_cost(&egraph, &egraph.root_eclasses),
+            parallel.dag_cost(&egraph, &egraph.root_eclasses),
+        );
+        assert!((serial - parallel).abs() < EPSILON_ALLOWANCE, "{name}");
+    }
+}
+
+#[test]
+fn extractors_choose_the_same_way_twice() {
+    use crate::ExtractionResult;
+
+    let egraph = generate_random_egraph();
+    for (name, ed) in extractors() {
+        let first = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        let second = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        assert!(
+            crate::differing_choices(&first, &second).is_empty(),
+            "{name}"
+        );
+        assert!(first.choices.keys().eq(second.choices.keys()), "{name}");
+    }
+
+    let mut changed = ExtractionResult::default();
+    changed.choose("a".into(), "x".into());
+    changed.choose("b".into(), "y".into());
+    let mut other = ExtractionResult::default();
+    other.choose("a".into(), "z".into());
+    other.choose("c".into(), "w".into());
+    assert_eq!(
+        crate::differing_choices(&changed, &other),
+        vec!["a".into(), "b".into(), "c".into()]
+    );
+}
+
+#[test]
+fn target_shapes_parse_with_wildcards() {
+    use crate::extract::shape::Shape;
+
+    let node = |op: &str, children: Vec<Shape>| Shape::Node(op.to_string(), children);
+    assert_eq!(
+        "(Add ? (Mul x ?))".parse::<Shape>(),
+        Ok(node(
+            "Add",
+            vec![Shape::Any, node("Mul", vec![node("x", vec![]), Shape::Any])]
+        ))
+    );
+    assert_eq!("?".parse::<Shape>(), Ok(Shape::Any));
+    for bad in ["(Add ?", "(? x)", "()", "(Add ?))", ")"] {
+        assert!(bad.parse::<Shape>().is_err(), "{bad}");
+    }
+}
+
+#[test]
+fn uncovered_lists_nodes_the_extraction_does_not_use() {
+    use crate::transform::must_use;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("probe", "probe", "p", &["y"], 1.0),
+            ("f", "f", "r", &["x"], 1.0),
+            ("g", "g", "r", &["probe"], 5.0),
+            ("h", "h", "r", &["x", "y"], 2.0),
+        ],
+        &["r"],
+    );
+
+    let cover: Vec<NodeId> = vec!["probe".into(), "y".into()];
+
+    for (name, ed) in extractors() {
+        let result = ed.extractor.extract(&egraph, &egraph.root_eclasses);
+        let uncovered = result.uncovered(&egraph, &egraph.root_eclasses, &cover);
+        assert_eq!(uncovered, vec![&cover[0], &cover[1]], "{name}");
+
+        // restricting the classes doesn't make the extraction use them
+        let restricted = must_use(&egraph, &cover).unwrap();
+        let result = ed.extractor.extract(&restricted, &restricted.root_eclasses);
+        assert_eq!(
+            result
+                .uncovered(&restricted, &restricted.root_eclasses, &cover)
+                .len(),
+            2
+        );
+    }
+}
+
+#[test]
+fn queries_cost_the_same_as_extracting_their_roots_alone() {
+    use egraph_serialize::ClassId;
+
+    let egraph = generate_random_egraph();
+    let extractor = &extractors()["faster-greedy-dag"].extractor;
+    let mut queries = indexmap::IndexMap::new();
+    for (i, root) in egraph.root_eclasses.iter().enumerate() {
+        queries.insert(format!("q{i}"), vec![root.clone()]);
+    }
+    queries.insert("all".to_string(), egraph.root_eclasses.clone());
+
+    let per_query = crate::extract_queries(extractor.as_ref(), &egraph, &egraph, &queries).unwrap();
+    for (name, roots) in &queries {
+        let dag = extractor
+            .extract(&egraph, roots)
+            .dag_cost(&egraph, roots)
+            .into_inner();
+        let reported = per_query[name.as_str()]["dag"].as_f64().unwrap();
+        assert!((dag - reported).abs() < EPSILON_ALLOWANCE, "{name}");
+    }
+
+    queries.insert("missing".to_string(), vec![ClassId::from("no such class")]);
+    assert!(crate::extract_queries(extractor.as_ref(), &egraph, &egraph, &queries).is_err());
+}
+
+#[test]
+fn strict_input_names_the_path_of_the_bad_field() {
+    use serde_json::json;
+
+    let node = |children: serde_json::Value| json!({"op": "f", "children": children, "eclass": "c", "cost": 1.0});
+    let valid =
+        json!({"nodes": {"x": node(json!([])), "y": node(json!(["x"]))}, "root_eclasses": ["c"]});
+    crate::schema::validate(&valid).unwrap();
+
+    let error = |json: serde_json::Value| crate::schema::validate(&json).unwrap_err().to_string();
+    assert_eq!(
+        error(
+            json!({"nodes": {"x": {"op": "f", "children": [], "eclass": "c"}}, "root_eclasses": []})
+        ),
+        "$.nodes[\"x\"].cost: missing"
+    );
+    assert_eq!(
+        error(json!({"nodes": {"x": node(json!(["x", 3]))}, "root_eclasses": []})),
+        "$.nodes[\"x\"].children[1]: expected a node id, found a number"
+    );
+    assert_eq!(
+        error(json!({"nodes": {"x": node(json!(["z"]))}, "root_eclasses": []})),
+        "$.nodes[\"x\"].children[0]: no node \"z\" in $.nodes"
+    );
+    assert!(error(json!({"nodes": {}})).starts_with("$.root_eclasses: missing"));
+}
+
+// Only keys of $.nodes count, not the same ids as fields, children or class data.
+#[test]
+fn duplicate_node_ids_are_found_in_the_text() {
+    let json = r#"{
+        "nodes": {
+            "x": {"op": "x", "children": [], "eclass": "x", "cost": 1},
+            "y": {"op": "y", "children": ["x", "x"], "eclass": "x", "cost": 1},
+            "x": {"op": "\"x\"", "children": [], "eclass": "x", "cost": 2}
+        },
+        "root_eclasses": ["x", "x"],
+        "class_data": {"x": {}, "y": {}, "y": {}}
+    }"#;
+    assert_eq!(crate::schema::duplicate_node_ids(json), vec!["x"]);
+    assert!(crate::schema::duplicate_node_ids(r#"{"nodes": {"a": {}, "b": {"a": 1}}}"#).is_empty());
+}
+
+#[test]
+fn choice_traces_end_with_the_final_choices() {
+    use crate::extract::{bottom_up::BottomUpExtractor, greedy_dag::GreedyDagExtractor};
+
+    for _ in 0..20 {
+        let egraph = generate_random_egraph();
+
+        let mut last = indexmap::IndexMap::new();
+        let mut iterations = vec![];
+        let result = BottomUpExtractor.extract_traced_with(
+            &egraph,
+            &crate::extract::NodeCost,
+            |iteration, class_id, node_id, _| {
+                iterations.push(iteration);
+                last.insert(class_id.clone(), node_id.clone());
+            },
+        );
+        assert!(iterations.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(last.len(), result.choices.len());
+        for (class_id, node_id) in &last {
+            assert_eq!(&result.choices[class_id], node_id);
+        }
+
+        let mut last = indexmap::IndexMap::new();
+        let result = GreedyDagExtractor.extract_traced(&egraph, |_, class_id, node_id, _| {
+            last.insert(class_id.clone(), node_id.clone());
+        });
+        assert_eq!(last.len(), result.choices.len());
+        for (class_id, node_id) in &last {
+            assert_eq!(&result.choices[class_id], node_id);
+        }
+    }
+}
+
+// Every extractor --choice-trace takes is one that can be asked for, and the last line for
+// each class in its trace is the choice it ended with.
+#[test]
+fn choice_trace_follows_registered_extractors() {
+    let registered = extractors();
+    for name in crate::TRACED_EXTRACTORS {
+        assert!(registered.contains_key(name), "{name} isn't registered");
+        for _ in 0..20 {
+            let egraph = generate_random_egraph();
+            let (result, trace) = crate::extract_traced(name, &egraph);
+            let mut last = indexmap::IndexMap::new();
+            for line in trace.lines().skip(1) {
+                let fields: Vec<&str> = line.split('\t').collect();
+                assert_eq!(fields.len(), 4);
+                last.insert(fields[1].to_string(), fields[2].to_string());
+            }
+            assert_eq!(last.len(), result.choices.len());
+            for (class_id, node_id) in &result.choices {
+                assert_eq!(last[&class_id.to_string()], node_id.to_string());
+            }
+        }
+    }
+}
+
+#[test]
+fn node_budget_keeps_the_roots_that_fit() {
+    use crate::extract::node_budget::NodeBudgetExtractor;
+
+    for _ in 0..50 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        let all = NodeBudgetExtractor { budget: usize::MAX }.extract(&egraph, roots);
+        let needed = all.choices.len();
+        for budget in [0, needed / 2, needed] {
+            let result = NodeBudgetExtractor { budget }.extract(&egraph, roots);
+            assert!(result.choices.len() <= budget);
+            let covered: Vec<_> = roots
+                .iter()
+                .filter(|root| result.choices.contains_key(*root))
+                .cloned()
+                .collect();
+            assert!(result.find_cycles(&egraph, &covered).is_empty());
+            if budget == needed {
+                assert_eq!(
+                    covered.len(),
+                    roots
+                        .iter()
+                        .filter(|r| all.choices.contains_key(*r))
+                        .count()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn critical_path_adds_up_the_slowest_ops() {
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("m", "Mul", "m", &["x", "x"], 1.0),
+            ("a1", "Add", "a1", &["x", "x"], 1.0),
+            ("a2", "Add", "a2", &["a1", "x"], 1.0),
+            ("r", "Add", "r", &["m", "a2"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let result = extractors()["bottom-up"]
+        .extractor
+        .extract(&egraph, &egraph.root_eclasses);
+
+    let critical_path = |table: &[(&str, f64)]| {
+        let table = table
+            .iter()
+            .map(|&(op, delay)| (op.to_string(), crate::objective::Objective::Num(delay)))
+            .collect();
+        let delays = crate::transform::delays_by_table(&egraph, &table).unwrap();
+        result.critical_path_with(&egraph, &egraph.root_eclasses, &delays)
+    };
+    // Without delays it counts levels: r, a2, a1, x.
+    assert_eq!(critical_path(&[]), 4.0);
+    // A slow multiplier makes r, m, x the slowest path.
+    assert_eq!(critical_path(&[("Mul", 5.0), ("x", 0.0)]), 6.0);
+    assert_eq!(critical_path(&[("Mul", 2.0), ("x", 0.0)]), 3.0);
+}
+
+#[test]
+fn min_latency_trades_cost_for_a_shorter_path() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::extract::{NodeCost, UnitCost};
+    use crate::objective::CostModelSpec;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("c", "c", "c", &["x"], 1.0),
+            ("d", "d", "d", &["c"], 1.0),
+            ("w1", "w1", "w1", &["x"], 1.5),
+            ("w2", "w2", "w2", &["x"], 1.5),
+            ("deep", "deep", "r", &["d"], 1.0),
+            ("wide", "wide", "r", &["w1", "w2"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let roots = &egraph.root_eclasses;
+
+    let cheapest = BottomUpExtractor.extract(&egraph, roots);
+    assert_eq!(cheapest.choices[&"r".into()], "deep".into());
+    assert_eq!(cheapest.critical_path_with(&egraph, roots, &NodeCost), 4.0);
+    let fastest = BottomUpExtractor.extract_min_latency(&egraph);
+    fastest.check(&egraph);
+    assert_eq!(fastest.choices[&"r".into()], "wide".into());
+    assert_eq!(fastest.critical_path_with(&egraph, roots, &NodeCost), 3.5);
+    assert_eq!(fastest.dag_cost_with(&egraph, roots, &UnitCost), 4.0);
+
+    assert_eq!("latency".parse(), Ok(CostModelSpec::Latency));
+    assert!(matches!(
+        " cost + depth ".parse(),
+        Ok(CostModelSpec::Objective(_))
+    ));
+    assert!("lat".parse::<CostModelSpec>().is_err());
+}
+
+#[test]
+fn merging_per_root_extractions_shares_their_classes() {
+    use crate::ExtractionResult;
+
+    for _ in 0..20 {
+        let egraph = generate_random_egraph();
+        let extractor = &extractors()["bottom-up"].extractor;
+        let per_root: Vec<ExtractionResult> = egraph
+            .root_eclasses
+            .iter()
+            .map(|root| extractor.extract(&egraph, std::slice::from_ref(root)))
+            .collect();
+        let merged = ExtractionResult::merge(&per_root).unwrap();
+        merged.check(&egraph);
+        let together = extractor.extract(&egraph, &egraph.root_eclasses);
+        assert_eq!(
+            merged.dag_cost(&egraph, &egraph.root_eclasses),
+            together.dag_cost(&egraph, &egraph.root_eclasses)
+        );
+
+        let (class_id, node_id) = merged.choices.first().unwrap();
+        if let Some(other) = egraph[class_id].nodes.iter().find(|n| *n != node_id) {
+            let mut conflicting = ExtractionResult::default();
+            conflicting.choose(class_id.clone(), other.clone());
+            assert!(ExtractionResult::merge(&[merged.clone(), conflicting]).is_err());
+        }
+    }
+}
+
+#[test]
+fn results_are_equal_whatever_order_they_chose_in() {
+    use crate::ExtractionResult;
+
+    let result = |choices: &[(&str, &str)]| {
+        let mut result = ExtractionResult::default();
+        for &(class_id, node_id) in choices {
+            result.choose(class_id.into(), node_id.into());
+        }
+        result
+    };
+    let forwards = result(&[("b", "b1"), ("a", "a1")]);
+    assert_eq!(forwards, result(&[("a", "a1"), ("b", "b1")]));
+    assert_ne!(forwards, result(&[("a", "a1"), ("b", "b2")]));
+    assert_ne!(forwards, result(&[("a", "a1")]));
+    let debug = format!("{forwards:?}");
+    assert!(
+        debug.find("a1").unwrap() < debug.find("b1").unwrap(),
+        "{debug}"
+    );
+}
+
+#[test]
+fn sidecar_costs_replace_the_embedded_ones() {
+    use crate::transform::cost_by_node;
+    use rustc_hash::{FxHashMap, FxHashSet};
+
+    let egraph = generate_random_egraph();
+    let ids: Vec<NodeId> = egraph.nodes.keys().cloned().collect();
+    let costs: FxHashMap<NodeId, crate::Cost> = ids
+        .iter()
+        .step_by(2)
+        .map(|id| (id.clone(), NotNan::new(7.0).unwrap()))
+        .collect();
+    let embedded: FxHashSet<NodeId> = ids.iter().cloned().collect();
+
+    let recosted = cost_by_node(&egraph, &costs, &embedded).unwrap();
+    for (i, id) in ids.iter().enumerate() {
+        let expected = if i % 2 == 0 {
+            7.0
+        } else {
+            egraph[id].cost.into_inner()
+        };
+        assert_eq!(recosted[id].cost.into_inner(), expected);
+    }
+
+    // The first node has a cost in neither once it's left out of the file.
+    let odd: FxHashSet<NodeId> = ids.iter().skip(1).step_by(2).cloned().collect();
+    let mut without_first = costs.clone();
+    without_first.remove(&ids[0]);
+    let error = cost_by_node(&egraph, &without_first, &odd).unwrap_err();
+    assert!(error.to_string().contains(&format!("Node {} ", ids[0])));
+
+    let mut extra = costs.clone();
+    extra.insert("no such node".into(), NotNan::new(1.0).unwrap());
+    assert!(cost_by_node(&egraph, &extra, &embedded).is_err());
+}
+
+#[test]
+fn restricting_to_a_root_keeps_its_tree_cost() {
+    for _ in 0..20 {
+        let egraph = generate_random_egraph();
+        let result = extractors()["bottom-up"]
+            .extractor
+            .extract(&egraph, &egraph.root_eclasses);
+        for root in &egraph.root_eclasses {
+            let roots = std::slice::from_ref(root);
+            let alone = result.restrict(&egraph, roots);
+            assert!(alone.choices.len() <= result.choices.len());
+            assert_eq!(
+                alone.tree_cost(&egraph, roots),
+                result.tree_cost(&egraph, roots)
+            );
+            assert_eq!(alone.restrict(&egraph, roots), alone);
+        }
+    }
+}
+
+#[test]
+fn low_memory_bottom_up_matches_bottom_up() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::TieFit;
+
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        let mut full = BottomUpExtractor.extract(&egraph, roots);
+        let mut low = BottomUpExtractor.extract_low_memory(&egraph);
+        assert_eq!(
+            full.choices
+                .keys()
+                .collect::<std::collections::BTreeSet<_>>(),
+            low.choices.keys().collect()
+        );
+        if full.find_cycles(&egraph, roots).is_empty() {
+            assert_eq!(
+                full.tree_cost(&egraph, roots),
+                low.tree_cost(&egraph, roots)
+            );
+            full.refit_ties(&egraph, TieFit::First);
+            low.refit_ties(&egraph, TieFit::First);
+            assert_eq!(full, low);
+        }
+    }
+}
+
+#[test]
+fn cycles_through_latches_are_allowed() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::extract::latch::LatchExtractor;
+    use egraph_serialize::ClassId;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("reg", "Reg", "r", &["add"], 1.0),
+            ("add", "Add", "next", &["reg", "x"], 1.0),
+            ("mul", "Mul", "next", &["x", "x"], 5.0),
+        ],
+        &["next"],
+    );
+
+    let extractor = LatchExtractor {
+        inner: BottomUpExtractor,
+        latch_op: "Reg".to_string(),
+    };
+    let result = extractor.extract(&egraph, &egraph.root_eclasses);
+    assert_eq!(result.choices[&ClassId::from("next")], NodeId::from("add"));
+    assert_eq!(result.choices[&ClassId::from("r")], NodeId::from("reg"));
+    assert!(!result
+        .find_cycles(&egraph, &egraph.root_eclasses)
+        .is_empty());
+    result.check_with_latches(&egraph, "Reg");
+}
+
+#[test]
+fn root_weights_steer_lighter_roots_off_what_heavier_ones_reach() {
+    use crate::extract::faster_greedy_dag::FasterGreedyDagExtractor;
+    use crate::transform::weight_by_roots;
+    use egraph_serialize::ClassId;
+    use indexmap::IndexMap;
+
+    let egraph = build(
+        &[
+            ("s1", "S", "s", &[], 2.0),
+            ("t1", "T", "t", &[], 3.0),
+            ("u1", "U", "u", &[], 2.5),
+            ("a1", "F", "a", &["s1"], 1.0),
+            ("a2", "G", "a", &["u1"], 1.0),
+            ("b1", "H", "b", &["u1"], 1.0),
+            ("b2", "K", "b", &["t1"], 1.0),
+        ],
+        &["a", "b"],
+    );
+
+    let b = ClassId::from("b");
+    let result = FasterGreedyDagExtractor.extract(&egraph, &egraph.root_eclasses);
+    assert_eq!(result.choices[&b], NodeId::from("b1"));
+
+    let weights = IndexMap::from([(ClassId::from("a"), 10.0)]);
+    let weighted = weight_by_roots(&egraph, &weights).unwrap();
+    assert_eq!(weighted[&NodeId::from("u1")].cost, 25.0);
+    assert_eq!(weighted[&NodeId::from("t1")].cost, 3.0);
+    let result = FasterGreedyDagExtractor.extract(&weighted, &weighted.root_eclasses);
+    assert_eq!(result.choices[&ClassId::from("a")], NodeId::from("a1"));
+    assert_eq!(result.choices[&b], NodeId::from("b2"));
+
+    let not_a_root = IndexMap::from([(ClassId::from("s"), 2.0)]);
+    assert!(weight_by_roots(&egraph, &not_a_root).is_err());
+}
+
+#[test]
+fn repeated_roots_are_costed_once() {
+    for _ in 0..100 {
+        let egraph = generate_random_egraph();
+        let result = extractors()["bottom-up"]
+            .extractor
+            .extract(&egraph, &egraph.root_eclasses);
+        let mut repeated = egraph.root_eclasses.clone();
+        repeated.extend(egraph.root_eclasses.iter().cloned());
+        assert_eq!(
+            result.tree_cost(&egraph, &repeated),
+            result.tree_cost(&egraph, &egraph.root_eclasses)
+        );
+        assert_eq!(
+            result.dag_cost(&egraph, &repeated),
+            result.dag_cost(&egraph, &egraph.root_eclasses)
+        );
+    }
+}
+
+#[test]
+fn selftest_passes() {
+    assert!(crate::selftest::run());
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+fn cost_scripts_see_the_ops_of_the_children() {
+    use crate::script::CostScript;
+    use crate::transform::cost_by_script;
+
+    let filename = std::env::temp_dir().join("extraction_gym_cost_script.rhai");
+    std::fs::write(
+        &filename,
+        r#"fn cost(op, num_children, children_ops) {
+            if op == "+" && children_ops.contains("-") { 10.0 } else { num_children + 1 }
+        }"#,
+    )
+    .unwrap();
+    let script = CostScript::load(&filename).unwrap();
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("n", "-", "n", &["x"], 1.0),
+            ("a", "+", "a", &["n", "x"], 1.0),
+        ],
+        &["a"],
+    );
+
+    let costed = cost_by_script(&egraph, &script).unwrap();
+    assert_eq!(costed[&NodeId::from("x")].cost, 1.0);
+    assert_eq!(costed[&NodeId::from("n")].cost, 2.0);
+    assert_eq!(costed[&NodeId::from("a")].cost, 10.0);
+}
+
+#[test]
+fn aliased_ops_are_renamed_before_anything_sees_them() {
+    use crate::extract::ops::{apply_aliases, parse_alias};
+
+    let egraph = build(
+        &[
+            ("x", "Var(\"x\")", "x", &[], 1.0),
+            ("c", "bvconst(3)", "c", &[], 1.0),
+            ("a", "bvadd", "a", &["x", "c"], 1.0),
+        ],
+        &["a"],
+    );
+
+    let aliases = ["bvadd=Add", "bvconst=Num"]
+        .iter()
+        .map(|alias| parse_alias(alias).unwrap())
+        .collect();
+    let aliased = apply_aliases(&egraph, &aliases);
+    assert_eq!(aliased[&NodeId::from("a")].op, "Add");
+    assert_eq!(aliased[&NodeId::from("c")].op, "Num(3)");
+    assert_eq!(aliased[&NodeId::from("x")].op, "Var(\"x\")");
+    assert!(parse_alias("bvadd").is_err());
+}
+
+#[test]
+fn corpus_summary_compares_each_file_with_its_best_extraction() {
+    use crate::summary::{read_reports, summarize};
+    use serde_json::json;
+
+    let dir = std::env::temp_dir().join("extraction_gym_corpus_summary");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let reports = [
+        json!({"name": "a", "extractor": "greedy", "tree": 9, "dag": 6, "micros": 10}),
+        json!({"name": "a", "extractor": "ilp", "tree": 9, "dag": 3, "micros": 1000, "ilp_optimal": true}),
+        json!({"name": "b", "extractor": "greedy", "tree": 4, "dag": 4, "micros": 30}),
+        json!({"name": "b", "extractor": "ilp", "tree": 4, "dag": 4, "micros": 2000, "ilp_optimal": false}),
+        json!({"nodes": {}, "root_eclasses": []}),
+    ];
+    for (i, report) in reports.iter().enumerate() {
+        std::fs::write(dir.join(format!("{i}.json")), report.to_string()).unwrap();
+    }
+
+    let reports = read_reports(&dir).unwrap();
+    assert_eq!(reports.len(), 4);
+    let summary = summarize(&reports).unwrap();
+    assert_eq!(summary["files"], json!(2));
+    let greedy = &summary["extractors"]["greedy"];
+    assert_eq!(greedy["dag_ratio"]["median"], json!(1.0));
+    assert_eq!(greedy["dag_ratio"]["p99"], json!(2.0));
+    assert_eq!(greedy["micros"]["median"], json!(10.0));
+    assert_eq!(greedy["timeouts"], json!(0));
+    let ilp = &summary["extractors"]["ilp"];
+    assert_eq!(ilp["dag_ratio"]["p90"], json!(1.0));
+    assert_eq!(ilp["timeouts"], json!(1));
+}
+
+#[test]
+fn free_ties_go_to_the_smallest_dag() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+    use crate::TieFit;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 0.0),
+            ("deep", "deep", "d", &["wide"], 0.0),
+            ("leaf", "leaf", "d", &[], 0.0),
+            ("wide", "wide", "w", &["x", "x", "x"], 0.0),
+            ("small", "small", "w", &["x"], 0.0),
+            ("big", "big", "r", &["deep", "wide"], 0.0),
+            ("tiny", "tiny", "r", &["x"], 0.0),
+            ("last", "last", "r", &["leaf", "x"], 0.0),
+            // fan's tree is bigger than chain's, but its dag is smaller
+            ("fan", "fan", "q", &["x", "x", "x"], 0.0),
+            ("chain", "chain", "q", &["link"], 0.0),
+            ("link", "link", "link", &["x"], 0.0),
+        ],
+        &["r"],
+    );
+
+    for tie_fit in [TieFit::First, TieFit::Last, TieFit::SmallestId] {
+        let mut result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+        result.refit_ties(&egraph, tie_fit);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"r".into()], "tiny".into());
+        assert_eq!(result.choices[&"q".into()], "fan".into());
+        assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 0.0);
+    }
+}
+
+// perturbed-greedy should be valid and the same for a seed, but not for every seed.
+#[test]
+fn perturbed_greedy_is_seeded() {
+    use crate::extract::perturbed_greedy::PerturbedGreedyExtractor;
+    use rand::SeedableRng;
+
+    let mut varied = false;
+    for seed in 0..50 {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let egraph = generate_random_egraph_with(&mut rng);
+        let roots = &egraph.root_eclasses;
+        let extract = |seed| {
+            let result = PerturbedGreedyExtractor {
+                fraction: 0.5,
+                seed,
+            }
+            .extract(&egraph, roots);
+            result.check(&egraph);
+            result
+        };
+        let first = extract(seed);
+        assert_eq!(first.choices, extract(seed).choices);
+        varied |= (1..5).any(|other| extract(seed + other).choices != first.choices);
+    }
+    assert!(varied);
+}
+
+// The LP relaxation bounds what the ILP finds from below.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn lp_bound_is_below_the_optimum() {
+    use crate::extract::ilp_cbc::{lp_bound, CbcExtractor};
+
+    for _ in 0..20 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        let optimal = CbcExtractor
+            .extract(&egraph, roots)
+            .dag_cost(&egraph, roots);
+        let bound = lp_bound(&egraph, roots, &Default::default()).unwrap();
+        assert!(bound <= optimal.into_inner() + EPSILON_ALLOWANCE);
+    }
+}
+
+// With a linear objective, the ILP on the weighted egraph minimizes the objective summed over
+// the extraction's nodes, so no heuristic gets a smaller sum.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn ilp_minimizes_a_linear_objective() {
+    use crate::extract::ilp_cbc::CbcExtractor;
+    use crate::objective::{node_metrics, Objective};
+    use crate::transform::weight_by_objective;
+
+    let objective: Objective = "cost + 2 * arity + depth / 4".parse().unwrap();
+    assert!(objective.is_linear());
+    for _ in 0..10 {
+        let egraph = generate_random_egraph();
+        let roots = &egraph.root_eclasses;
+        let by_objective: indexmap::IndexMap<NodeId, crate::Cost> = node_metrics(&egraph)
+            .iter()
+            .map(|(node_id, metrics)| {
+                (
+                    node_id.clone(),
+                    NotNan::new(objective.eval(metrics)).unwrap(),
+                )
+            })
+            .collect();
+        let weighted = weight_by_objective(&egraph, &objective).unwrap();
+
+        let optimal = CbcExtractor.extract(&weighted, roots);
+        optimal.check(&egraph);
+        let summed = optimal.dag_cost_with(&egraph, roots, &by_objective);
+        assert!((summed - optimal.dag_cost(&weighted, roots)).abs() < EPSILON_ALLOWANCE);
+        for (name, ed) in extractors() {
+            if ed.is_ilp {
+                continue;
+            }
+            let result = ed.extractor.extract(&weighted, roots);
+            let heuristic = result.dag_cost_with(&egraph, roots, &by_objective);
+            assert!(summed <= heuristic + EPSILON_ALLOWANCE, "{name}");
+        }
+    }
+}
+
+// --export-lp and --export-mps should write the model before it's solved.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn ilp_models_are_exported() {
+    use crate::extract::ilp_cbc::{solve, Config};
+
+    // One directory per process, so concurrent runs don't overwrite each other's models.
+    let dir = std::env::temp_dir().join(format!("extraction-gym-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = Config {
+        export_lp: Some(dir.join("model.lp")),
+        export_mps: Some(dir.join("model.mps")),
+        ..Default::default()
+    };
+    let egraph = generate_random_egraph();
+    solve(&egraph, &egraph.root_eclasses, &config, 10);
+    let models: Vec<String> = ["model.lp", "model.mps"]
+        .iter()
+        .map(|file| std::fs::read_to_string(dir.join(file)).unwrap_or_default())
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+    for (file, model) in ["model.lp", "model.mps"].iter().zip(models) {
+        assert!(!model.is_empty(), "{file}");
+    }
+}
+
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn exclusive_groups_allow_one_op_each() {
+    use crate::extract::ilp_cbc::{Config, ConfiguredCbcExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("a1", "Mul_a", "p", &["x"], 1.0),
+            ("b1", "Mul_b", "p", &["x"], 3.0),
+            ("a2", "Mul_a", "q", &["x"], 3.0),
+            ("b2", "Mul_b", "q", &["x"], 1.0),
+            ("r", "Add", "r", &["a1", "a2"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let extractor = ConfiguredCbcExtractor {
+        config: Config {
+            exclusive_groups: vec![vec!["Mul_a".to_string(), "Mul_b".to_string()]],
+            ..Default::default()
+        },
+        timeout_seconds: u32::MAX,
+    };
+    let result = extractor.extract(&egraph, &egraph.root_eclasses);
+    result.check(&egraph);
+    let ops: std::collections::HashSet<&str> = ["p", "q"]
+        .iter()
+        .map(|c| egraph[&result.choices[&(*c).into()]].op.as_str())
+        .collect();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 6.0);
+
+    // Each class having only one of the ops makes it infeasible.
+    let egraph = crate::transform::retain_nodes(&egraph, |id, _| {
+        !["b1", "a2"].contains(&id.to_string().as_str())
+    });
+    let result = extractor.extract(&egraph, &egraph.root_eclasses);
+    assert!(result.choices.is_empty());
+}
+
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn linked_groups_choose_all_or_none() {
+    use crate::extract::ilp_cbc::{Config, ConfiguredCbcExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("a1", "a1", "p", &["x"], 1.0),
+            ("b1", "b1", "p", &["x"], 2.0),
+            ("a2", "a2", "q", &["x"], 3.0),
+            ("b2", "b2", "q", &["x"], 1.0),
+            ("r", "r", "r", &["a1", "a2"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let extract_linked = |group: &[&str]| {
+        let extractor = ConfiguredCbcExtractor {
+            config: Config {
+                linked_groups: vec![group.iter().map(|&n| n.into()).collect()],
+                ..Default::default()
+            },
+            timeout_seconds: u32::MAX,
+        };
+        let result = extractor.extract(&egraph, &egraph.root_eclasses);
+        result.check(&egraph);
+        result
+    };
+    // Both a1 and a2 cost more than leaving them out.
+    let result = extract_linked(&["a1", "a2"]);
+    assert_eq!(result.choices[&"p".into()], "b1".into());
+    assert_eq!(result.choices[&"q".into()], "b2".into());
+    assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 5.0);
+    // a1 and b2 are the cheapest anyway.
+    let result = extract_linked(&["a1", "b2"]);
+    assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 4.0);
+}
+
+// Using one op for both p and q costs more than mixing them, but keeps to three ops.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn max_distinct_ops_keeps_the_op_count() {
+    use crate::extract::ilp_cbc::{Config, ConfiguredCbcExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("a1", "Mul", "p", &["x"], 1.0),
+            ("b1", "Shl", "p", &["x"], 3.0),
+            ("a2", "Mul", "q", &["x"], 3.0),
+            ("b2", "Shl", "q", &["x"], 1.0),
+            ("r", "Add", "r", &["a1", "a2"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let extract_with = |max_distinct_ops: usize| {
+        let extractor = ConfiguredCbcExtractor {
+            config: Config {
+                max_distinct_ops: Some(max_distinct_ops),
+                ..Default::default()
+            },
+            timeout_seconds: u32::MAX,
+        };
+        extractor.extract(&egraph, &egraph.root_eclasses)
+    };
+    for (max_distinct_ops, cost) in [(4, 4.0), (3, 6.0)] {
+        let result = extract_with(max_distinct_ops);
+        result.check(&egraph);
+        let ops: std::collections::HashSet<&str> = result
+            .choices
+            .values()
+            .map(|n| egraph[n].op.as_str())
+            .collect();
+        assert_eq!(ops.len(), max_distinct_ops);
+        assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), cost);
+    }
+    // x and Add alone can't make p and q.
+    assert!(extract_with(2).choices.is_empty());
+}
+
+// The cheapest root is four nodes deep, so a lower max_depth has to pay for the shallow one.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn ilp_max_depth_gives_up_a_cheaper_deep_extraction() {
+    use crate::extract::ilp_cbc::{Config, ConfiguredCbcExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("a", "f", "a", &["x"], 1.0),
+            ("b", "g", "b", &["a"], 1.0),
+            ("deep", "h", "r", &["b"], 1.0),
+            ("shallow", "k", "r", &["x"], 10.0),
+        ],
+        &["r"],
+    );
+
+    let extract_with = |max_depth: usize| {
+        let extractor = ConfiguredCbcExtractor {
+            config: Config {
+                max_depth: Some(max_depth),
+                ..Default::default()
+            },
+            timeout_seconds: u32::MAX,
+        };
+        extractor.extract(&egraph, &egraph.root_eclasses)
+    };
+    for (max_depth, chosen, cost) in [(4, "deep", 4.0), (3, "shallow", 11.0), (2, "shallow", 11.0)]
+    {
+        let result = extract_with(max_depth);
+        result.check(&egraph);
+        assert_eq!(result.choices[&"r".into()], chosen.into(), "{max_depth}");
+        assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), cost);
+    }
+    // The root can't be a leaf.
+    assert!(extract_with(1).choices.is_empty());
+}
+
+// A solver that only proves the extraction optimal with at least five seconds has the
+// timeout doubled up to there, or up to the max if that's lower.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn escalating_timeouts_double_until_the_max() {
+    use crate::extract::ilp_cbc::{escalate, IlpSolution};
+
+    let escalate_to = |initial_seconds: u32, max_seconds: u32| {
+        let mut tried = vec![];
+        let (solution, timeout_seconds) = escalate(initial_seconds, max_seconds, |seconds| {
+            tried.push(seconds);
+            if seconds >= 5 {
+                IlpSolution::Optimal(Default::default())
+            } else {
+                IlpSolution::NoSolution
+            }
+        });
+        assert_eq!(tried.last(), Some(&timeout_seconds));
+        (tried, matches!(solution, IlpSolution::Optimal(_)))
+    };
+    assert_eq!(escalate_to(1, 100), (vec![1, 2, 4, 8], true));
+    assert_eq!(escalate_to(1, 6), (vec![1, 2, 4, 6], true));
+    assert_eq!(escalate_to(1, 3), (vec![1, 2, 3], false));
+    assert_eq!(escalate_to(5, 100), (vec![5], true));
+    assert_eq!(escalate_to(8, 3), (vec![3], false));
+}
+
+// The shape rules out the cheaper node in every class it reaches, but leaves y's free.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn ilp_extractions_match_the_target_shape() {
+    use crate::extract::ilp_cbc::{Config, ConfiguredCbcExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("z", "z", "x", &[], 0.5),
+            ("y", "y", "y", &[], 1.0),
+            ("mul", "Mul", "m", &["x", "x"], 1.0),
+            ("shl", "Shl", "m", &["x"], 0.0),
+            ("add", "Add", "r", &["y", "mul"], 5.0),
+            ("sub", "Sub", "r", &["x"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let extract_with = |shape: &str| {
+        let extractor = ConfiguredCbcExtractor {
+            config: Config {
+                target_shape: Some(shape.parse().unwrap()),
+                ..Default::default()
+            },
+            timeout_seconds: u32::MAX,
+        };
+        extractor.extract(&egraph, &egraph.root_eclasses)
+    };
+    let result = extract_with("(Add ? (Mul x ?))");
+    result.check(&egraph);
+    for (class, node) in [("r", "add"), ("m", "mul"), ("x", "x")] {
+        assert_eq!(result.choices[&class.into()], node.into());
+    }
+    assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 8.0);
+    // Without the shape it'd be sub over z.
+    let result = extract_with("?");
+    assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 1.5);
+    // Sub's child class has no y.
+    assert!(extract_with("(Sub y)").choices.is_empty());
+}
+
+// Both roots together need four nodes, so a budget of three only fits one of them, and
+// the cheaper one is kept.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn ilp_node_budget_drops_the_roots_that_dont_fit() {
+    use crate::extract::ilp_cbc::{Config, ConfiguredCbcExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("f", "f", "r1", &["x"], 1.0),
+            ("k", "k", "a", &["x"], 1.0),
+            ("h", "h", "r2", &["k"], 2.0),
+        ],
+        &["r1", "r2"],
+    );
+
+    let extract_with = |node_budget: usize, maximize_roots: bool| {
+        let extractor = ConfiguredCbcExtractor {
+            config: Config {
+                node_budget: Some(node_budget),
+                maximize_roots,
+                ..Default::default()
+            },
+            timeout_seconds: u32::MAX,
+        };
+        extractor.extract(&egraph, &egraph.root_eclasses)
+    };
+    let result = extract_with(4, true);
+    result.check(&egraph);
+    assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 5.0);
+
+    let result = extract_with(3, true);
+    assert_eq!(result.choices.len(), 2);
+    assert_eq!(result.choices[&"r1".into()], "f".into());
+    assert!(!result.choices.contains_key(&"r2".into()));
+    // Without maximize_roots, every root has to fit.
+    assert!(extract_with(3, false).choices.is_empty());
+    // x alone isn't a root.
+    assert!(extract_with(1, true).choices.is_empty());
+}
+
+#[test]
+fn linked_groups_that_cant_be_chosen_together_are_reported() {
+    use crate::transform::check_linked_group;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("px", "px", "p", &["x"], 1.0),
+            ("pq", "pq", "p", &["qx"], 1.0),
+            ("qx", "qx", "q", &["x"], 1.0),
+            ("qp", "qp", "q", &["px"], 1.0),
+            ("r", "r", "r", &["px", "qx"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let check = |group: &[&str]| {
+        let group: Vec<NodeId> = group.iter().map(|&n| n.into()).collect();
+        check_linked_group(&egraph, &group)
+    };
+    assert!(check(&["pq", "qx"]).is_ok());
+    // Together they make a cycle between p and q.
+    assert!(check(&["pq", "qp"]).is_err());
+    assert!(check(&["px", "pq"]).is_err());
+    assert!(check(&["px", "nope"]).is_err());
+}
+
+#[test]
+fn allowed_ops_keep_leaves_and_matching_names() {
+    use crate::transform::{allow_ops, newly_unextractable};
+
+    let egraph = build(
+        &[
+            ("a", "Var(\"a\")", "a", &[], 1.0),
+            ("b", "Var(\"b\"):8", "b", &[], 1.0),
+            ("xor", "Xor:8", "x", &["a", "b"], 1.0),
+            ("and", "And:8", "n", &["a", "b"], 1.0),
+            ("nand", "Nand", "x2", &["a", "and"], 1.0),
+            ("mux", "Mux", "r", &["xor", "and", "a"], 1.0),
+            ("or", "Or", "r", &["and", "a"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let allowed = allow_ops(&egraph, &["And".to_string(), "Or".to_string()]);
+    let mut kept: Vec<String> = allowed.nodes.keys().map(|n| n.to_string()).collect();
+    kept.sort();
+    assert_eq!(kept, ["a", "and", "b", "or"]);
+    let mut lost = newly_unextractable(&egraph, &allowed);
+    lost.sort();
+    assert_eq!(lost, vec!["x".into(), "x2".into()]);
+}
+
+// A node is pruned only if a sibling costs no more and needs no other classes.
+#[test]
+fn dominated_nodes_are_pruned() {
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("f", "f", "r", &["x"], 2.0),
+            ("g", "g", "r", &["x", "y"], 2.0),
+            ("h", "h", "r", &["x", "x"], 3.0),
+            ("k", "k", "r", &["y"], 1.0),
+            ("same", "same", "r", &["x"], 2.0),
+            ("cheap", "cheap", "r", &["x", "y"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let pruned = crate::transform::prune_dominated(&egraph);
+    let kept: Vec<String> = pruned.nodes.keys().map(|id| id.to_string()).collect();
+    assert_eq!(kept, vec!["x", "y", "f", "k"]);
+}
+
+// With a rarity penalty, the op used everywhere else beats a slightly cheaper one-off.
+#[test]
+fn rare_ops_are_penalized() {
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 0.0),
+            ("a", "add", "a", &["x", "x"], 1.0),
+            ("b", "add", "b", &["a", "x"], 1.0),
+            ("c", "add", "r", &["b", "x"], 1.5),
+            ("d", "fma", "r", &["b", "x", "x"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let extract = |egraph: &EGraph| {
+        let result =
+            crate::extract::bottom_up::BottomUpExtractor.extract(egraph, &egraph.root_eclasses);
+        result.choices[&"r".into()].to_string()
+    };
+    assert_eq!(extract(&egraph), "d");
+    let penalized = crate::transform::penalize_rare_ops(&egraph, 1.0);
+    // fma costs 1 + 1, add 1.5 + 1/3
+    assert_eq!(extract(&penalized), "c");
+}
+
+// Only the classes with a real choice, and the fixed subtrees they use as leaves, are left
+// for the ILP, and the stitched result is still an extraction.
+#[cfg(feature = "ilp-cbc")]
+#[test]
+fn contracted_ilp_solves_only_the_hard_classes() {
+    use crate::extract::contracted_ilp::{contract, ContractedIlpExtractor};
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("n", "n", "n", &["x"], 1.0),
+            ("worse", "worse", "n", &["x"], 2.0),
+            ("f", "f", "r", &["n"], 3.0),
+            ("g", "g", "r", &["x"], 4.0),
+        ],
+        &["r"],
+    );
+
+    let (contracted, fixed) = contract(&egraph, &egraph.root_eclasses);
+    assert_eq!(fixed.choices.len(), 2);
+    let mut kept: Vec<String> = contracted.nodes.keys().map(|id| id.to_string()).collect();
+    kept.sort();
+    assert_eq!(kept, vec!["f", "g", "n", "x"]);
+    assert_eq!(contracted[&NodeId::from("n")].cost, 2.0);
+    assert!(contracted[&NodeId::from("n")].children.is_empty());
+
+    for _ in 0..20 {
+        let egraph = generate_random_egraph();
+        let result = ContractedIlpExtractor.extract(&egraph, &egraph.root_eclasses);
+        result.check(&egraph);
+    }
+}
+
+// The fixpoints only take strict improvements, so they never choose a cycle of zero cost
+// nodes, even though it looks as cheap as the way out.
+#[test]
+fn bottom_up_gets_around_free_cycles() {
+    use egraph_serialize::ClassId;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("self", "self", "x", &["x"], 0.0),
+            ("f", "f", "a", &["g"], 0.0),
+            ("g", "g", "b", &["f"], 0.0),
+            ("h", "h", "b", &["x"], 1.0),
+        ],
+        &["a"],
+    );
+    let extractors: [&dyn Extractor; 3] = [
+        &crate::extract::bottom_up::BottomUpExtractor,
+        &crate::extract::faster_bottom_up::FasterBottomUpExtractor,
+        &crate::extract::lazy_bottom_up::LazyBottomUpExtractor,
+    ];
+    for extractor in extractors {
+        let result = extractor.extract(&egraph, &egraph.root_eclasses);
+        result.check(&egraph);
+        assert_eq!(result.choices[&ClassId::from("b")], "h".into());
+    }
+}
+
+#[test]
+fn alternatives_are_the_cheapest_unchosen_nodes() {
+    use crate::extract::bottom_up::BottomUpExtractor;
+
+    let egraph = build(
+        &[
+            ("x1", "x1", "x", &[], 1.0),
+            ("x2", "x2", "x", &[], 4.0),
+            ("y", "y", "y", &[], 3.0),
+            ("f", "f", "r", &["x1"], 1.0),
+            ("g", "g", "r", &["y"], 1.0),
+            // reaches back to its own class
+            ("h", "h", "r", &["f"], 0.0),
+        ],
+        &["r"],
+    );
+
+    let result = BottomUpExtractor.extract(&egraph, &egraph.root_eclasses);
+    let alternatives = result.alternatives(&egraph, &egraph.root_eclasses);
+    let delta = |class: &str| {
+        let (node_id, delta) = &alternatives[&egraph_serialize::ClassId::from(class)];
+        (node_id.clone(), delta.into_inner())
+    };
+    assert_eq!(alternatives.len(), 2);
+    assert_eq!(delta("x"), ("x2".into(), 3.0));
+    assert_eq!(delta("r"), ("g".into(), 2.0));
+}
+
+#[test]
+fn commutative_canonicalization_shares_nodes() {
+    use crate::extract::faster_greedy_dag::FasterGreedyDagExtractor;
+    use crate::transform::canonicalize_commutative;
+
+    let egraph = build(
+        &[
+            ("x", "x", "x", &[], 1.0),
+            ("y", "y", "y", &[], 1.0),
+            ("xy", "Add", "a", &["x", "y"], 1.0),
+            ("yx", "Add", "b", &["y", "x"], 1.0),
+            ("sub", "Sub", "r", &["xy", "yx"], 1.0),
+        ],
+        &["r"],
+    );
+
+    let result = FasterGreedyDagExtractor.extract(&egraph, &egraph.root_eclasses);
+    assert_eq!(result.dag_cost(&egraph, &egraph.root_eclasses), 5.0);
+
+    let canon = canonicalize_commutative(&egraph, &["Add".to_string()]);
+    assert_eq!(canon.classes().len(), 4);
+    let result = FasterGreedyDagExtractor.extract(&canon, &canon.root_eclasses);
+    result.check(&canon);
+    assert_eq!(result.dag_cost(&canon, &canon.root_eclasses), 4.0);
+}
+
 #[test]
 #[should_panic]
 fn check_assert_enabled() {
@@ -212,7 +3124,7 @@ macro_rules! create_optimal_check_tests {
             fn $name() {
                 let optimal_dag_found = extractors().into_iter().any(|(_, ed)| ed.optimal == Optimal::DAG);
                 let iterations = if optimal_dag_found { 100 } else { 10000 };
-                let egraphs = (0..iterations).map(|_| generate_random_egraph());
+                let egraphs = (0..iterations).map(|i| (format!("random egraph {i}"), generate_random_egraph()));
                 check_optimal_results(egraphs);
             }
         )*
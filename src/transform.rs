@@ -0,0 +1,819 @@
+/* Transformations of an egraph applied before extraction.
+
+They return a new egraph (the classes of an egraph are cached, so it can't be edited in place).
+Unless noted otherwise, node ids are preserved, so an extraction of the transformed egraph
+is also an extraction of the original one.
+*/
+
+use crate::objective::{self, Objective};
+use crate::*;
+use indexmap::{IndexMap, IndexSet};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// Keeps only the nodes `keep` accepts, along with the nodes that can still be built from them.
+///
+/// A node with a child class that lost all its nodes is removed as well, and children
+/// are redirected to a surviving node of their class.
+pub fn retain_nodes(egraph: &EGraph, mut keep: impl FnMut(&NodeId, &Node) -> bool) -> EGraph {
+    let mut kept: IndexSet<NodeId> = egraph
+        .nodes
+        .iter()
+        .filter(|(id, node)| keep(id, node))
+        .map(|(id, _)| id.clone())
+        .collect();
+
+    loop {
+        let live: FxHashSet<&ClassId> = kept.iter().map(|id| egraph.nid_to_cid(id)).collect();
+        let before = kept.len();
+        kept.retain(|id| {
+            egraph[id]
+                .children
+                .iter()
+                .all(|c| live.contains(egraph.nid_to_cid(c)))
+        });
+        if kept.len() == before {
+            break;
+        }
+    }
+
+    let mut representative: FxHashMap<&ClassId, &NodeId> = FxHashMap::default();
+    for id in &kept {
+        representative.entry(egraph.nid_to_cid(id)).or_insert(id);
+    }
+
+    let mut result = EGraph::default();
+    for id in &kept {
+        let node = &egraph[id];
+        let children = node
+            .children
+            .iter()
+            .map(|c| representative[egraph.nid_to_cid(c)].clone())
+            .collect();
+        result.add_node(
+            id.clone(),
+            Node {
+                children,
+                ..node.clone()
+            },
+        );
+    }
+    result.root_eclasses = egraph.root_eclasses.clone();
+    result.class_data = egraph.class_data.clone();
+    result
+}
+
+/// One egraph per group of roots, with just the classes those roots can reach. The groups
+/// should share no classes, like the ones `stats::root_components` finds.
+pub fn split_by_roots(egraph: &EGraph, groups: &[Vec<ClassId>]) -> Vec<EGraph> {
+    groups
+        .iter()
+        .map(|roots| {
+            let mut reachable: FxHashSet<&ClassId> = FxHashSet::default();
+            let mut todo: Vec<&ClassId> = roots.iter().collect();
+            while let Some(class_id) = todo.pop() {
+                if reachable.insert(class_id) {
+                    for node_id in &egraph[class_id].nodes {
+                        todo.extend(
+                            egraph[node_id]
+                                .children
+                                .iter()
+                                .map(|c| egraph.nid_to_cid(c)),
+                        );
+                    }
+                }
+            }
+
+            let mut result = EGraph::default();
+            for (node_id, node) in &egraph.nodes {
+                if reachable.contains(&node.eclass) {
+                    result.add_node(node_id.clone(), node.clone());
+                }
+            }
+            result.root_eclasses = roots.clone();
+            result.class_data = egraph.class_data.clone();
+            result
+        })
+        .collect()
+}
+
+/// Restricts the class of each node in `must_use` to just that node, so any extraction
+/// that uses the class has to choose it.
+///
+/// Fails if a node doesn't exist, if two of them share a class, or if the restriction
+/// leaves a root without any acyclic extraction.
+pub fn must_use(egraph: &EGraph, must_use: &[NodeId]) -> anyhow::Result<EGraph> {
+    let mut forced: FxHashMap<&ClassId, &NodeId> = FxHashMap::default();
+    for node_id in must_use {
+        let Some(node) = egraph.nodes.get(node_id) else {
+            anyhow::bail!("Must-use node {node_id} is not in the egraph");
+        };
+        if let Some(other) = forced.insert(&node.eclass, node_id) {
+            anyhow::bail!(
+                "Must-use nodes {other} and {node_id} are both in class {}, only one can be chosen",
+                node.eclass
+            );
+        }
+    }
+
+    let result = retain_nodes(egraph, |id, node| {
+        forced.get(&node.eclass).is_none_or(|&forced| forced == id)
+    });
+
+    let unextractable = unextractable_roots(&result, &result.root_eclasses);
+    if !unextractable.is_empty() {
+        anyhow::bail!(
+            "Can't use all of the must-use nodes: roots {unextractable:?} would have no extraction without a cycle"
+        );
+    }
+    Ok(result)
+}
+
+/// Checks that the nodes of a linked group can all be chosen at once: they exist, no two
+/// share a class, and restricting their classes to them leaves each of those classes an
+/// extraction, so together they don't make a cycle.
+pub fn check_linked_group(egraph: &EGraph, group: &[NodeId]) -> anyhow::Result<()> {
+    let mut forced: FxHashMap<&ClassId, &NodeId> = FxHashMap::default();
+    for node_id in group {
+        let Some(node) = egraph.nodes.get(node_id) else {
+            anyhow::bail!("Linked node {node_id} is not in the egraph");
+        };
+        if let Some(other) = forced.insert(&node.eclass, node_id) {
+            anyhow::bail!(
+                "Linked nodes {other} and {node_id} are both in class {}, only one can be chosen",
+                node.eclass
+            );
+        }
+    }
+
+    let restricted = retain_nodes(egraph, |id, node| {
+        forced.get(&node.eclass).is_none_or(|&forced| forced == id)
+    });
+    let classes: Vec<ClassId> = group.iter().map(|n| egraph.nid_to_cid(n).clone()).collect();
+    let unextractable = unextractable_roots(&restricted, &classes);
+    if !unextractable.is_empty() {
+        anyhow::bail!(
+            "Choosing all of {group:?} leaves classes {unextractable:?} with no extraction without a cycle"
+        );
+    }
+    Ok(())
+}
+
+/// Removes the classes in `exclude`, along with every node that needs one of them, even
+/// through its descendants.
+///
+/// Fails if that leaves a root without an extraction.
+pub fn exclude_classes(egraph: &EGraph, exclude: &[ClassId]) -> anyhow::Result<EGraph> {
+    let exclude: FxHashSet<&ClassId> = exclude.iter().collect();
+    let result = retain_nodes(egraph, |_, node| !exclude.contains(&node.eclass));
+
+    let unextractable = unextractable_roots(&result, &result.root_eclasses);
+    if !unextractable.is_empty() {
+        anyhow::bail!("Roots {unextractable:?} can't be extracted without the excluded classes");
+    }
+    Ok(result)
+}
+
+/// Treats the classes in `available` as already computed: each is left with one of its
+/// nodes, with no children and no cost, so using them is free.
+///
+/// This changes the egraph's structure, so an extraction of the result is only an
+/// extraction of the original egraph up to the available classes.
+pub fn make_available(egraph: &EGraph, available: &[ClassId]) -> anyhow::Result<EGraph> {
+    let available: FxHashSet<&ClassId> = available.iter().collect();
+    for class_id in &available {
+        if !egraph.classes().contains_key(*class_id) {
+            anyhow::bail!("Available class {class_id} is not in the egraph");
+        }
+    }
+
+    let mut free = EGraph::default();
+    for (node_id, node) in &egraph.nodes {
+        let node = if available.contains(&node.eclass) {
+            Node {
+                children: vec![],
+                cost: Cost::default(),
+                ..node.clone()
+            }
+        } else {
+            node.clone()
+        };
+        free.add_node(node_id.clone(), node);
+    }
+    free.root_eclasses = egraph.root_eclasses.clone();
+    free.class_data = egraph.class_data.clone();
+
+    let mut seen: FxHashSet<ClassId> = FxHashSet::default();
+    Ok(retain_nodes(&free, |_, node| {
+        !available.contains(&node.eclass) || seen.insert(node.eclass.clone())
+    }))
+}
+
+/// How to choose between equally cheap nodes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TieBreak {
+    /// Prefer the node with the smallest id.
+    #[default]
+    LowestId,
+    /// Prefer whichever node the extractor finds first in the input's order.
+    Input,
+}
+
+impl std::str::FromStr for TieBreak {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lowest-id" => Ok(TieBreak::LowestId),
+            "input" => Ok(TieBreak::Input),
+            _ => Err(format!("Unknown tie break: {s}")),
+        }
+    }
+}
+
+/// Orders the nodes (and so the classes) by id. The extractors keep the first of equally
+/// cheap nodes they come across, so this makes them prefer the smallest ids on ties rather
+/// than whatever came first in the input, which keeps extractions stable as the egraph
+/// is edited. Ids are compared as strings.
+pub fn sort_by_node_id(egraph: &EGraph) -> EGraph {
+    let mut node_ids: Vec<&NodeId> = egraph.nodes.keys().collect();
+    node_ids.sort();
+    let mut result = EGraph::default();
+    for node_id in node_ids {
+        result.add_node(node_id.clone(), egraph[node_id].clone());
+    }
+    result.root_eclasses = egraph.root_eclasses.clone();
+    result.class_data = egraph.class_data.clone();
+    result
+}
+
+/// A preference for some ops on ties, like `Shl=2`, from --tie-break-key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TieBreakKey {
+    /// The whole op, its name before any `(` or `:` width, or a prefix followed by `*`.
+    pub pattern: String,
+    pub priority: i64,
+}
+
+impl std::str::FromStr for TieBreakKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((pattern, priority)) = s.rsplit_once('=') else {
+            return Err(format!(
+                "A tie break key is <op-pattern>=<priority>, not {s}"
+            ));
+        };
+        let priority = priority
+            .trim()
+            .parse()
+            .map_err(|e| format!("Bad priority in tie break key {s}: {e}"))?;
+        if pattern.is_empty() {
+            return Err(format!("Tie break key {s} has no pattern"));
+        }
+        Ok(TieBreakKey {
+            pattern: pattern.to_string(),
+            priority,
+        })
+    }
+}
+
+impl TieBreakKey {
+    pub fn matches(&self, op: &str) -> bool {
+        match self.pattern.strip_suffix('*') {
+            Some(prefix) if !prefix.is_empty() => op.starts_with(prefix),
+            _ => op == self.pattern || op.split(['(', ':']).next() == Some(&self.pattern),
+        }
+    }
+}
+
+/// Reorders the nodes of each class so the ones matching higher priority keys come first,
+/// and extractors that keep the first of equally cheap nodes prefer them. A class's nodes
+/// swap places only among themselves, so nodes of other classes aren't moved ahead of or
+/// behind them. A node matching several keys takes the highest of their priorities, and one
+/// matching none has priority 0. Nodes with the same priority keep their order, so the
+/// --tie-break still decides between them.
+pub fn sort_by_tie_break_keys(egraph: &EGraph, keys: &[TieBreakKey]) -> EGraph {
+    let priority = |node_id: &NodeId| tie_break_priority(keys, &egraph[node_id]);
+    let mut sorted: FxHashMap<&ClassId, std::vec::IntoIter<&NodeId>> = egraph
+        .classes()
+        .iter()
+        .map(|(class_id, class)| {
+            let mut nodes: Vec<&NodeId> = class.nodes.iter().collect();
+            nodes.sort_by_key(|node_id| std::cmp::Reverse(priority(node_id)));
+            (class_id, nodes.into_iter())
+        })
+        .collect();
+    let mut result = EGraph::default();
+    for node in egraph.nodes.values() {
+        let node_id = sorted.get_mut(&node.eclass).unwrap().next().unwrap();
+        result.add_node(node_id.clone(), egraph[node_id].clone());
+    }
+    result.root_eclasses = egraph.root_eclasses.clone();
+    result.class_data = egraph.class_data.clone();
+    result
+}
+
+/// The highest priority of the keys a node's op matches, or 0 if it matches none.
+pub fn tie_break_priority(keys: &[TieBreakKey], node: &Node) -> i64 {
+    keys.iter()
+        .filter(|key| key.matches(&node.op))
+        .map(|key| key.priority)
+        .max()
+        .unwrap_or(0)
+}
+
+/// The roots which have no extraction, because every choice in them leads to a cycle.
+pub fn unextractable_roots(egraph: &EGraph, roots: &[ClassId]) -> Vec<ClassId> {
+    let result = extract::bottom_up::BottomUpExtractor.extract(egraph, roots);
+    roots
+        .iter()
+        .filter(|root| !result.choices.contains_key(*root))
+        .cloned()
+        .collect()
+}
+
+/// Keeps only the classes at most `max_distance` hops from a root, through the children of
+/// any of their nodes. Nodes with a child further away are removed, so the classes at the
+/// edge can only be extracted with nodes that stay inside.
+pub fn within_distance(egraph: &EGraph, max_distance: usize) -> EGraph {
+    let mut reached: FxHashSet<&ClassId> = FxHashSet::default();
+    let mut frontier: Vec<&ClassId> = egraph.root_eclasses.iter().collect();
+    for _ in 0..=max_distance {
+        let mut next = vec![];
+        for class_id in frontier {
+            if !reached.insert(class_id) {
+                continue;
+            }
+            for node_id in &egraph[class_id].nodes {
+                next.extend(
+                    egraph[node_id]
+                        .children
+                        .iter()
+                        .map(|c| egraph.nid_to_cid(c)),
+                );
+            }
+        }
+        frontier = next;
+    }
+    retain_nodes(egraph, |_, node| reached.contains(&node.eclass))
+}
+
+/// Removes the `percent`% most expensive nodes of the egraph (rounding down), as if the
+/// rewrites that made them weren't available. Ties go to the nodes that come first.
+pub fn exclude_top_cost(egraph: &EGraph, percent: f64) -> EGraph {
+    let count = (egraph.nodes.len() as f64 * percent / 100.0) as usize;
+    let mut by_cost: Vec<(&NodeId, &Node)> = egraph.nodes.iter().collect();
+    by_cost.sort_by_key(|(_, node)| std::cmp::Reverse(node.cost));
+    let excluded: FxHashSet<&NodeId> = by_cost[..count].iter().map(|(id, _)| *id).collect();
+    retain_nodes(egraph, |id, _| !excluded.contains(id))
+}
+
+/// Removes the nodes whose op isn't allowed, like the cells a technology library doesn't
+/// have. Ops are matched by their name, before any `(` or `:` width, and leaves are kept
+/// whatever their op, as they're the inputs and constants.
+pub fn allow_ops(egraph: &EGraph, allowed: &[String]) -> EGraph {
+    retain_nodes(egraph, |_, node| {
+        let name = node.op.split(['(', ':']).next().unwrap();
+        node.children.is_empty() || allowed.iter().any(|op| op == name)
+    })
+}
+
+/// Removes each node that a sibling dominates, as `extract::dominance` describes, which
+/// keeps the cost of the cheapest dag extraction.
+///
+/// The tree cost counts a class once per child, so it can go up.
+pub fn prune_dominated(egraph: &EGraph) -> EGraph {
+    let dominated = extract::dominance::dominated_nodes(egraph);
+    retain_nodes(egraph, |id, _| !dominated.contains(id))
+}
+
+/// The number of classes with an acyclic extraction.
+pub fn extractable_classes(egraph: &EGraph) -> usize {
+    extract::bottom_up::BottomUpExtractor
+        .extract(egraph, &egraph.root_eclasses)
+        .choices
+        .len()
+}
+
+/// The classes that have an acyclic extraction in `before` but not in `after`, which has
+/// some of its nodes removed.
+pub fn newly_unextractable(before: &EGraph, after: &EGraph) -> Vec<ClassId> {
+    let extractor = extract::bottom_up::BottomUpExtractor;
+    let still = extractor.extract(after, &after.root_eclasses);
+    extractor
+        .extract(before, &before.root_eclasses)
+        .choices
+        .into_keys()
+        .filter(|class_id| !still.choices.contains_key(class_id))
+        .collect()
+}
+
+/// The node field holding the rewrite iteration that introduced a node.
+pub const ITERATION_FIELD: &str = "iteration";
+
+/// Reads a numeric field of each node from the egraph's JSON file. The egraph itself doesn't
+/// keep fields it doesn't know about, and nodes without the field are left out.
+pub fn read_node_field(filename: &str, field: &str) -> anyhow::Result<FxHashMap<NodeId, f64>> {
+    Ok(read_node_fields(filename, &[field])?.pop().unwrap())
+}
+
+/// Like `read_node_field` for each of `fields`, in the same order, but reading the file
+/// only once.
+pub fn read_node_fields(
+    filename: &str,
+    fields: &[&str],
+) -> anyhow::Result<Vec<FxHashMap<NodeId, f64>>> {
+    let nodes = read_nodes(filename)?;
+    Ok(fields
+        .iter()
+        .map(|&field| {
+            nodes
+                .iter()
+                .filter_map(|(id, node)| {
+                    Some((NodeId::from(id.as_str()), node.get(field)?.as_f64()?))
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Like `read_node_field`, but keeps the field's value whatever it is, e.g. a source
+/// location like "foo.rs:42" or {"file": "foo.rs", "line": 42}.
+pub fn read_node_values(
+    filename: &str,
+    field: &str,
+) -> anyhow::Result<FxHashMap<NodeId, serde_json::Value>> {
+    Ok(read_nodes(filename)?
+        .into_iter()
+        .filter_map(|(id, mut node)| Some((NodeId::from(id.as_str()), node.get_mut(field)?.take())))
+        .collect())
+}
+
+fn read_nodes(filename: &str) -> anyhow::Result<serde_json::Map<String, serde_json::Value>> {
+    let mut json: serde_json::Value =
+        serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(filename)?))?;
+    match json.get_mut("nodes").map(serde_json::Value::take) {
+        Some(serde_json::Value::Object(nodes)) => Ok(nodes),
+        _ => anyhow::bail!("{filename} has no nodes"),
+    }
+}
+
+/// Makes the nodes that have a literal value in `values` constants: they cost nothing and
+/// their op becomes `Num(value)`, keeping a `:N` width, which is how every output format
+/// writes constants. A string value is used as it is, any other as its JSON.
+///
+/// Fails if one of them has children, as a constant can't depend on anything.
+pub fn mark_constants(
+    egraph: &EGraph,
+    values: &FxHashMap<NodeId, serde_json::Value>,
+) -> anyhow::Result<EGraph> {
+    let mut result = EGraph::default();
+    for (node_id, node) in &egraph.nodes {
+        let Some(value) = values.get(node_id) else {
+            result.add_node(node_id.clone(), node.clone());
+            continue;
+        };
+        if !node.children.is_empty() {
+            anyhow::bail!(
+                "Node {node_id} ({}) has a literal value and children",
+                node.op
+            );
+        }
+        let literal = match value {
+            serde_json::Value::String(s) => s.clone(),
+            value => value.to_string(),
+        };
+        let op = match output::split_width(&node.op) {
+            (_, Some(width)) => format!("Num({literal}):{width}"),
+            (_, None) => format!("Num({literal})"),
+        };
+        result.add_node(
+            node_id.clone(),
+            Node {
+                op,
+                cost: Cost::default(),
+                ..node.clone()
+            },
+        );
+    }
+    result.root_eclasses = egraph.root_eclasses.clone();
+    result.class_data = egraph.class_data.clone();
+    Ok(result)
+}
+
+/// Adds `weight * iteration` to the cost of each node, so a positive weight steers the
+/// extraction away from the nodes later rewrites introduced and a negative one towards them.
+/// Costs don't go below zero.
+pub fn bias_by_iteration(
+    egraph: &EGraph,
+    iterations: &FxHashMap<NodeId, f64>,
+    weight: f64,
+) -> EGraph {
+    set_costs(egraph, |node_id, node| {
+        let iteration = iterations.get(node_id).copied().unwrap_or(0.0);
+        Ok(Cost::new(
+            (node.cost.into_inner() + weight * iteration).max(0.0),
+        )?)
+    })
+    .unwrap()
+}
+
+/// Adds `lambda / count` to the cost of each node, where `count` is how many nodes in the
+/// egraph have its op, so the extraction leans towards the ops that are common.
+pub fn penalize_rare_ops(egraph: &EGraph, lambda: f64) -> EGraph {
+    let mut counts: FxHashMap<&str, usize> = FxHashMap::default();
+    for node in egraph.nodes.values() {
+        *counts.entry(node.op.as_str()).or_default() += 1;
+    }
+    set_costs(egraph, |_, node| {
+        Ok(node.cost + lambda / counts[node.op.as_str()] as f64)
+    })
+    .unwrap()
+}
+
+/// Multiplies the cost of each node by the largest weight of the roots that can reach it,
+/// so the extraction pays more for what the heavier roots use. Roots without a weight, and
+/// nodes no root reaches, have a weight of 1.
+///
+/// Fails if a weighted class isn't a root or a weight is negative.
+pub fn weight_by_roots(
+    egraph: &EGraph,
+    weights: &IndexMap<ClassId, f64>,
+) -> anyhow::Result<EGraph> {
+    for (class_id, &weight) in weights {
+        if !egraph.root_eclasses.contains(class_id) {
+            anyhow::bail!("Class {class_id} has a root weight but isn't a root");
+        }
+        if weight.is_nan() || weight < 0.0 {
+            anyhow::bail!("The weight of root {class_id} is {weight}, it can't be negative");
+        }
+    }
+
+    // Reaching classes from the heaviest root first, the first weight a class gets is the
+    // largest.
+    let mut roots: Vec<(&ClassId, f64)> = egraph
+        .root_eclasses
+        .iter()
+        .map(|root| (root, weights.get(root).copied().unwrap_or(1.0)))
+        .collect();
+    roots.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut class_weights: FxHashMap<&ClassId, f64> = FxHashMap::default();
+    for (root, weight) in roots {
+        let mut todo = vec![root];
+        while let Some(class_id) = todo.pop() {
+            if class_weights.contains_key(class_id) {
+                continue;
+            }
+            class_weights.insert(class_id, weight);
+            for node_id in &egraph[class_id].nodes {
+                todo.extend(
+                    egraph[node_id]
+                        .children
+                        .iter()
+                        .map(|c| egraph.nid_to_cid(c)),
+                );
+            }
+        }
+    }
+
+    set_costs(egraph, |_, node| {
+        let weight = class_weights.get(&node.eclass).copied().unwrap_or(1.0);
+        Ok(node.cost * weight)
+    })
+}
+
+/// Sets the cost of each node to the value of `objective` for it.
+///
+/// Fails if that is negative or not a number for any node.
+pub fn weight_by_objective(egraph: &EGraph, objective: &Objective) -> anyhow::Result<EGraph> {
+    let metrics = objective::node_metrics(egraph);
+    set_costs(egraph, |node_id, _| {
+        let cost = objective.eval(&metrics[node_id]);
+        if cost.is_nan() || cost < 0.0 {
+            anyhow::bail!("The objective is {cost} for node {node_id}, it can't be negative");
+        }
+        Ok(Cost::new(cost)?)
+    })
+}
+
+/// Reads a sidecar file of costs, a JSON object from node id to cost.
+pub fn read_node_costs(filename: &str) -> anyhow::Result<FxHashMap<NodeId, Cost>> {
+    let json: serde_json::Value =
+        serde_json::from_reader(std::io::BufReader::new(std::fs::File::open(filename)?))?;
+    let Some(costs) = json.as_object() else {
+        anyhow::bail!("{filename} should be an object from node id to cost");
+    };
+    costs
+        .iter()
+        .map(|(node_id, cost)| match cost.as_f64() {
+            Some(cost) if cost >= 0.0 => Ok((NodeId::from(node_id.as_str()), Cost::new(cost)?)),
+            _ => anyhow::bail!(
+                "The cost of node {node_id} should be a non-negative number, not {cost}"
+            ),
+        })
+        .collect()
+}
+
+/// Sets the cost of each node in `costs`. Other nodes keep their cost, which they have to
+/// have had in the egraph's file: `embedded` are the nodes that did.
+///
+/// Fails if `costs` has a node that isn't in the egraph, or a node is in neither.
+pub fn cost_by_node(
+    egraph: &EGraph,
+    costs: &FxHashMap<NodeId, Cost>,
+    embedded: &FxHashSet<NodeId>,
+) -> anyhow::Result<EGraph> {
+    if let Some(node_id) = costs.keys().find(|id| !egraph.nodes.contains_key(*id)) {
+        anyhow::bail!("There's a cost for node {node_id}, which isn't in the egraph");
+    }
+    set_costs(egraph, |node_id, node| match costs.get(node_id) {
+        Some(&cost) => Ok(cost),
+        None if embedded.contains(node_id) => Ok(node.cost),
+        None => anyhow::bail!("Node {node_id} has no cost in the egraph or the costs file"),
+    })
+}
+
+/// Sets the cost of each node whose op matches a key of the table to the table's value for
+/// it, e.g. so the cost of a variadic op can depend on its number of children. Other nodes
+/// keep their cost. See `table_entry` for how keys match ops.
+///
+/// Fails if a cost is negative or not a number.
+pub fn cost_by_table(
+    egraph: &EGraph,
+    table: &IndexMap<String, Objective>,
+) -> anyhow::Result<EGraph> {
+    let metrics = objective::node_metrics(egraph);
+    set_costs(egraph, |node_id, node| {
+        table_value(table, &metrics, "cost", node_id, node).unwrap_or(Ok(node.cost))
+    })
+}
+
+/// Sets the cost of each node to the one the cost script gives it.
+#[cfg(feature = "scripting")]
+pub fn cost_by_script(
+    egraph: &EGraph,
+    script: &crate::script::CostScript,
+) -> anyhow::Result<EGraph> {
+    set_costs(egraph, |node_id, _| script.cost(egraph, node_id))
+}
+
+/// The delay of each node from a table like `cost_by_table`'s, for
+/// `ExtractionResult::critical_path_with`. Ops not in the table take 1, so without any the
+/// critical path is the number of levels.
+pub fn delays_by_table(
+    egraph: &EGraph,
+    table: &IndexMap<String, Objective>,
+) -> anyhow::Result<IndexMap<NodeId, Cost>> {
+    let metrics = objective::node_metrics(egraph);
+    egraph
+        .nodes
+        .iter()
+        .map(|(node_id, node)| {
+            let delay = table_value(table, &metrics, "delay", node_id, node)
+                .unwrap_or(Ok(Cost::new(1.0).unwrap()))?;
+            Ok((node_id.clone(), delay))
+        })
+        .collect()
+}
+
+/// The entry of a cost or delay table for an op. A key is either the whole op, like
+/// `Mul(i32)`, a prefix of it ending in `*`, like `Mul*`, or `*` for any op. The most
+/// specific key wins: the whole op, then the longest matching prefix, then `*`.
+pub fn table_entry<'a, V>(table: &'a IndexMap<String, V>, op: &str) -> Option<&'a V> {
+    if let Some(value) = table.get(op) {
+        return Some(value);
+    }
+    table
+        .iter()
+        .filter_map(|(key, value)| Some((key.strip_suffix('*')?, value)))
+        .filter(|(prefix, _)| op.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| value)
+}
+
+/// The table's value for a node, if a key of the table matches its op. Fails if it's
+/// negative.
+fn table_value(
+    table: &IndexMap<String, Objective>,
+    metrics: &FxHashMap<NodeId, objective::NodeMetrics>,
+    what: &str,
+    node_id: &NodeId,
+    node: &Node,
+) -> Option<anyhow::Result<Cost>> {
+    let objective = table_entry(table, &node.op)?;
+    let value = objective.eval(&metrics[node_id]);
+    if value.is_nan() || value < 0.0 {
+        return Some(Err(anyhow::anyhow!(
+            "The {what} of {} is {value} for node {node_id}, it can't be negative",
+            node.op
+        )));
+    }
+    Some(Cost::new(value).map_err(Into::into))
+}
+
+fn set_costs(
+    egraph: &EGraph,
+    cost: impl Fn(&NodeId, &Node) -> anyhow::Result<Cost>,
+) -> anyhow::Result<EGraph> {
+    let mut result = EGraph::default();
+    for (node_id, node) in &egraph.nodes {
+        result.add_node(
+            node_id.clone(),
+            Node {
+                cost: cost(node_id, node)?,
+                ..node.clone()
+            },
+        );
+    }
+    result.root_eclasses = egraph.root_eclasses.clone();
+    result.class_data = egraph.class_data.clone();
+    Ok(result)
+}
+
+/// Sorts the children of nodes whose op is in `commutative`, then merges classes that end up
+/// with the same node, so `a + b` and `b + a` are shared in the extraction.
+///
+/// This changes node identity: merged classes take the id of one of them, and duplicate
+/// nodes are dropped. Only list ops that really are commutative, otherwise classes with
+/// different values get merged.
+pub fn canonicalize_commutative(egraph: &EGraph, commutative: &[String]) -> EGraph {
+    let commutative: FxHashSet<&str> = commutative.iter().map(|op| op.as_str()).collect();
+
+    let mut parent: FxHashMap<ClassId, ClassId> = FxHashMap::default();
+    fn find(parent: &FxHashMap<ClassId, ClassId>, class: &ClassId) -> ClassId {
+        let mut class = class;
+        while let Some(p) = parent.get(class) {
+            class = p;
+        }
+        class.clone()
+    }
+
+    let key = |parent: &FxHashMap<ClassId, ClassId>, node_id: &NodeId| {
+        let node = &egraph[node_id];
+        let mut children: Vec<ClassId> = node
+            .children
+            .iter()
+            .map(|c| find(parent, egraph.nid_to_cid(c)))
+            .collect();
+        if commutative.contains(node.op.as_str()) {
+            children.sort();
+        }
+        (node.op.as_str(), children)
+    };
+
+    // Merging two classes can make their parents equal too, so repeat until nothing changes.
+    loop {
+        let mut merged = false;
+        let mut seen: FxHashMap<(&str, Vec<ClassId>), ClassId> = FxHashMap::default();
+        for node_id in egraph.nodes.keys() {
+            if !commutative.contains(egraph[node_id].op.as_str()) {
+                continue;
+            }
+            let class = find(&parent, egraph.nid_to_cid(node_id));
+            let other = seen
+                .entry(key(&parent, node_id))
+                .or_insert_with(|| class.clone());
+            if *other != class {
+                parent.insert(class, other.clone());
+                merged = true;
+            }
+        }
+        if !merged {
+            break;
+        }
+    }
+
+    let mut result = EGraph::default();
+    for (node_id, node) in &egraph.nodes {
+        let mut children = node.children.clone();
+        if commutative.contains(node.op.as_str()) {
+            children.sort_by_key(|c| find(&parent, egraph.nid_to_cid(c)));
+        }
+        result.add_node(
+            node_id.clone(),
+            Node {
+                children,
+                eclass: find(&parent, &node.eclass),
+                ..node.clone()
+            },
+        );
+    }
+    let mut roots: IndexSet<ClassId> = IndexSet::default();
+    for root in &egraph.root_eclasses {
+        roots.insert(find(&parent, root));
+    }
+    result.root_eclasses = roots.into_iter().collect();
+    for (class, data) in &egraph.class_data {
+        result
+            .class_data
+            .entry(find(&parent, class))
+            .or_insert_with(|| data.clone());
+    }
+
+    // Drop the nodes that are now duplicated within their class.
+    let mut seen: FxHashSet<(&str, Vec<ClassId>, ClassId)> = FxHashSet::default();
+    retain_nodes(&result, |node_id, node| {
+        let (op, children) = key(&parent, node_id);
+        seen.insert((op, children, node.eclass.clone()))
+    })
+}